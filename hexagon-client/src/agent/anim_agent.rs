@@ -40,6 +40,8 @@ pub enum AgentInput {
     BackWard,
     Pause,
     Reset,
+    /// Jumps to a progress between 0 and 1 without animating.
+    Seek(f32),
     // GetProgress,
 }
 
@@ -49,6 +51,13 @@ pub enum AgentOutput {
     Progress(f32),
 }
 
+impl Compo {
+    /// Progress made in one frame, durations below a second included.
+    fn step(&self, fps: u64) -> f32 {
+        1.0 / (fps as f32 * self.duration.as_secs_f32()).max(1.0)
+    }
+}
+
 impl Agent for AnimAgent {
     type Reach = Context<Self>;
 
@@ -80,8 +89,7 @@ impl Agent for AnimAgent {
                     match comp.state {
                         AnimState::Forwarding => {
                             if comp.progress < 1.0 {
-                                comp.progress +=
-                                    1.0 / ((self.fps * comp.duration.as_secs()) as f32);
+                                comp.progress += comp.step(self.fps);
                                 comp.progress = comp.progress.clampi(0.0, 1.0);
                                 self.link
                                     .respond(comp.handle, AgentOutput::Progress(comp.progress));
@@ -93,8 +101,7 @@ impl Agent for AnimAgent {
                         }
                         AnimState::BackWarding => {
                             if comp.progress > 0.0 {
-                                comp.progress -=
-                                    1.0 / ((self.fps * comp.duration.as_secs()) as f32);
+                                comp.progress -= comp.step(self.fps);
                                 comp.progress = comp.progress.clampi(0.0, 1.0);
                                 self.link
                                     .respond(comp.handle, AgentOutput::Progress(comp.progress));
@@ -154,6 +161,11 @@ impl Agent for AnimAgent {
                     self.handlers[pos].progress = 0.0;
                 }
             }
+            AgentInput::Seek(progress) => {
+                if let Some(pos) = self.handlers.iter().position(|f| f.handle == id) {
+                    self.handlers[pos].progress = progress.clampi(0.0, 1.0);
+                }
+            }
         }
     }
 }
@@ -1,4 +1,6 @@
-use hexagon_shared::structures::{PlayerMessage, SocketMessage};
+use std::time::Duration;
+
+use hexagon_shared::structures::{CloseCodes, Credentials, PlayerMessage, SocketMessage};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::MessageEvent;
@@ -7,6 +9,7 @@ use yew::agent::Agent;
 use yew::agent::Context;
 use yew::agent::HandlerId;
 use yew::prelude::*;
+use yew::services::timeout::{TimeoutService, TimeoutTask};
 use yew::worker::AgentLink;
 
 use serde::{Deserialize, Serialize};
@@ -23,13 +26,30 @@ pub enum AgentOutput {
     SocketConnected,
     SocketDisconnected(Option<(u16, String)>),
     SocketErrorConnecting,
+    /// Connection dropped while in a lobby, this is the attempt to get back.
+    SocketReconnecting(u32),
 }
 
+/// Delay before the first reconnect, doubled after every failed attempt.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(16);
+const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+
 pub struct SocketAgent {
     link: AgentLink<Self>,
     subscribers: Vec<HandlerId>,
     socket: Option<WebSocket>,
     updatecallback: Callback<(WebSocket, String)>,
+
+    url: Option<String>,
+    /// Name sent with the last `Initialize`.
+    name: Option<String>,
+    /// Session token the server gave this connection.
+    token: Option<String>,
+    /// Lobby to rejoin when the connection drops.
+    lobby: Option<String>,
+    reconnect_attempts: u32,
+    reconnect_task: Option<TimeoutTask>,
 }
 
 pub enum Msg {
@@ -41,6 +61,7 @@ pub enum Msg {
     // PeerConnect(u32),
     // PeerDisconnect(u32),
     SendSocketMessage(PlayerMessage),
+    Reconnect,
     Ignore,
 }
 
@@ -57,6 +78,12 @@ impl Agent for SocketAgent {
             link,
             socket: None,
             subscribers: vec![],
+            url: None,
+            name: None,
+            token: None,
+            lobby: None,
+            reconnect_attempts: 0,
+            reconnect_task: None,
         }
     }
 
@@ -95,12 +122,38 @@ impl Agent for SocketAgent {
 
                 onmessage_callback.forget();
                 self.socket = Some(socket.0);
+                if self.reconnect_attempts > 0 {
+                    if let (Some(name), Some(token), Some(lobby)) =
+                        (&self.name, &self.token, &self.lobby)
+                    {
+                        log::info!("Reconnected, rejoining lobby {}", lobby);
+                        self.send_socket_message(&PlayerMessage::Initialize(
+                            Credentials::Token(token.clone()),
+                            name.clone(),
+                        ));
+                        self.send_socket_message(&PlayerMessage::Rejoin(lobby.clone()));
+                        return;
+                    }
+                }
                 for subs in self.subscribers.iter() {
                     self.link
                         .respond(subs.clone(), AgentOutput::SocketConnected)
                 }
             }
             Msg::SocketMessage(msg) => {
+                if let SocketMessage::LobbyJoined(lobby, _) | SocketMessage::Spectating(lobby) =
+                    &msg
+                {
+                    self.lobby = Some(lobby.id.clone());
+                    self.reconnect_attempts = 0;
+                }
+                match &msg {
+                    SocketMessage::Identified(identity) => {
+                        self.token = Some(identity.token.clone());
+                    }
+                    SocketMessage::LeftLobby => self.lobby = None,
+                    _ => {}
+                }
                 // log::debug!("socket message {:#?}", msg);
                 // self.handle_socket_msg(&msg);
                 self.broadcast(AgentOutput::SocketMessage(msg));
@@ -108,7 +161,29 @@ impl Agent for SocketAgent {
             Msg::Disconnected(code) => {
                 log::warn!("Disconnected from socket");
                 self.socket = None;
-                self.broadcast(AgentOutput::SocketDisconnected(code));
+                if self.lobby.is_some()
+                    && should_reconnect(&code)
+                    && self.reconnect_attempts < MAX_RECONNECT_ATTEMPTS
+                {
+                    let delay = (RECONNECT_DELAY * 2u32.pow(self.reconnect_attempts))
+                        .min(MAX_RECONNECT_DELAY);
+                    self.reconnect_attempts += 1;
+                    self.reconnect_task = Some(TimeoutService::spawn(
+                        delay,
+                        self.link.callback(|_| Msg::Reconnect),
+                    ));
+                    self.broadcast(AgentOutput::SocketReconnecting(self.reconnect_attempts));
+                } else {
+                    self.lobby = None;
+                    self.reconnect_attempts = 0;
+                    self.broadcast(AgentOutput::SocketDisconnected(code));
+                }
+            }
+            Msg::Reconnect => {
+                self.reconnect_task = None;
+                if let Some(url) = self.url.clone() {
+                    self.connect_to_socket(url);
+                }
             }
 
             Msg::SendSocketMessage(data) => {
@@ -125,9 +200,23 @@ impl Agent for SocketAgent {
     fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
         match msg {
             AgentInput::Connect(url) => {
+                let open = self
+                    .socket
+                    .as_ref()
+                    .is_some_and(|socket| socket.ready_state() == WebSocket::OPEN);
+                if open && self.lobby.is_none() && self.url.as_ref() == Some(&url) {
+                    // Still open after leaving a lobby, the server takes a
+                    // new `Initialize` and lobby on it.
+                    self.link.respond(_id, AgentOutput::SocketConnected);
+                    return;
+                }
+                self.url = Some(url.clone());
                 self.connect_to_socket(url);
             }
             AgentInput::Send(msg) => {
+                if let PlayerMessage::Initialize(_, name) = &msg {
+                    self.name = Some(name.clone());
+                }
                 self.send_socket_message(&msg);
             }
         }
@@ -205,3 +294,14 @@ impl SocketAgent {
         }
     }
 }
+
+/// Connections closed by the server on purpose, with one of the
+/// `CloseCodes`, are not retried. Timed out ones are, the tab may just have
+/// been in the background.
+fn should_reconnect(code: &Option<(u16, String)>) -> bool {
+    match code {
+        Some((code, _)) if *code == CloseCodes::TimedOut.to_code() => true,
+        Some((code, _)) => *code < 4000 && *code != 1003 && *code != 1013,
+        None => true,
+    }
+}
@@ -1,7 +1,9 @@
 use hexagon_shared::{
     board::Board,
     colors::colors::Color,
-    structures::{Lobby, Player, PlayerMessage, PlayerStatus, SocketMessage, State},
+    structures::{
+        CloseCodes, Lobby, Player, PlayerMessage, PlayerStatus, ProtocolError, SocketMessage, State,
+    },
 };
 use yew::prelude::*;
 use yew_router::prelude::*;
@@ -9,16 +11,18 @@ use yew_router::prelude::*;
 use crate::agent::notification_agent::*;
 use crate::agent::socket_agent::*;
 
+use crate::components::analysis::Analysis;
 use crate::components::backdrop::HoneyCombBackdrop;
 use crate::components::game::Game;
 use crate::components::home::Home;
 use crate::components::notification_widget::NotificationWidget;
+use crate::components::replay::Replay;
 use crate::components::room::Room;
 
 pub struct App {
     _agent: Box<dyn yew::Bridge<SocketAgent>>,
     notif_agent: Box<dyn yew::Bridge<NotificationAgent>>,
-    lobby: Option<(Lobby, Color)>,
+    lobby: Option<(Lobby, Option<Color>)>,
     selfid: String,
     link: ComponentLink<Self>,
     ping_interval: yew::services::interval::IntervalTask,
@@ -27,16 +31,25 @@ pub struct App {
 pub enum Msg {
     Ignore,
     Ping,
-    LobbyJoined(String, Lobby, Color),
+    LobbyJoined(String, Lobby, Option<Color>),
     GameStart(Lobby),
 
     Disconnected(Option<(u16, String)>),
+    Reconnecting(u32),
+    Rejoined(Lobby, Option<Color>),
+    PlayerAway(Player),
     PlayerDisconnected(Player),
     PlayerJoined(Player),
+    LeftLobby,
+    ProtocolError(ProtocolError),
 }
 
 #[derive(Switch, Debug, Clone)]
 pub enum AppRoute {
+    #[to = "/analysis"]
+    Analysis,
+    #[to = "/replay/{id}"]
+    Replay(String),
     #[to = "/{roomid}"]
     Room(String),
     #[to = "/"]
@@ -58,9 +71,15 @@ impl Component for App {
             AgentOutput::SocketMessage(msg) => match msg {
                 SocketMessage::PlayerJoined(p, _) => Msg::PlayerJoined(p),
                 SocketMessage::PlayerDisconnected(p) => Msg::PlayerDisconnected(p),
+                SocketMessage::PlayerAway(p) => Msg::PlayerAway(p),
+                SocketMessage::LobbyJoined(lobby, color) => Msg::Rejoined(lobby, Some(color)),
+                SocketMessage::Spectating(lobby) => Msg::Rejoined(lobby, None),
+                SocketMessage::LeftLobby => Msg::LeftLobby,
+                SocketMessage::ProtocolError(err) => Msg::ProtocolError(err),
                 _ => Msg::Ignore,
             },
             AgentOutput::SocketDisconnected(reason) => Msg::Disconnected(reason),
+            AgentOutput::SocketReconnecting(attempt) => Msg::Reconnecting(attempt),
             _ => Msg::Ignore,
         }));
         let pinginterval = yew::services::IntervalService::spawn(
@@ -72,7 +91,7 @@ impl Component for App {
             notif_agent,
             lobby: None,
             link: _link,
-            selfid: unsafe { crate::components::home::get_uid() },
+            selfid: String::new(),
             ping_interval: pinginterval,
         }
     }
@@ -98,6 +117,9 @@ impl Component for App {
                 true
             }
 
+            Msg::Disconnected(Some((code, _))) if code == CloseCodes::MatchCancelled.to_code() => {
+                false
+            }
             Msg::Disconnected(reason) => {
                 self.notif_agent
                     .send(NotificationAgentInput::Notify(Notification {
@@ -115,6 +137,45 @@ impl Component for App {
                     }));
                 false
             }
+            Msg::LeftLobby => {
+                self.lobby = None;
+                false
+            }
+            Msg::ProtocolError(err) => {
+                log::warn!("Server refused a message {:#?}", err);
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Warning,
+                        content: format!("Server refused the request: {:?}", err),
+                    }));
+                false
+            }
+            Msg::Reconnecting(attempt) => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Warning,
+                        content: format!("Connection lost, reconnecting (attempt {})", attempt),
+                    }));
+                false
+            }
+            Msg::Rejoined(lob, color) => {
+                // Only rejoins after a reconnect, joining from the home page
+                // goes through `LobbyJoined`.
+                if self.lobby.is_some() {
+                    self.lobby = Some((lob, color));
+                    true
+                } else {
+                    false
+                }
+            }
+            Msg::PlayerAway(p) => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Warning,
+                        content: format!("{} lost their connection", p.name),
+                    }));
+                false
+            }
             Msg::PlayerJoined(p) => {
                 self.notif_agent
                     .send(NotificationAgentInput::Notify(Notification {
@@ -140,7 +201,7 @@ impl Component for App {
 
     fn view(&self) -> Html {
         let home = html! {
-            <Home prefillroomid="".to_string() lobbyjoinedcb=self.link.callback(move |f:(String,Lobby,Color)|Msg::LobbyJoined(f.0,f.1,f.2))/>
+            <Home prefillroomid="".to_string() lobbyjoinedcb=self.link.callback(move |f:(String,Lobby,Option<Color>)|Msg::LobbyJoined(f.0,f.1,f.2))/>
         };
 
         let lobby = self.lobby.clone();
@@ -158,6 +219,8 @@ impl Component for App {
                         let link = linkclone.clone();
                         match switch {
                             AppRoute::Home=>home.clone(),
+                            AppRoute::Analysis=>html!{<Analysis/>},
+                            AppRoute::Replay(id)=>html!{<Replay id=id/>},
                             AppRoute::Room(_roomid)=>{
                                 if let Some((lobby,color))=lobby.clone(){
                                     match &lobby.state{
@@ -174,7 +237,7 @@ impl Component for App {
                                     }
                                 }else{
                                     html!{
-                                        <Home prefillroomid=_roomid lobbyjoinedcb=linkclone.callback(move |f:(String,Lobby,Color)|Msg::LobbyJoined(f.0,f.1,f.2))/>
+                                        <Home prefillroomid=_roomid lobbyjoinedcb=linkclone.callback(move |f:(String,Lobby,Option<Color>)|Msg::LobbyJoined(f.0,f.1,f.2))/>
                                     }
                                 }
                             },
@@ -0,0 +1,242 @@
+use hexagon_shared::{
+    ai::{PieceDifference, Searcher},
+    board::{GameRecord, Notation, Outcome},
+    colors::colors::Color,
+    structures::Move,
+};
+use web_sys::File;
+use yew::prelude::*;
+use yew::services::reader::{FileData, ReaderService, ReaderTask};
+
+use crate::components::avatar::avatar;
+use crate::components::hex_board::HexBoard;
+use crate::components::move_list::move_list;
+
+/// Deeper than the in-game hint, there is no clock to keep up with here.
+const ANALYSIS_NODE_LIMIT: u64 = 20_000;
+
+/// Steps through a game record loaded from a file or pasted in.
+pub struct Analysis {
+    link: ComponentLink<Self>,
+    reader: ReaderService,
+    reading: Option<ReaderTask>,
+    text: String,
+    error: Option<String>,
+    record: Option<GameRecord>,
+    /// Number of turns played on the shown board.
+    shown: usize,
+    best: Option<Move>,
+}
+
+pub enum Msg {
+    Ignore,
+    Text(String),
+    File(Option<File>),
+    FileLoaded(FileData),
+    Load,
+    Unload,
+    Show(usize),
+    BestMove,
+    GoHome,
+}
+
+impl Component for Analysis {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(_props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Self {
+            link,
+            reader: ReaderService::new(),
+            reading: None,
+            text: String::new(),
+            error: None,
+            record: None,
+            shown: 0,
+            best: None,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Ignore => false,
+            Msg::Text(text) => {
+                self.text = text;
+                false
+            }
+            Msg::File(file) => {
+                if let Some(file) = file {
+                    let callback = self.link.callback(Msg::FileLoaded);
+                    match self.reader.read_file(file, callback) {
+                        Ok(task) => self.reading = Some(task),
+                        Err(err) => self.error = Some(err.to_string()),
+                    }
+                }
+                true
+            }
+            Msg::FileLoaded(data) => {
+                self.reading = None;
+                self.text = String::from_utf8_lossy(&data.content).into_owned();
+                self.update(Msg::Load)
+            }
+            Msg::Load => {
+                match self.text.parse::<GameRecord>() {
+                    Ok(record) => {
+                        self.shown = record.history.moves.len();
+                        self.record = Some(record);
+                        self.error = None;
+                    }
+                    Err(err) => self.error = Some(err.to_string()),
+                }
+                self.best = None;
+                true
+            }
+            Msg::Unload => {
+                self.record = None;
+                self.best = None;
+                true
+            }
+            Msg::Show(moves) => {
+                if let Some(record) = &self.record {
+                    self.shown = moves.min(record.history.moves.len());
+                    self.best = None;
+                }
+                true
+            }
+            Msg::BestMove => {
+                if let Some(record) = &self.record {
+                    self.best = Searcher::new(PieceDifference, 4)
+                        .with_node_limit(ANALYSIS_NODE_LIMIT)
+                        .best_move(&record.history.board_at(self.shown));
+                }
+                true
+            }
+            Msg::GoHome => {
+                crate::app::go_to_route(yew_router::route::Route::from(crate::app::AppRoute::Home));
+                false
+            }
+        }
+    }
+
+    fn change(&mut self, _props: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <div class="section">
+                <div class="container">
+                    <h1 class="title has-text-centered">{"Analysis"}</h1>
+                </div>
+                {
+                    match &self.record {
+                        Some(record) => self.view_record(record),
+                        None => self.view_load(),
+                    }
+                }
+            </div>
+        }
+    }
+}
+
+impl Analysis {
+    fn view_load(&self) -> Html {
+        html! {
+            <div class="center-div">
+                <div class="box mt-4" style="min-width:300px;">
+                    <div class="field">
+                        <div class="control">
+                            <textarea class="textarea is-family-monospace" rows="12" placeholder="Paste a game record" value=self.text.clone() oninput=self.link.callback(|msg:InputData|Msg::Text(msg.value))/>
+                        </div>
+                    </div>
+                    <div class="field">
+                        <div class="control">
+                            <input type="file" accept=".txt,text/plain" onchange=self.link.callback(|data|match data {
+                                ChangeData::Files(files) => Msg::File(files.get(0)),
+                                _ => Msg::Ignore,
+                            })/>
+                        </div>
+                    </div>
+                    {
+                        if let Some(error) = &self.error {
+                            html!{<p class="help is-danger mb-2">{error}</p>}
+                        } else {
+                            html!{}
+                        }
+                    }
+                    <div class="buttons">
+                        <button class=if self.reading.is_some() {"button is-loading"} else {"button"} onclick=self.link.callback(|_|Msg::Load)>{"Load"}</button>
+                        <button class="button" onclick=self.link.callback(|_|Msg::GoHome)>{"Home"}</button>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_record(&self, record: &GameRecord) -> Html {
+        let history = &record.history;
+        let board = history.board_at(self.shown);
+        let turn = board.turn;
+        let last = history.moves.len();
+        let shown = self.shown;
+        let name = |color: &Color| {
+            record
+                .names
+                .get(color)
+                .filter(|name| !name.is_empty())
+                .cloned()
+                .unwrap_or_else(|| String::from(*color))
+        };
+        let result = match record.result {
+            Outcome::Win(color) => format!("{} wins", name(&color)),
+            Outcome::TeamWin(team) => format!("Team {} wins", team + 1),
+            Outcome::Draw => "Draw".to_string(),
+            Outcome::InProgress => "Unfinished".to_string(),
+        };
+        html! {
+            <div class="columns mt-4">
+                <div class="column is-three-quarters-widescreen">
+                    <HexBoard is_sim=true key={format!("{}-{:?}",shown,self.best)} color=turn board=board hint=self.best.clone() move_callback=self.link.callback(|_|Msg::Ignore) />
+                    <div class="buttons is-centered my-4">
+                        <button class="button" disabled=shown == 0 onclick=self.link.callback(|_|Msg::Show(0))>{"⏮"}</button>
+                        <button class="button" disabled=shown == 0 onclick=self.link.callback(move |_|Msg::Show(shown.saturating_sub(1)))>{"◀"}</button>
+                        <button class="button" disabled=shown == last onclick=self.link.callback(move |_|Msg::Show(shown + 1))>{"▶"}</button>
+                        <button class="button" disabled=shown == last onclick=self.link.callback(move |_|Msg::Show(last))>{"⏭"}</button>
+                        <button class="button" onclick=self.link.callback(|_|Msg::BestMove)>{"Best move"}</button>
+                    </div>
+                    {
+                        if let Some(best) = &self.best {
+                            html!{
+                                <p class="has-text-centered">{format!("Best move: {}", Notation::new(&history.start).move_name(best))}</p>
+                            }
+                        } else {
+                            html!{}
+                        }
+                    }
+                </div>
+                <div class="column">
+                    <div class="box">
+                        {
+                            for history.start.players.iter().map(|color|html!{
+                                <div class="level is-mobile">
+                                    <div class="level-left">
+                                        <div class="level-item">{avatar(&name(color),color)}</div>
+                                        <div class="level-item">{name(color)}</div>
+                                    </div>
+                                </div>
+                            })
+                        }
+                        <p class="subtitle">{result}</p>
+                    </div>
+                    <div class="box">
+                        {move_list(history, Some(shown), name, &self.link.callback(Msg::Show))}
+                    </div>
+                    <div class="buttons">
+                        <button class="button" onclick=self.link.callback(|_|Msg::Unload)>{"Load another"}</button>
+                        <button class="button" onclick=self.link.callback(|_|Msg::GoHome)>{"Home"}</button>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+}
@@ -2,8 +2,12 @@ use crate::ui::GameColors;
 use crate::{
     agent::anim_agent::AgentInput, agent::anim_agent::AnimAgent, components::hex_board::HexBoard,
 };
-use hexagon_shared::{board::Board, colors::colors::Color, models::OffsetCoord, structures::Move};
-use rand::seq::SliceRandom;
+use hexagon_shared::{
+    ai::{self, Mcts, PieceDifference},
+    board::Board,
+    colors::colors::Color,
+    models::{AxialCoord, OffsetCoord},
+};
 use yew::agent::*;
 use yew::prelude::*;
 
@@ -13,6 +17,7 @@ pub struct HoneyCombBackdrop {
     pub wcell: i32,
     pub hcell: i32,
     pub anim_agent: Box<dyn yew::Bridge<AnimAgent>>,
+    engine: Mcts<PieceDifference>,
 }
 
 pub enum Msg {
@@ -29,13 +34,6 @@ impl HoneyCombBackdrop {
 
     
 
-    fn get_next_color(&self) -> Color {
-        if self.board.turn == Color::BackgroundP1 {
-            Color::BackgroundP2
-        } else {
-            Color::BackgroundP1
-        }
-    }
     fn generate_board() -> (Board, f32, f32) {
         let width: f32 = yew::utils::document().body().expect("No body")
             .client_width() as f32;
@@ -72,6 +70,7 @@ impl Component for HoneyCombBackdrop {
             hcell: hcell as i32,
             wcell: wcell as i32,
             anim_agent,
+            engine: Mcts::new(PieceDifference, 24).with_playout_depth(2),
         }
     }
 
@@ -79,25 +78,10 @@ impl Component for HoneyCombBackdrop {
         match msg {
             Msg::Ignore => false,
             Msg::MakeMove => {
-                let pts = self
-                    .board
-                    .pieces
-                    .iter()
-                    .filter(|(p, c)| **c == self.board.turn)
-                    .collect::<Vec<_>>();
-                let pt = pts.choose(&mut rand::thread_rng());
-                if let Some(pt) = pt {
-                    let moves = self.board.get_legal_moves(pt.0);
-                    let mv = moves.choose(&mut rand::thread_rng());
-                    if let Some(mv) = mv {
-                        self.board.apply_move(&Move {
-                            from: *pt.0,
-                            to: *mv,
-                        });
-                        self.board.change_turn(self.get_next_color());
-                    } else {
-                        self.board = Self::generate_board().0;
-                    }
+                if let Some(mov) = self.engine.best_move(&self.board) {
+                    self.board = ai::play(&self.board, &mov);
+                } else {
+                    self.board = Self::generate_board().0;
                 }
                 self.anim_agent.send(AgentInput::Reset);
                 self.anim_agent.send(AgentInput::Forward);
@@ -113,8 +97,8 @@ impl Component for HoneyCombBackdrop {
     fn view(&self) -> Html {
         let cellwidth = 100.0 / ((self.wcell * 2) - 1) as f32;
         let cellheight: f32 = 100.0 / ((self.hcell * 2) - 1) as f32;
-        let hexs = self.board.points.iter().map(|(k, v)| {
-            let off = OffsetCoord::from(v.clone());
+        let hexs = self.board.points().iter().map(|k| {
+            let off = OffsetCoord::from(AxialCoord::from(*k));
             let shift_left = {
                 if off.row % 2 != 0 {
                     0.5
@@ -126,10 +110,7 @@ impl Component for HoneyCombBackdrop {
             let pt = k.clone();
             // log::debug!("width {}, height {} ", cellwidth, cellheight);
             let mut color = GameColors::NormalCellColor;
-            let mut piece = None;
-            if let Some(val) = self.board.pieces.get(&pt) {
-                piece = Some(*val);
-            }
+            let piece = self.board.piece_at(&pt);
             html! {
                 <>
                 <div class="hexagon"
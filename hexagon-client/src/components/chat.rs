@@ -0,0 +1,120 @@
+use hexagon_shared::structures::{
+    ChatMessage, Lobby, PlayerMessage, SocketMessage, CHAT_HISTORY, MAX_CHAT_LENGTH,
+};
+use yew::prelude::*;
+
+use crate::agent::notification_agent::*;
+use crate::agent::socket_agent::*;
+
+/// Adds `msg` to the chat of `lobby`, dropping the oldest messages the
+/// server doesn't keep either.
+pub fn push_chat(lobby: &mut Lobby, msg: ChatMessage) {
+    lobby.chat.push(msg);
+    if lobby.chat.len() > CHAT_HISTORY {
+        let excess = lobby.chat.len() - CHAT_HISTORY;
+        lobby.chat.drain(..excess);
+    }
+}
+
+/// Chat log of a lobby with a box to write messages, the messages are kept
+/// by the parent.
+pub struct ChatPanel {
+    socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
+    notif_agent: Box<dyn yew::Bridge<NotificationAgent>>,
+    link: ComponentLink<Self>,
+    messages: Vec<ChatMessage>,
+    draft: String,
+}
+
+pub enum Msg {
+    Ignore,
+    Draft(String),
+    Send,
+    Rejected(String),
+}
+
+#[derive(Properties, Clone, Debug)]
+pub struct Props {
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Component for ChatPanel {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let socket_agent = SocketAgent::bridge(link.callback(|data| match data {
+            AgentOutput::SocketMessage(SocketMessage::ChatRejected(err)) => {
+                Msg::Rejected(err.to_string())
+            }
+            _ => Msg::Ignore,
+        }));
+        let notif_agent = NotificationAgent::bridge(link.callback(|_| Msg::Ignore));
+        Self {
+            socket_agent,
+            notif_agent,
+            link,
+            messages: props.messages,
+            draft: String::new(),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Ignore => false,
+            Msg::Draft(text) => {
+                self.draft = text;
+                false
+            }
+            Msg::Send => {
+                if self.draft.trim().is_empty() {
+                    return false;
+                }
+                self.socket_agent
+                    .send(AgentInput::Send(PlayerMessage::Chat(std::mem::take(
+                        &mut self.draft,
+                    ))));
+                true
+            }
+            Msg::Rejected(reason) => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Warning,
+                        content: reason,
+                    }));
+                false
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.messages != props.messages {
+            self.messages = props.messages;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <div class="box chat">
+                <div class="chat-log">
+                {
+                    for self.messages.iter().map(|msg|html!{
+                        <p><strong>{format!("{}: ",msg.name)}</strong>{&msg.text}</p>
+                    })
+                }
+                </div>
+                <form class="field has-addons mt-2" onsubmit=self.link.callback(|e:FocusEvent|{e.prevent_default();Msg::Send})>
+                    <div class="control is-expanded">
+                        <input class="input" type="text" placeholder="Say something" maxlength=MAX_CHAT_LENGTH.to_string() value=self.draft.clone() oninput=self.link.callback(|msg:InputData|Msg::Draft(msg.value))/>
+                    </div>
+                    <div class="control">
+                        <button class="button" type="submit">{"Send"}</button>
+                    </div>
+                </form>
+            </div>
+        }
+    }
+}
@@ -3,23 +3,47 @@ use std::collections::hash_map::Entry;
 use std::{collections::HashMap, ops::Index};
 
 use hexagon_shared::{
-    board::Board,
+    ai::{PieceDifference, Searcher},
+    board::{Board, GameHistory, GameRecord, MoveRecord, Outcome},
     colors::colors::Color,
-    structures::{Lobby, Move, Player, PlayerMessage, PlayerStatus, SocketMessage, State},
+    structures::{
+        ChatMessage, GameSettings, Lobby, Move, Player, PlayerClock, PlayerMessage, PlayerStatus, RatingChange, SocketMessage, State,
+    },
 };
 use yew::prelude::*;
 
+use crate::components::avatar::avatar;
+use crate::components::chat::{push_chat, ChatPanel};
 use crate::components::hex_board::HexBoard;
 use crate::components::home::Home;
+use crate::components::move_list::move_list;
 use crate::components::peer::PeerWidget;
 
 use crate::agent::notification_agent::*;
 use crate::agent::socket_agent::*;
 
+/// Keeps the hint search short enough not to freeze the page.
+const HINT_NODE_LIMIT: u64 = 5_000;
+
 pub struct Game {
     _socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
     notif_agent: Box<dyn yew::Bridge<NotificationAgent>>,
     last_move: Option<Move>,
+    hint: Option<Move>,
+    result: Option<(Outcome, Vec<(Color, u32)>)>,
+    /// Id the server keeps the finished game under.
+    replay: Option<String>,
+    clocks: HashMap<Color, PlayerClock>,
+    /// Number of moves of the position shown instead of the live board.
+    preview: Option<usize>,
+    /// Player asking to take back their move.
+    undo_request: Option<Color>,
+    /// Player offering a draw.
+    draw_offer: Option<Color>,
+    /// Players who want to play again.
+    rematch: Vec<Color>,
+    /// What the finished game did to the ratings of the players.
+    rating_changes: Vec<RatingChange>,
     lobby: Lobby,
     selfid: String,
     link: ComponentLink<Self>,
@@ -27,12 +51,38 @@ pub struct Game {
 
 pub enum Msg {
     Ignore,
+    Refresh(Lobby),
     PlayerJoin(Player),
     PlayerDisconnect(Player),
+    SpectatorJoin(Player),
+    SpectatorLeave(Player),
+    Chat(ChatMessage),
+    Recorded(MoveRecord),
+    Preview(Option<usize>),
     LeaderChange(State),
 
     PlayerMove(Move),
+    Hint,
     BoardUpdate(Board, Option<Move>),
+    TurnSkipped(Color, Board),
+    Forfeited(Color, Board),
+    Clocks(Vec<PlayerClock>),
+    GameOver(Outcome, Vec<(Color, u32)>, String),
+    RatingsChanged(Vec<RatingChange>),
+    RequestUndo,
+    AnswerUndo(bool),
+    UndoRequested(Color),
+    UndoDeclined(Color),
+    Undone(Board, usize),
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DrawOffered(Color),
+    Rematch,
+    RematchRequested(Color),
+    Restart(State, GameSettings),
+    Replay,
+    GoHome,
 }
 
 #[derive(Properties, Clone, Debug)]
@@ -50,8 +100,29 @@ impl Component for Game {
             AgentOutput::SocketMessage(msg) => match msg {
                 SocketMessage::PlayerJoined(p, _) => Msg::PlayerJoin(p),
                 SocketMessage::PlayerDisconnected(p) => Msg::PlayerDisconnect(p),
+                SocketMessage::PlayerAway(p) => Msg::PlayerJoin(p),
+                SocketMessage::LobbyJoined(lobby, _) | SocketMessage::Spectating(lobby) => {
+                    Msg::Refresh(lobby)
+                }
+                SocketMessage::SpectatorJoined(p) => Msg::SpectatorJoin(p),
+                SocketMessage::ChatMessage(msg) => Msg::Chat(msg),
+                SocketMessage::TurnRecorded(record) => Msg::Recorded(record),
+                SocketMessage::SpectatorLeft(p) => Msg::SpectatorLeave(p),
                 SocketMessage::LeaderChange(leader) => Msg::LeaderChange(leader),
                 SocketMessage::Moved(board, mov) => Msg::BoardUpdate(board, Some(mov)),
+                SocketMessage::TurnSkipped(color, board) => Msg::TurnSkipped(color, board),
+                SocketMessage::Forfeited(color, board) => Msg::Forfeited(color, board),
+                SocketMessage::Clocks(clocks) => Msg::Clocks(clocks),
+                SocketMessage::UndoRequested(color) => Msg::UndoRequested(color),
+                SocketMessage::UndoDeclined(color) => Msg::UndoDeclined(color),
+                SocketMessage::Undone(board, moves) => Msg::Undone(board, moves),
+                SocketMessage::DrawOffered(color) => Msg::DrawOffered(color),
+                SocketMessage::RematchRequested(color) => Msg::RematchRequested(color),
+                SocketMessage::GameStart(state, settings) => Msg::Restart(state, settings),
+                SocketMessage::GameOver(outcome, counts, id) => {
+                    Msg::GameOver(outcome, counts, id)
+                }
+                SocketMessage::RatingsChanged(changes) => Msg::RatingsChanged(changes),
                 _ => Msg::Ignore,
             },
             _ => Msg::Ignore,
@@ -64,12 +135,26 @@ impl Component for Game {
             link: _link,
             selfid: _props.selfid,
             last_move: None,
+            hint: None,
+            result: None,
+            replay: None,
+            clocks: HashMap::new(),
+            preview: None,
+            undo_request: None,
+            draw_offer: None,
+            rematch: vec![],
+            rating_changes: vec![],
         }
     }
 
     fn update(&mut self, _msg: Self::Message) -> ShouldRender {
         match _msg {
             Msg::Ignore => false,
+            Msg::Refresh(lobby) => {
+                self.lobby = lobby;
+                self.hint = None;
+                true
+            }
             Msg::LeaderChange(leader) => {
                 self.lobby.state = leader;
                 true
@@ -82,9 +167,179 @@ impl Component for Game {
                 self.lobby.players.remove(&p.id);
                 true
             }
+            Msg::Chat(msg) => {
+                push_chat(&mut self.lobby, msg);
+                true
+            }
+            Msg::Recorded(record) => {
+                if self.draw_offer != Some(record.color) {
+                    self.draw_offer = None;
+                }
+                if let Some(history) = &mut self.lobby.history {
+                    history.moves.push(record);
+                }
+                self.undo_request = None;
+                true
+            }
+            Msg::Preview(moves) => {
+                self.preview = moves;
+                true
+            }
+            Msg::SpectatorJoin(p) => {
+                self.lobby.spectators.insert(p.id.clone(), p);
+                true
+            }
+            Msg::SpectatorLeave(p) => {
+                self.lobby.spectators.remove(&p.id);
+                true
+            }
             Msg::BoardUpdate(board, mov) => {
                 self.lobby.state = State::Game(board);
                 self.last_move = mov;
+                self.hint = None;
+                true
+            }
+            Msg::TurnSkipped(color, board) => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Info,
+                        content: if board.empty_points().is_empty() {
+                            format!(
+                                "{} has no legal move, remaining cells go to the opponent",
+                                self.player_name(&color),
+                            )
+                        } else {
+                            format!("{} has no legal move and is skipped", self.player_name(&color))
+                        },
+                    }));
+                self.lobby.state = State::Game(board);
+                true
+            }
+            Msg::Forfeited(color, board) => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Info,
+                        content: format!("{} is out of the game", self.player_name(&color)),
+                    }));
+                self.lobby.state = State::Game(board);
+                true
+            }
+            Msg::Clocks(clocks) => {
+                self.clocks = clocks.into_iter().map(|c| (c.color, c)).collect();
+                true
+            }
+            Msg::GameOver(outcome, counts, id) => {
+                self.result = Some((outcome, counts));
+                self.replay = Some(id);
+                true
+            }
+            Msg::RequestUndo => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::RequestUndo));
+                false
+            }
+            Msg::AnswerUndo(accept) => {
+                self.undo_request = None;
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::AnswerUndo(accept)));
+                true
+            }
+            Msg::UndoRequested(color) => {
+                self.undo_request = Some(color);
+                true
+            }
+            Msg::UndoDeclined(color) => {
+                self.undo_request = None;
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Info,
+                        content: format!("{} declined the takeback", self.player_name(&color)),
+                    }));
+                true
+            }
+            Msg::Undone(board, moves) => {
+                if let Some(history) = &mut self.lobby.history {
+                    history.moves.truncate(moves);
+                }
+                self.lobby.state = State::Game(board);
+                self.undo_request = None;
+                self.last_move = None;
+                self.hint = None;
+                self.preview = None;
+                true
+            }
+            Msg::Resign => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::Resign));
+                false
+            }
+            Msg::OfferDraw => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::OfferDraw));
+                false
+            }
+            Msg::AcceptDraw => {
+                self.draw_offer = None;
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::AcceptDraw));
+                true
+            }
+            Msg::DrawOffered(color) => {
+                self.draw_offer = Some(color);
+                true
+            }
+            Msg::Rematch => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::Rematch));
+                false
+            }
+            Msg::RematchRequested(color) => {
+                self.rematch.push(color);
+                true
+            }
+            Msg::RatingsChanged(changes) => {
+                self.rating_changes = changes;
+                true
+            }
+            Msg::Restart(state, settings) => {
+                if let State::Game(board) = &state {
+                    self.lobby.history = Some(GameHistory::new(board.clone(), settings));
+                }
+                self.lobby.state = state;
+                self.result = None;
+                self.replay = None;
+                self.clocks.clear();
+                self.preview = None;
+                self.undo_request = None;
+                self.draw_offer = None;
+                self.rematch.clear();
+                self.rating_changes.clear();
+                self.last_move = None;
+                self.hint = None;
+                true
+            }
+            Msg::Replay => {
+                if let Some(id) = &self.replay {
+                    crate::app::go_to_route(yew_router::route::Route::from(
+                        crate::app::AppRoute::Replay(id.clone()),
+                    ));
+                }
+                false
+            }
+            Msg::GoHome => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::LeaveLobby));
+                crate::app::go_to_route(yew_router::route::Route::from(
+                    crate::app::AppRoute::Home,
+                ));
+                false
+            }
+            Msg::Hint => {
+                if let State::Game(board) = &self.lobby.state {
+                    self.hint = Searcher::new(PieceDifference, 3)
+                        .with_node_limit(HINT_NODE_LIMIT)
+                        .best_move(board);
+                }
                 true
             }
             Msg::PlayerMove(mov) => {
@@ -100,25 +355,27 @@ impl Component for Game {
     }
 
     fn view(&self) -> Html {
+        if let Some((outcome, counts)) = &self.result {
+            return self.view_result(outcome, counts);
+        }
         let state = self.lobby.state.clone();
-        let color = {
-            let selfp = &self.lobby.players[&self.selfid];
-            if let PlayerStatus::JoinedLobby(_, color) = &selfp.status {
-                color.clone()
-            } else {
-                Color::Blue
-            }
+        let own_color = match self.lobby.players.get(&self.selfid).map(|p| &p.status) {
+            Some(PlayerStatus::JoinedLobby(_, color)) => Some(*color),
+            _ => None,
         };
+        let spectating = own_color.is_none();
+        let color = own_color.unwrap_or(Color::Blue);
         match &state {
             State::Lobby(_) => {
                 html! {}
             }
             State::Game(board) => {
-                let mut amounts: HashMap<Color, i32> = HashMap::new();
-                for p in board.pieces.values() {
-                    let e = amounts.entry(*p);
-                    e.and_modify(|e| *e += 1).or_insert(1);
-                }
+                let amounts: HashMap<Color, i32> = board
+                    .piece_counts()
+                    .into_iter()
+                    .filter(|(_, count)| *count > 0)
+                    .map(|(color, count)| (color, count as i32))
+                    .collect();
                 let mut progresses = vec![];
                 let total: i32 = amounts.values().sum();
                 let mut amIt = amounts.into_iter().collect::<Vec<_>>();
@@ -166,11 +423,28 @@ impl Component for Game {
                     {
                         for self.lobby.players.iter().map(|p|html!{
                             <div class="column mh-2">
-                            <PeerWidget key=format!("{:#?}",p) state=state.clone() peer=p.1.clone() turn={Some(board.turn.clone())}/>
+                            <PeerWidget key=format!("{:#?}",p) state=state.clone() peer=p.1.clone() turn={Some(board.turn.clone())} clock=self.player_clock(p.1)/>
                             </div>
                         })
                     }
                     </div>
+                    {
+                        if self.lobby.spectators.is_empty() && !spectating {
+                            html!{}
+                        } else {
+                            html!{
+                                <p class="has-text-centered">
+                                    {
+                                        if spectating {
+                                            format!("Spectating, {} watching", self.lobby.spectators.len())
+                                        } else {
+                                            format!("{} watching", self.lobby.spectators.len())
+                                        }
+                                    }
+                                </p>
+                            }
+                        }
+                    }
 
                     <div class="columns">
                         <div class="column  is-three-quarters-widescreen">
@@ -179,9 +453,37 @@ impl Component for Game {
                                     for progresses
                                 }
                             </div>
-                                <HexBoard is_sim=false  key={format!("{:?}",board)} color=color board=board move_callback=self.link.callback(|mv|Msg::PlayerMove(mv)) />
+                                {
+                                    match (self.preview, &self.lobby.history) {
+                                        (Some(moves), Some(history)) => {
+                                            let shown = history.board_at(moves);
+                                            html!{
+                                                <HexBoard is_sim=true key={format!("preview-{}",moves)} color=color board=shown move_callback=self.link.callback(|mv|Msg::PlayerMove(mv)) />
+                                            }
+                                        }
+                                        _ => html!{
+                                            <HexBoard is_sim=spectating  key={format!("{:?}",board)} color=color board=board hint=self.hint.clone() move_callback=self.link.callback(|mv|Msg::PlayerMove(mv)) />
+                                        },
+                                    }
+                                }
+                                {self.view_undo(own_color)}
+                                {self.view_draw_resign(own_color)}
+                                {
+                                    if board.turn == color && !spectating && self.preview.is_none() {
+                                        html!{
+                                            <div class="container my-4 has-text-centered">
+                                                <button class="button" onclick=self.link.callback(|_|Msg::Hint)>{"Hint"}</button>
+                                            </div>
+                                        }
+                                    } else {
+                                        html!{}
+                                    }
+                                }
+                        </div>
+                        <div class="column">
+                            {self.view_history()}
+                            <ChatPanel messages=self.lobby.chat.clone()/>
                         </div>
-
                     </div>
                     </div>
                     </div>
@@ -190,3 +492,269 @@ impl Component for Game {
         }
     }
 }
+
+impl Game {
+    fn player_clock(&self, player: &Player) -> Option<PlayerClock> {
+        match &player.status {
+            PlayerStatus::JoinedLobby(_, color) => self.clocks.get(color).copied(),
+            _ => None,
+        }
+    }
+
+    /// Move list, clicking a move shows the board right after it.
+    fn view_history(&self) -> Html {
+        let history = match &self.lobby.history {
+            Some(history) if !history.moves.is_empty() => history,
+            _ => return html! {},
+        };
+        html! {
+            <div class="box">
+                {
+                    move_list(history, self.preview, |color|self.player_name(color), &self.link.callback(|moves|Msg::Preview(Some(moves))))
+                }
+                {
+                    if self.preview.is_some() {
+                        html!{
+                            <button class="button is-small mt-2" onclick=self.link.callback(|_|Msg::Preview(None))>{"Back to game"}</button>
+                        }
+                    } else {
+                        html!{}
+                    }
+                }
+            </div>
+        }
+    }
+
+    /// Takeback button, or the question of the player asking for one.
+    fn view_undo(&self, own_color: Option<Color>) -> Html {
+        let own_color = match own_color {
+            Some(color) if self.preview.is_none() => color,
+            _ => return html! {},
+        };
+        let history = match &self.lobby.history {
+            Some(history) if history.settings.takebacks => history,
+            _ => return html! {},
+        };
+        match self.undo_request {
+            Some(color) if color == own_color => html! {
+                <p class="has-text-centered my-4">{"Waiting for the others to accept the takeback"}</p>
+            },
+            Some(color) => html! {
+                <div class="container my-4 has-text-centered">
+                    <p class="mb-2">{format!("{} asks to take back their move", self.player_name(&color))}</p>
+                    <div class="buttons is-centered">
+                        <button class="button is-success" onclick=self.link.callback(|_|Msg::AnswerUndo(true))>{"Accept"}</button>
+                        <button class="button is-danger" onclick=self.link.callback(|_|Msg::AnswerUndo(false))>{"Decline"}</button>
+                    </div>
+                </div>
+            },
+            None if history.moves.iter().any(|record| record.color == own_color) => html! {
+                <div class="container my-4 has-text-centered">
+                    <button class="button" onclick=self.link.callback(|_|Msg::RequestUndo)>{"Take back"}</button>
+                </div>
+            },
+            None => html! {},
+        }
+    }
+
+    /// Resign and draw buttons, or the draw offered by another player.
+    fn view_draw_resign(&self, own_color: Option<Color>) -> Html {
+        let own_color = match own_color {
+            Some(color) if self.preview.is_none() => color,
+            _ => return html! {},
+        };
+        html! {
+            <div class="container my-4 has-text-centered">
+                {
+                    match self.draw_offer {
+                        Some(color) if color != own_color => html!{
+                            <p class="mb-2">
+                                {format!("{} offers a draw ", self.player_name(&color))}
+                                <button class="button is-small is-success" onclick=self.link.callback(|_|Msg::AcceptDraw)>{"Accept"}</button>
+                            </p>
+                        },
+                        _ => html!{},
+                    }
+                }
+                <div class="buttons is-centered">
+                    <button class="button" disabled=self.draw_offer.is_some() onclick=self.link.callback(|_|Msg::OfferDraw)>{"Offer draw"}</button>
+                    <button class="button is-danger is-outlined" onclick=self.link.callback(|_|Msg::Resign)>{"Resign"}</button>
+                </div>
+            </div>
+        }
+    }
+
+    /// Rematch button and the players who asked for one.
+    fn view_rematch(&self) -> Html {
+        let own_color = match self.lobby.players.get(&self.selfid).map(|p| &p.status) {
+            Some(PlayerStatus::JoinedLobby(_, color)) => *color,
+            _ => return html! {},
+        };
+        let asked = self
+            .rematch
+            .iter()
+            .map(|color| self.player_name(color))
+            .collect::<Vec<_>>();
+        html! {
+            <>
+            {
+                if asked.is_empty() {
+                    html!{}
+                } else {
+                    html!{<p class="mt-4">{format!("Rematch asked by {}", asked.join(", "))}</p>}
+                }
+            }
+            <button class="button mt-4 mr-2" disabled=self.rematch.contains(&own_color) onclick=self.link.callback(|_|Msg::Rematch)>{"Rematch"}</button>
+            </>
+        }
+    }
+
+    fn player_name(&self, color: &Color) -> String {
+        self.lobby
+            .players
+            .values()
+            .find(|p| matches!(&p.status, PlayerStatus::JoinedLobby(_, c) if c == color))
+            .map(|p| p.name.clone())
+            .unwrap_or_else(|| String::from(*color))
+    }
+
+    /// The finished game with the names of its players.
+    fn record(&self, outcome: &Outcome) -> Option<GameRecord> {
+        let history = self.lobby.history.clone()?;
+        Some(GameRecord {
+            names: history
+                .start
+                .players
+                .iter()
+                .map(|color| (*color, self.player_name(color)))
+                .collect(),
+            history,
+            result: *outcome,
+        })
+    }
+
+    /// New rating of the player of `color` and how much it moved, if the game
+    /// was rated.
+    fn view_rating_change(&self, color: &Color) -> Html {
+        match self
+            .rating_changes
+            .iter()
+            .find(|change| change.color == *color)
+        {
+            Some(change) => {
+                let class = if change.delta < 0 {
+                    "has-text-danger"
+                } else {
+                    "has-text-success"
+                };
+                html! {
+                    <div class="level-item">
+                        <span class=class>
+                            {format!("{} ({:+})", change.rating, change.delta)}
+                        </span>
+                    </div>
+                }
+            }
+            None => html! {},
+        }
+    }
+
+    /// Shows the final standings, `counts` is ordered from first to last.
+    fn view_result(&self, outcome: &Outcome, counts: &[(Color, u32)]) -> Html {
+        let title = match outcome {
+            Outcome::Win(color) => format!("{} wins", self.player_name(color)),
+            Outcome::TeamWin(team) => match &self.lobby.state {
+                State::Game(board) => format!(
+                    "{} win",
+                    board
+                        .teams
+                        .get(*team)
+                        .map(|colors| {
+                            colors
+                                .iter()
+                                .map(|color| self.player_name(color))
+                                .collect::<Vec<_>>()
+                                .join(" & ")
+                        })
+                        .unwrap_or_default()
+                ),
+                State::Lobby(_) => format!("Team {} wins", team + 1),
+            },
+            Outcome::Draw => "Draw".to_string(),
+            Outcome::InProgress => "Game in progress".to_string(),
+        };
+        html! {
+            <div class="section">
+                <div class="container">
+                    <h1 class="title has-text-centered">
+                        {format!("Room {}",self.lobby.id)}
+                    </h1>
+                </div>
+                <div class="center-div">
+                <div style="display:inline-flex;">
+                    <div class="my-4 box has-text-centered" style="min-width:300px;">
+                        <h2 class="subtitle">{title}</h2>
+                        {
+                            match &self.lobby.state {
+                                State::Game(board) if !board.teams.is_empty() => html!{
+                                    <p class="mb-4">
+                                    {
+                                        board.team_counts().iter().enumerate().map(|(i,count)|format!("Team {}: {}",i+1,count)).collect::<Vec<_>>().join(" · ")
+                                    }
+                                    </p>
+                                },
+                                _ => html!{},
+                            }
+                        }
+                        {
+                            for counts.iter().enumerate().map(|(i,(color,count))|html!{
+                                <div class="level is-mobile">
+                                    <div class="level-left">
+                                        <div class="level-item">
+                                            {format!("#{}",place(counts,i))}
+                                        </div>
+                                        <div class="level-item">
+                                            {avatar(&self.player_name(color),color)}
+                                        </div>
+                                        <div class="level-item">
+                                            {self.player_name(color)}
+                                        </div>
+                                    </div>
+                                    <div class="level-right">
+                                        {self.view_rating_change(color)}
+                                        <div class="level-item">
+                                            {count}
+                                        </div>
+                                    </div>
+                                </div>
+                            })
+                        }
+                        {
+                            if let Some(record) = self.record(outcome) {
+                                html!{
+                                    <a class="button mt-4 mr-2" download=format!("hexagon-{}.txt",self.lobby.id) href=format!("data:text/plain;charset=utf-8,{}",String::from(js_sys::encode_uri_component(&record.to_text())))>{"Download game"}</a>
+                                }
+                            } else {
+                                html!{}
+                            }
+                        }
+                        {self.view_rematch()}
+                        <button class="button mt-4 mr-2" onclick=self.link.callback(|_|Msg::Replay)>{"Watch replay"}</button>
+                        <button class="button mt-4" onclick=self.link.callback(|_|Msg::GoHome)>{"Home"}</button>
+                    </div>
+                </div>
+                </div>
+            </div>
+        }
+    }
+}
+
+/// Place of the `i`th entry of the standings, players with the same number
+/// of pieces share a place unless they were eliminated.
+fn place(counts: &[(Color, u32)], i: usize) -> usize {
+    let count = counts[i].1;
+    if count == 0 {
+        return i + 1;
+    }
+    counts.iter().position(|(_, c)| *c == count).unwrap_or(i) + 1
+}
@@ -15,9 +15,21 @@ pub struct HexBoard {
     move_callback: Callback<Move>,
     selected_cell: Option<Point>,
     player_color: Color,
+    hint: Option<Move>,
+    transition: Option<Transition>,
     link: ComponentLink<Self>,
 }
 
+/// A turn animated on the board, from `before` to the shown board.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub before: Board,
+    /// The move played, none for passes and forfeits.
+    pub mov: Option<Move>,
+    /// From 0 showing `before` to 1 showing the board after the turn.
+    pub progress: f32,
+}
+
 pub enum Msg {
     SelectPoint(Point),
 }
@@ -28,6 +40,10 @@ pub struct Props {
     pub color: Color,
     pub move_callback: Callback<Move>,
     pub is_sim: bool,
+    #[prop_or_default]
+    pub hint: Option<Move>,
+    #[prop_or_default]
+    pub transition: Option<Transition>,
 }
 
 impl Component for HexBoard {
@@ -43,6 +59,8 @@ impl Component for HexBoard {
             player_color: props.color,
             move_callback: props.move_callback,
             is_sim: props.is_sim,
+            hint: props.hint,
+            transition: props.transition,
         }
     }
 
@@ -76,36 +94,37 @@ impl Component for HexBoard {
     }
 
     fn change(&mut self, _props: Self::Properties) -> ShouldRender {
-        false
+        // Animated boards change every frame, others are keyed by the parent.
+        let animating = self.transition.is_some() || _props.transition.is_some();
+        if animating {
+            self.board = _props.board;
+        }
+        self.transition = _props.transition;
+        if self.hint != _props.hint {
+            self.hint = _props.hint;
+            true
+        } else {
+            animating
+        }
     }
 
     fn view(&self) -> Html {
-        let cellwidth = 100.0 / ((self.board.max_size * 2) - 1) as f32;
+        let cellwidth = self.cell_width();
 
         let mut neighbourpts = vec![];
         let mut secondaryneighbours = vec![];
 
         if let Some(pt) = self.selected_cell {
-            if let Some(c) = self.board.pieces.get(&pt) {
-                if *c == self.board.turn && self.board.turn == self.player_color {
+            if let Some(c) = self.board.piece_at(&pt) {
+                if c == self.board.turn && self.board.turn == self.player_color {
                     neighbourpts = self.board.get_neighbours(&pt);
                     secondaryneighbours = self.board.get_secondary_neighbours(&pt);
                 }
             }
         }
 
-        let hexs = self.board.points.iter().map(|(k,v)|{
-            let off = OffsetCoord::from(v.clone());
-            let shift_left = {
-                if off.row%2 !=0 {
-                    0.5
-                }else{
-                    0 as f32
-                }
-            };
-            let shift_top = {
-                off.row as f32 * cellwidth * 0.25
-            };
+        let hexs = self.board.points().iter().map(|k|{
+            let (left, top) = self.position(k);
             let pt = k.clone();
 
             let mut color = {
@@ -121,14 +140,13 @@ impl Component for HexBoard {
                             GameColors::NormalCellColor
                         }
                     }
+                }else if self.hint.as_ref().map(|h|h.from == pt || h.to == pt).unwrap_or(false){
+                    GameColors::HintCellColor
                 }else{
                     GameColors::NormalCellColor
                 }
             };
-            let mut piece = None;
-            if let Some(val)= self.board.pieces.get(&pt){
-                piece = Some(*val);
-            }
+            let (piece, effect) = self.animated_piece(&pt);
             html!{
                 <>
                 <div class="hexagon" 
@@ -139,16 +157,17 @@ impl Component for HexBoard {
                     ",
                     cellwidth,
                     cellwidth,
-                    cellwidth*(off.col - 1 + self.board.max_size as i32) as f32 + shift_left*cellwidth,
-                    cellwidth*(off.row - 1 + self.board.max_size as i32) as f32 - shift_top,
+                    left,
+                    top,
                     
                 )
                 onclick = self.link.callback(move|_|Msg::SelectPoint(pt.clone()))
                 >
                     <div class="hex-cell"
                         style = format!(
-                            "background-color:{};",
-                            piece.map(|f|String::from(f)).unwrap_or(String::from(color))
+                            "background-color:{};{}",
+                            piece.map(|f|String::from(f)).unwrap_or(String::from(color)),
+                            effect
                         )
                     />
                     
@@ -157,11 +176,126 @@ impl Component for HexBoard {
                 </>
             }
         });
+        let holes = self.board.blocked().iter().map(|k|{
+            let (left, top) = self.position(k);
+            html!{
+                <div class="hexagon blocked"
+                style=format!(
+                    "height:{}%;width:{}%;
+                    left:{}%;top:{}%;
+                    ",
+                    cellwidth,
+                    cellwidth,
+                    left,
+                    top,
+                )
+                >
+                    <div class="hex-cell"
+                        style = format!(
+                            "background-color:{};",
+                            String::from(GameColors::BlockedCellColor)
+                        )
+                    />
+                </div>
+            }
+        });
         html! {
             <div class="hex-board" >
             {
                 for hexs
             }
+            {
+                for holes
+            }
+            {
+                self.view_moving_piece()
+            }
+            </div>
+        }
+    }
+}
+
+impl HexBoard {
+    fn cell_width(&self) -> f32 {
+        100.0 / ((self.board.max_size * 2) - 1) as f32
+    }
+
+    /// Left and top of the cell at `pt` in percent of the board.
+    fn position(&self, pt: &Point) -> (f32, f32) {
+        let cellwidth = self.cell_width();
+        let off = OffsetCoord::from(AxialCoord::from(*pt));
+        let shift_left = if off.row % 2 != 0 { 0.5 } else { 0.0 };
+        let shift_top = off.row as f32 * cellwidth * 0.25;
+        (
+            cellwidth * (off.col - 1 + self.board.max_size as i32) as f32 + shift_left * cellwidth,
+            cellwidth * (off.row - 1 + self.board.max_size as i32) as f32 - shift_top,
+        )
+    }
+
+    /// Piece shown at `pt` and extra style for it while a turn is animated.
+    /// The moved piece lands halfway, captured and filled cells change then
+    /// and removed pieces fade out.
+    fn animated_piece(&self, pt: &Point) -> (Option<Color>, String) {
+        let piece = self.board.piece_at(pt);
+        let transition = match &self.transition {
+            Some(transition) => transition,
+            None => return (piece, String::new()),
+        };
+        let before = transition.before.piece_at(pt);
+        let arrived = transition.progress >= 0.5;
+        let pop = format!(
+            "transform:scale({});",
+            0.6 + 0.8 * (transition.progress - 0.5)
+        );
+        match &transition.mov {
+            Some(mov) if mov.to == *pt => {
+                if arrived {
+                    (piece, pop)
+                } else {
+                    (None, String::new())
+                }
+            }
+            Some(mov) if mov.from == *pt && piece.is_none() => (None, String::new()),
+            _ if before == piece => (piece, String::new()),
+            _ if piece.is_some() => {
+                if arrived {
+                    (piece, pop)
+                } else {
+                    (before, String::new())
+                }
+            }
+            _ => (before, format!("opacity:{};", 1.0 - transition.progress)),
+        }
+    }
+
+    /// The piece travelling from the start to the end of an animated move.
+    fn view_moving_piece(&self) -> Html {
+        let (transition, mov) = match &self.transition {
+            Some(transition) if transition.progress < 0.5 => match &transition.mov {
+                Some(mov) => (transition, mov),
+                None => return html! {},
+            },
+            _ => return html! {},
+        };
+        let color = match transition.before.piece_at(&mov.from) {
+            Some(color) => color,
+            None => return html! {},
+        };
+        let cellwidth = self.cell_width();
+        let k = transition.progress * 2.0;
+        let (from_left, from_top) = self.position(&mov.from);
+        let (to_left, to_top) = self.position(&mov.to);
+        html! {
+            <div class="hexagon moving"
+            style=format!(
+                "height:{}%;width:{}%;left:{}%;top:{}%;",
+                cellwidth,
+                cellwidth,
+                from_left + (to_left - from_left) * k,
+                from_top + (to_top - from_top) * k,
+            )
+            >
+                <div class="hex-cell" style=format!("background-color:{};", String::from(color))/>
             </div>
         }
     }
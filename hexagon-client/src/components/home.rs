@@ -1,11 +1,15 @@
 use hexagon_shared::{
     colors::colors::Color,
-    structures::{Lobby, PlayerMessage, SocketMessage},
+    structures::{
+        CloseCodes, Credentials, GameSettings, Identity, Lobby, LobbySummary, PlayerMessage,
+        SettingsError, SocketMessage, MAX_NAME_LENGTH,
+    },
 };
 use yew::prelude::*;
 
 use crate::agent::socket_agent::{AgentInput, AgentOutput, SocketAgent};
 use crate::components::avatar::avatar;
+use crate::components::leaderboard::Leaderboard;
 use lazy_static::lazy_static;
 
 use wasm_bindgen::*;
@@ -16,16 +20,36 @@ lazy_static! {
 
 pub struct Home {
     name: String,
+    /// Id the server knows us by, from the last connection.
+    id: String,
+    /// Username of the account signed into, empty for guests.
+    account: String,
+    /// Signing into an account with the next connection.
+    signing_in: bool,
+    new_account: bool,
+    username: String,
+    password: String,
     room_id: String,
     link: ComponentLink<Self>,
     is_connecting: bool,
+    /// Connecting to look for a public match instead of a room.
+    matching: bool,
+    /// Why the server refused to look for a match.
+    match_error: Option<String>,
+    /// List the created room for everyone browsing.
+    public: bool,
+    /// Public lobbies, while browsing them.
+    listing: Option<Vec<LobbySummary>>,
+    show_leaderboard: bool,
     socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
     props: Props,
 }
 
 #[derive(Debug, Properties, Clone)]
 pub struct Props {
-    pub lobbyjoinedcb: Callback<(String, Lobby, Color)>,
+    /// Called with the own id, the lobby and the own color, none when
+    /// spectating.
+    pub lobbyjoinedcb: Callback<(String, Lobby, Option<Color>)>,
     pub prefillroomid: String,
 }
 
@@ -33,7 +57,13 @@ use wasm_bindgen::prelude::*;
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = window)]
-    pub fn get_uid() -> String;
+    pub fn get_token() -> String;
+    #[wasm_bindgen(js_namespace = window)]
+    pub fn set_token(token: &str);
+    #[wasm_bindgen(js_namespace = window)]
+    pub fn get_account() -> String;
+    #[wasm_bindgen(js_namespace = window)]
+    pub fn set_account(account: &str);
 }
 
 pub enum Msg {
@@ -42,9 +72,24 @@ pub enum Msg {
     ErrorConnecting,
     Connect,
     Ignore,
-    LobbyJoined(Lobby, Color),
+    LobbyJoined(Lobby, Option<Color>),
     NameChange(String),
     RoomIdChange(String),
+    Analyse,
+    ToggleLeaderboard,
+    FindMatch,
+    CancelMatch,
+    MatchRejected(SettingsError),
+    TogglePublic,
+    Browse,
+    Listing(Vec<LobbySummary>),
+    JoinListed(String),
+    Identified(Identity),
+    ToggleSignIn,
+    ToggleNewAccount,
+    UsernameChange(String),
+    PasswordChange(String),
+    SignOut,
 }
 
 impl Component for Home {
@@ -56,19 +101,36 @@ impl Component for Home {
             AgentOutput::SocketConnected => Msg::Connected,
 
             AgentOutput::SocketMessage(msg) => match msg {
-                SocketMessage::LobbyJoined(lobby, color) => Msg::LobbyJoined(lobby, color),
+                SocketMessage::LobbyJoined(lobby, color) => Msg::LobbyJoined(lobby, Some(color)),
+                SocketMessage::Spectating(lobby) => Msg::LobbyJoined(lobby, None),
+                SocketMessage::Lobbies(lobbies) => Msg::Listing(lobbies),
+                SocketMessage::Identified(identity) => Msg::Identified(identity),
+                SocketMessage::SettingsRejected(err) => Msg::MatchRejected(err),
                 SocketMessage::Close(_) => Msg::Disconnected(None),
                 _ => Msg::Ignore,
             },
             AgentOutput::SocketDisconnected(reason) => Msg::Disconnected(reason),
             AgentOutput::SocketErrorConnecting => Msg::ErrorConnecting,
+            AgentOutput::SocketReconnecting(_) => Msg::Ignore,
         }));
+        let account = unsafe { get_account() };
         Home {
-            name: "".to_string(),
+            name: account.clone(),
+            id: String::new(),
+            account,
+            signing_in: false,
+            new_account: false,
+            username: String::new(),
+            password: String::new(),
             room_id: _props.prefillroomid.clone(),
             link: _link,
             socket_agent: agent,
             is_connecting: false,
+            matching: false,
+            match_error: None,
+            public: false,
+            listing: None,
+            show_leaderboard: false,
             props: _props,
         }
     }
@@ -83,6 +145,54 @@ impl Component for Home {
                 self.room_id = id;
                 true
             }
+            Msg::ToggleLeaderboard => {
+                self.show_leaderboard = !self.show_leaderboard;
+                true
+            }
+            Msg::Analyse => {
+                crate::app::go_to_route(yew_router::route::Route::from(
+                    crate::app::AppRoute::Analysis,
+                ));
+                false
+            }
+            Msg::FindMatch => {
+                self.matching = !self.name.is_empty();
+                self.match_error = None;
+                self.update(Msg::Connect)
+            }
+            Msg::CancelMatch => {
+                self.socket_agent
+                    .send(AgentInput::Send(PlayerMessage::CancelMatch));
+                false
+            }
+            Msg::MatchRejected(err) => {
+                self.matching = false;
+                self.is_connecting = false;
+                self.match_error = Some(err.to_string());
+                true
+            }
+            Msg::TogglePublic => {
+                self.public = !self.public;
+                true
+            }
+            Msg::Browse => {
+                if self.name.is_empty() {
+                    return false;
+                }
+                self.listing = Some(vec![]);
+                self.update(Msg::Connect)
+            }
+            Msg::Listing(lobbies) => {
+                self.listing = Some(lobbies);
+                self.is_connecting = false;
+                true
+            }
+            Msg::JoinListed(id) => {
+                self.is_connecting = true;
+                self.socket_agent
+                    .send(AgentInput::Send(PlayerMessage::JoinLobby(id)));
+                true
+            }
             Msg::Connect => {
                 if self.name.is_empty() {
                     false
@@ -94,16 +204,26 @@ impl Component for Home {
                 }
             }
             Msg::Connected => {
-                let uid = unsafe { get_uid() };
-                log::info!("uid is {:#?}", uid);
                 self.socket_agent
                     .send(AgentInput::Send(PlayerMessage::Initialize(
-                        uid,
+                        self.credentials(),
                         self.name.to_string(),
                     )));
-                if self.room_id.is_empty() {
+                if self.matching {
+                    self.socket_agent
+                        .send(AgentInput::Send(PlayerMessage::FindMatch(
+                            GameSettings::default(),
+                        )));
+                } else if self.listing.is_some() {
+                    self.socket_agent
+                        .send(AgentInput::Send(PlayerMessage::ListLobbies));
+                } else if self.room_id.is_empty() {
                     self.socket_agent
                         .send(AgentInput::Send(PlayerMessage::CreateLobby));
+                    if self.public {
+                        self.socket_agent
+                            .send(AgentInput::Send(PlayerMessage::SetPublic(true)));
+                    }
                 } else {
                     self.socket_agent
                         .send(AgentInput::Send(PlayerMessage::JoinLobby(
@@ -112,20 +232,68 @@ impl Component for Home {
                 }
                 false
             }
+            Msg::Identified(identity) => {
+                unsafe {
+                    set_token(&identity.token);
+                    set_account(identity.account.as_deref().unwrap_or_default());
+                }
+                self.id = identity.id;
+                self.account = identity.account.unwrap_or_default();
+                self.signing_in = false;
+                self.password.clear();
+                true
+            }
+            Msg::ToggleSignIn => {
+                self.signing_in = !self.signing_in;
+                self.name = if self.signing_in {
+                    self.username.clone()
+                } else {
+                    String::new()
+                };
+                true
+            }
+            Msg::ToggleNewAccount => {
+                self.new_account = !self.new_account;
+                true
+            }
+            Msg::UsernameChange(username) => {
+                self.name = username.clone();
+                self.username = username;
+                true
+            }
+            Msg::PasswordChange(password) => {
+                self.password = password;
+                true
+            }
+            Msg::SignOut => {
+                self.sign_out();
+                self.name.clear();
+                true
+            }
+            Msg::Disconnected(Some((code, _))) if code == CloseCodes::InvalidSession.to_code() => {
+                self.is_connecting = false;
+                self.matching = false;
+                self.listing = None;
+                self.sign_out();
+                true
+            }
             Msg::Disconnected(_) => {
                 self.is_connecting = false;
+                self.matching = false;
+                self.listing = None;
                 true
             }
             Msg::ErrorConnecting => {
                 self.is_connecting = false;
+                self.matching = false;
+                self.listing = None;
                 true
             }
             Msg::LobbyJoined(lob, color) => {
                 crate::app::go_to_route(yew_router::route::Route::from(
                     crate::app::AppRoute::Room(lob.id.clone()),
                 ));
-                let uid = unsafe { get_uid() };
-                self.props.lobbyjoinedcb.emit((uid, lob, color));
+                self.props.lobbyjoinedcb.emit((self.id.clone(), lob, color));
                 true
             }
             Msg::Ignore => false,
@@ -156,11 +324,7 @@ impl Component for Home {
 
                     <div class="container mt-2">
                         <fieldset disabled=self.is_connecting>
-                        <div class="field">
-                            <div class="control">
-                                <input oninput=self.link.callback(|msg:InputData|Msg::NameChange(msg.value)) class="input" type="text" placeholder="Enter Name"/>
-                            </div>
-                        </div>
+                        {self.view_account()}
                         </fieldset>
                     </div>
                     <div class="container mt-2">
@@ -185,6 +349,33 @@ impl Component for Home {
                             </a>
                         </button>
                     </div>
+                    {
+                        if self.room_id.is_empty() {
+                            html!{
+                                <div class="container center-div mt-2">
+                                    <label class="checkbox">
+                                        <input type="checkbox" checked=self.public onclick=self.link.callback(|_|Msg::TogglePublic)/>
+                                        {" Public room"}
+                                    </label>
+                                </div>
+                            }
+                        } else {
+                            html!{}
+                        }
+                    }
+                    {self.view_matching()}
+                    {self.view_listing()}
+                    <div class="container center-div mt-2">
+                        <a class="button is-text is-small" onclick=self.link.callback(|_|Msg::Analyse)>{"Analyse a game"}</a>
+                        <a class="button is-text is-small" onclick=self.link.callback(|_|Msg::ToggleLeaderboard)>{"Leaderboard"}</a>
+                    </div>
+                    {
+                        if self.show_leaderboard {
+                            html!{<Leaderboard/>}
+                        } else {
+                            html!{}
+                        }
+                    }
                 </div>
                 </div>
             </section>
@@ -192,3 +383,142 @@ impl Component for Home {
         }
     }
 }
+
+impl Home {
+    /// What the next connection identifies with, the stored session unless
+    /// signing into an account.
+    fn credentials(&self) -> Credentials {
+        if self.signing_in {
+            let (username, password) = (self.username.clone(), self.password.clone());
+            if self.new_account {
+                Credentials::Register(username, password)
+            } else {
+                Credentials::Login(username, password)
+            }
+        } else {
+            let token = unsafe { get_token() };
+            if token.is_empty() {
+                Credentials::Guest
+            } else {
+                Credentials::Token(token)
+            }
+        }
+    }
+
+    /// Forgets the stored session, the next connection is a new guest.
+    fn sign_out(&mut self) {
+        unsafe {
+            set_token("");
+            set_account("");
+        }
+        self.account.clear();
+    }
+
+    /// Name field for guests, the username and password when signing in or
+    /// the account signed into.
+    fn view_account(&self) -> Html {
+        if !self.account.is_empty() {
+            return html! {
+                <div class="field">
+                    <p>{format!("Signed in as {}", self.account)}</p>
+                    <a class="button is-text is-small" onclick=self.link.callback(|_|Msg::SignOut)>{"Sign out"}</a>
+                </div>
+            };
+        }
+        if !self.signing_in {
+            return html! {
+                <div class="field">
+                    <div class="control">
+                        <input value=self.name.clone() oninput=self.link.callback(|msg:InputData|Msg::NameChange(msg.value)) class="input" type="text" maxlength=MAX_NAME_LENGTH.to_string() placeholder="Enter Name"/>
+                    </div>
+                    <a class="button is-text is-small" onclick=self.link.callback(|_|Msg::ToggleSignIn)>{"Sign in"}</a>
+                </div>
+            };
+        }
+        html! {
+            <div class="field">
+                <div class="control">
+                    <input value=self.username.clone() oninput=self.link.callback(|msg:InputData|Msg::UsernameChange(msg.value)) class="input" type="text" placeholder="Username"/>
+                </div>
+                <div class="control mt-2">
+                    <input value=self.password.clone() oninput=self.link.callback(|msg:InputData|Msg::PasswordChange(msg.value)) class="input" type="password" placeholder="Password"/>
+                </div>
+                <label class="checkbox mt-2">
+                    <input type="checkbox" checked=self.new_account onclick=self.link.callback(|_|Msg::ToggleNewAccount)/>
+                    {" New account"}
+                </label>
+                <a class="button is-text is-small" onclick=self.link.callback(|_|Msg::ToggleSignIn)>{"Play as guest"}</a>
+            </div>
+        }
+    }
+
+    /// Button to browse the public lobbies, or the lobbies with a button to
+    /// join each.
+    fn view_listing(&self) -> Html {
+        let listing = match &self.listing {
+            Some(listing) => listing,
+            None => {
+                return html! {
+                    <div class="container center-div mt-2">
+                        <button class="button is-outlined" disabled=self.name.is_empty() || self.is_connecting onclick=self.link.callback(|_|Msg::Browse)>{"Browse rooms"}</button>
+                    </div>
+                }
+            }
+        };
+        html! {
+            <div class="panel mt-4 has-text-left">
+                <p class="panel-heading">{"Public rooms"}</p>
+                {
+                    if listing.is_empty() {
+                        html!{<div class="panel-block">{"No public rooms right now"}</div>}
+                    } else {
+                        html!{}
+                    }
+                }
+                {
+                    for listing.iter().map(|lobby|{
+                        let id = lobby.id.clone();
+                        let status = match &lobby.settings {
+                            Some(settings) if lobby.in_game => format!("playing, radius {}", settings.radius),
+                            _ if lobby.in_game => "playing".to_string(),
+                            _ => "waiting".to_string(),
+                        };
+                        html!{
+                            <div class="panel-block">
+                                <span class="mr-2">{format!("{} · {} · {} players · {}", lobby.id, lobby.host, lobby.players, status)}</span>
+                                <button class="button is-small" disabled=self.is_connecting onclick=self.link.callback(move |_|Msg::JoinListed(id.clone()))>
+                                    {if lobby.in_game {"Watch"} else {"Join"}}
+                                </button>
+                            </div>
+                        }
+                    })
+                }
+            </div>
+        }
+    }
+
+    /// Button to look for a public match, or the search going on.
+    fn view_matching(&self) -> Html {
+        if self.matching {
+            html! {
+                <div class="container center-div mt-2">
+                    <p class="mr-2">{"Looking for an opponent"}</p>
+                    <button class="button is-small" onclick=self.link.callback(|_|Msg::CancelMatch)>{"Cancel"}</button>
+                </div>
+            }
+        } else {
+            html! {
+                <div class="container center-div mt-2">
+                    <button class="button is-outlined" disabled=self.name.is_empty() || self.is_connecting onclick=self.link.callback(|_|Msg::FindMatch)>{"Find a match"}</button>
+                    {
+                        if let Some(err) = &self.match_error {
+                            html!{<p class="help is-danger ml-2">{err}</p>}
+                        } else {
+                            html!{}
+                        }
+                    }
+                </div>
+            }
+        }
+    }
+}
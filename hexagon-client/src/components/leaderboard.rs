@@ -0,0 +1,110 @@
+use hexagon_shared::structures::Rating;
+use yew::format::{Nothing, Text};
+use yew::prelude::*;
+use yew::services::fetch::{FetchService, FetchTask, Request, Response};
+
+/// Best rated players, loaded from the server when shown.
+pub struct Leaderboard {
+    _fetch: Option<FetchTask>,
+    ratings: Option<Vec<Rating>>,
+    error: Option<String>,
+}
+
+pub enum Msg {
+    Loaded(Result<Vec<Rating>, String>),
+}
+
+/// Where the server serves the leaderboard, next to the socket.
+fn leaderboard_url() -> String {
+    format!(
+        "{}/leaderboard",
+        env!("SERVER_URL")
+            .replacen("ws", "http", 1)
+            .trim_end_matches('/')
+    )
+}
+
+impl Component for Leaderboard {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(_props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let request = Request::get(leaderboard_url()).body(Nothing);
+        let fetch = request.map_err(|err| err.to_string()).and_then(|request| {
+            FetchService::fetch(
+                request,
+                link.callback(|response: Response<Text>| {
+                    let status = response.status();
+                    Msg::Loaded(match response.into_body() {
+                        Ok(text) if status.is_success() => {
+                            serde_json::from_str(&text).map_err(|err| err.to_string())
+                        }
+                        Ok(_) => Err(format!("Leaderboard unavailable ({})", status)),
+                        Err(err) => Err(err.to_string()),
+                    })
+                }),
+            )
+            .map_err(|err| err.to_string())
+        });
+        let (fetch, error) = match fetch {
+            Ok(task) => (Some(task), None),
+            Err(err) => (None, Some(err)),
+        };
+        Self {
+            _fetch: fetch,
+            ratings: None,
+            error,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Loaded(Ok(ratings)) => {
+                self.ratings = Some(ratings);
+                true
+            }
+            Msg::Loaded(Err(err)) => {
+                self.error = Some(err);
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, _props: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        if let Some(error) = &self.error {
+            return html! {
+                <div class="notification is-danger mt-4">{error}</div>
+            };
+        }
+        let ratings = match &self.ratings {
+            Some(ratings) => ratings,
+            None => return html! {<progress class="progress is-small mt-4" max="100"/>},
+        };
+        html! {
+            <div class="panel mt-4 has-text-left">
+                <p class="panel-heading">{"Leaderboard"}</p>
+                {
+                    if ratings.is_empty() {
+                        html!{<div class="panel-block">{"No rated games yet"}</div>}
+                    } else {
+                        html!{}
+                    }
+                }
+                {
+                    for ratings.iter().enumerate().map(|(i, rating)| html!{
+                        <div class="panel-block">
+                            <span class="mr-2">{format!("#{}", i + 1)}</span>
+                            <span class="mr-2">{&rating.name}</span>
+                            <strong class="mr-2">{rating.rating}</strong>
+                            <span class="has-text-grey">{format!("{} games", rating.games)}</span>
+                        </div>
+                    })
+                }
+            </div>
+        }
+    }
+}
@@ -1,8 +1,13 @@
+pub mod analysis;
 pub mod avatar;
 pub mod backdrop;
+pub mod chat;
 pub mod game;
 pub mod hex_board;
 pub mod home;
+pub mod leaderboard;
+pub mod move_list;
 pub mod notification_widget;
 pub mod peer;
+pub mod replay;
 pub mod room;
@@ -0,0 +1,31 @@
+use hexagon_shared::{
+    board::{GameHistory, Notation},
+    colors::colors::Color,
+};
+use yew::prelude::*;
+
+/// Turns of `history` in notation, clicking one calls `onselect` with the
+/// number of turns up to and including it. The `selected` turn is
+/// highlighted.
+pub fn move_list(
+    history: &GameHistory,
+    selected: Option<usize>,
+    name: impl Fn(&Color) -> String,
+    onselect: &Callback<usize>,
+) -> Html {
+    let notation = Notation::new(&history.start);
+    html! {
+        <div class="move-list">
+        {
+            for history.moves.iter().enumerate().map(|(i,record)|{
+                let onclick = onselect.reform(move |_|i + 1);
+                html!{
+                    <a class=if selected == Some(i + 1) {"panel-block is-active"} else {"panel-block"} onclick=onclick>
+                        {format!("{}. {} {}", i + 1, name(&record.color), notation.action(&record.action))}
+                    </a>
+                }
+            })
+        }
+        </div>
+    }
+}
@@ -1,6 +1,6 @@
 use hexagon_shared::{
     colors::colors::Color,
-    structures::{Player, PlayerStatus, State},
+    structures::{Player, PlayerClock, PlayerStatus, State},
 };
 use web_sys::Blob;
 use yew::prelude::*;
@@ -13,6 +13,7 @@ pub struct PeerWidget {
     _socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
     state: State,
     turn: Option<Color>,
+    clock: Option<PlayerClock>,
     link: ComponentLink<Self>,
 
     peer: Player,
@@ -27,6 +28,8 @@ pub struct Props {
     pub peer: Player,
     pub state: State,
     pub turn: Option<Color>,
+    #[prop_or_default]
+    pub clock: Option<PlayerClock>,
 }
 
 impl Component for PeerWidget {
@@ -44,6 +47,7 @@ impl Component for PeerWidget {
             peer: _props.peer,
             state: _props.state,
             turn: _props.turn,
+            clock: _props.clock,
         }
     }
 
@@ -57,6 +61,7 @@ impl Component for PeerWidget {
         self.state = _props.state;
         self.peer = _props.peer;
         self.turn = _props.turn;
+        self.clock = _props.clock;
         true
     }
 
@@ -66,7 +71,7 @@ impl Component for PeerWidget {
         use crate::components::avatar::avatar;
         let color = {
             match &self.peer.status {
-                PlayerStatus::Initiated => Color::Blue,
+                PlayerStatus::Initiated | PlayerStatus::Spectating(_) => Color::Blue,
                 PlayerStatus::JoinedLobby(_, color) => *color,
             }
         };
@@ -87,7 +92,7 @@ impl Component for PeerWidget {
         };
         html! {
             <>
-                <div class="container has-text-centered">
+                <div class="container has-text-centered" style=if self.peer.away {"opacity:0.5;"} else {""}>
 
                     <div id=&self.peer.id style=format!("display:inline-block;border-width:5px;border-style:solid;border-radius:50%;border-color:{}",border_color)>
                     {
@@ -99,8 +104,26 @@ impl Component for PeerWidget {
                         &self.peer.name
                     }
                     </div>
+                    {
+                        if let Some(clock) = &self.clock {
+                            html!{
+                                <div class="is-family-monospace">
+                                {
+                                    clock.turn.iter().chain(clock.total.iter()).map(|secs|format_time(*secs)).collect::<Vec<_>>().join(" / ")
+                                }
+                                </div>
+                            }
+                        } else {
+                            html!{}
+                        }
+                    }
                 </div>
             </>
         }
     }
 }
+
+/// Seconds as `m:ss`.
+fn format_time(secs: u32) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
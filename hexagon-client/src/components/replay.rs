@@ -0,0 +1,367 @@
+use std::time::Duration;
+
+use hexagon_shared::{
+    board::{Board, GameRecord, Notation, Outcome, TurnAction},
+    colors::colors::Color,
+};
+use yew::format::{Nothing, Text};
+use yew::prelude::*;
+use yew::services::fetch::{FetchService, FetchTask, Request, Response};
+
+use crate::agent::anim_agent::{AgentInput, AgentOutput, AnimAgent};
+use crate::components::avatar::avatar;
+use crate::components::hex_board::{HexBoard, Transition};
+use crate::components::move_list::move_list;
+
+/// How long one turn takes to play at normal speed.
+const TURN_DURATION: Duration = Duration::from_millis(800);
+const SPEEDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+
+/// Plays back a finished game the server kept, turn by turn.
+pub struct Replay {
+    link: ComponentLink<Self>,
+    anim_agent: Box<dyn yew::Bridge<AnimAgent>>,
+    _fetch: Option<FetchTask>,
+    record: Option<GameRecord>,
+    error: Option<String>,
+    /// Board after each number of turns.
+    boards: Vec<Board>,
+    /// Number of turns played on the board at rest.
+    shown: usize,
+    /// Turn being animated and how far, with the position it ends on.
+    animating: Option<(usize, f32)>,
+    target: usize,
+    playing: bool,
+    speed: f32,
+}
+
+pub enum Msg {
+    Ignore,
+    Loaded(Result<GameRecord, String>),
+    Progress(f32),
+    AnimEnd,
+    Next,
+    Prev,
+    Play,
+    Pause,
+    Speed(f32),
+    Scrub(usize),
+    GoHome,
+}
+
+#[derive(Properties, Clone, Debug)]
+pub struct Props {
+    pub id: String,
+}
+
+/// Where the server serves the record of a game, next to the socket.
+fn record_url(id: &str) -> String {
+    format!(
+        "{}/games/{}",
+        env!("SERVER_URL")
+            .replacen("ws", "http", 1)
+            .trim_end_matches('/'),
+        id
+    )
+}
+
+impl Component for Replay {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let anim_agent = AnimAgent::bridge(link.callback(|msg| match msg {
+            AgentOutput::Progress(progress) => Msg::Progress(progress),
+            AgentOutput::End(_) => Msg::AnimEnd,
+        }));
+        let request = Request::get(record_url(&props.id)).body(Nothing);
+        let fetch = request.map_err(|err| err.to_string()).and_then(|request| {
+            FetchService::fetch(
+                request,
+                link.callback(|response: Response<Text>| {
+                    let status = response.status();
+                    Msg::Loaded(match response.into_body() {
+                        Ok(text) if status.is_success() => {
+                            text.parse::<GameRecord>().map_err(|err| err.to_string())
+                        }
+                        Ok(_) => Err(format!("No game found ({})", status)),
+                        Err(err) => Err(err.to_string()),
+                    })
+                }),
+            )
+            .map_err(|err| err.to_string())
+        });
+        let (fetch, error) = match fetch {
+            Ok(task) => (Some(task), None),
+            Err(err) => (None, Some(err)),
+        };
+        Self {
+            link,
+            anim_agent,
+            _fetch: fetch,
+            record: None,
+            error,
+            boards: vec![],
+            shown: 0,
+            animating: None,
+            target: 0,
+            playing: false,
+            speed: 1.0,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Ignore => false,
+            Msg::Loaded(Ok(record)) => {
+                let mut board = record.history.start.clone();
+                self.boards = vec![board.clone()];
+                for turn in &record.history.moves {
+                    turn.replay(&mut board);
+                    self.boards.push(board.clone());
+                }
+                self.record = Some(record);
+                self.anim_agent
+                    .send(AgentInput::ChangeDuration(self.turn_duration()));
+                true
+            }
+            Msg::Loaded(Err(err)) => {
+                self.error = Some(err);
+                true
+            }
+            Msg::Progress(progress) => {
+                if let Some((turn, _)) = self.animating {
+                    self.animating = Some((turn, progress));
+                    true
+                } else {
+                    false
+                }
+            }
+            Msg::AnimEnd => {
+                self.settle();
+                if self.playing {
+                    self.playing = self.animate_forward();
+                }
+                true
+            }
+            Msg::Next => {
+                self.settle();
+                self.animate_forward();
+                true
+            }
+            Msg::Prev => {
+                self.settle();
+                self.animate_back();
+                true
+            }
+            Msg::Play => {
+                self.settle();
+                if self.shown + 1 >= self.boards.len() {
+                    self.shown = 0;
+                }
+                self.playing = self.animate_forward();
+                true
+            }
+            Msg::Pause => {
+                self.playing = false;
+                true
+            }
+            Msg::Speed(speed) => {
+                self.speed = speed;
+                self.anim_agent
+                    .send(AgentInput::ChangeDuration(self.turn_duration()));
+                true
+            }
+            Msg::Scrub(turn) => {
+                self.settle();
+                self.shown = turn.min(self.boards.len().saturating_sub(1));
+                if self.playing {
+                    self.playing = self.animate_forward();
+                }
+                true
+            }
+            Msg::GoHome => {
+                crate::app::go_to_route(yew_router::route::Route::from(crate::app::AppRoute::Home));
+                false
+            }
+        }
+    }
+
+    fn change(&mut self, _props: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <div class="section">
+                <div class="container">
+                    <h1 class="title has-text-centered">{"Replay"}</h1>
+                </div>
+                {
+                    match (&self.record, &self.error) {
+                        (_, Some(error)) => html!{
+                            <div class="center-div">
+                                <div class="box mt-4 has-text-centered">
+                                    <p class="mb-4">{error}</p>
+                                    <button class="button" onclick=self.link.callback(|_|Msg::GoHome)>{"Home"}</button>
+                                </div>
+                            </div>
+                        },
+                        (Some(record), None) => self.view_record(record),
+                        (None, None) => html!{
+                            <progress class="progress is-small" max="100"/>
+                        },
+                    }
+                }
+            </div>
+        }
+    }
+}
+
+impl Replay {
+    fn turn_duration(&self) -> Duration {
+        TURN_DURATION.div_f32(self.speed)
+    }
+
+    /// Ends a running animation on the position it was heading to.
+    fn settle(&mut self) {
+        if self.animating.take().is_some() {
+            self.anim_agent.send(AgentInput::Pause);
+            self.shown = self.target;
+        }
+    }
+
+    /// Starts animating the next turn, returns false at the end of the game.
+    fn animate_forward(&mut self) -> bool {
+        if self.shown + 1 >= self.boards.len() {
+            return false;
+        }
+        self.animating = Some((self.shown, 0.0));
+        self.target = self.shown + 1;
+        self.anim_agent.send(AgentInput::Seek(0.0));
+        self.anim_agent.send(AgentInput::Forward);
+        true
+    }
+
+    /// Plays the last turn backwards.
+    fn animate_back(&mut self) {
+        if self.shown == 0 {
+            return;
+        }
+        self.animating = Some((self.shown - 1, 1.0));
+        self.target = self.shown - 1;
+        self.anim_agent.send(AgentInput::Seek(1.0));
+        self.anim_agent.send(AgentInput::BackWard);
+    }
+
+    fn view_record(&self, record: &GameRecord) -> Html {
+        let history = &record.history;
+        let last = history.moves.len();
+        let shown = self.shown;
+        let (key, board, transition) = match self.animating {
+            Some((turn, progress)) => (
+                format!("turn-{}", turn),
+                self.boards[turn + 1].clone(),
+                Some(Transition {
+                    before: self.boards[turn].clone(),
+                    mov: match &history.moves[turn].action {
+                        TurnAction::Move(mov) => Some(mov.clone()),
+                        _ => None,
+                    },
+                    progress,
+                }),
+            ),
+            None => (format!("at-{}", shown), self.boards[shown].clone(), None),
+        };
+        let turn = board.turn;
+        let name = |color: &Color| {
+            record
+                .names
+                .get(color)
+                .filter(|name| !name.is_empty())
+                .cloned()
+                .unwrap_or_else(|| String::from(*color))
+        };
+        let status = match shown.checked_sub(1).map(|i| &history.moves[i]) {
+            Some(played) => format!(
+                "Turn {} of {}: {} {}",
+                shown,
+                last,
+                name(&played.color),
+                Notation::new(&history.start).action(&played.action)
+            ),
+            None => format!("Start, {} turns", last),
+        };
+        let result = match record.result {
+            Outcome::Win(color) => format!("{} wins", name(&color)),
+            Outcome::TeamWin(team) => format!("Team {} wins", team + 1),
+            Outcome::Draw => "Draw".to_string(),
+            Outcome::InProgress => "Unfinished".to_string(),
+        };
+        html! {
+            <div class="columns mt-4">
+                <div class="column is-three-quarters-widescreen">
+                    <HexBoard is_sim=true key=key color=turn board=board transition=transition move_callback=self.link.callback(|_|Msg::Ignore) />
+                    <p class="has-text-centered mt-4">{status}</p>
+                    <input class="slider is-fullwidth" type="range" min="0" max=last.to_string() value=shown.to_string() oninput=self.link.callback(|data:InputData|match data.value.parse() {
+                        Ok(turn) => Msg::Scrub(turn),
+                        Err(_) => Msg::Ignore,
+                    })/>
+                    {self.view_controls(last)}
+                </div>
+                <div class="column">
+                    <div class="box">
+                        {
+                            for history.start.players.iter().map(|color|html!{
+                                <div class="level is-mobile">
+                                    <div class="level-left">
+                                        <div class="level-item">{avatar(&name(color),color)}</div>
+                                        <div class="level-item">{name(color)}</div>
+                                    </div>
+                                </div>
+                            })
+                        }
+                        <p class="subtitle">{result}</p>
+                    </div>
+                    <div class="box">
+                        {move_list(history, Some(shown), name, &self.link.callback(Msg::Scrub))}
+                    </div>
+                    <button class="button" onclick=self.link.callback(|_|Msg::GoHome)>{"Home"}</button>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_controls(&self, last: usize) -> Html {
+        let shown = self.shown;
+        html! {
+            <div class="level is-mobile my-4">
+                <div class="level-item buttons">
+                    <button class="button" disabled=shown == 0 onclick=self.link.callback(|_|Msg::Scrub(0))>{"⏮"}</button>
+                    <button class="button" disabled=shown == 0 onclick=self.link.callback(|_|Msg::Prev)>{"◀"}</button>
+                    {
+                        if self.playing {
+                            html!{<button class="button" onclick=self.link.callback(|_|Msg::Pause)>{"⏸"}</button>}
+                        } else {
+                            html!{<button class="button" onclick=self.link.callback(|_|Msg::Play)>{"⏵"}</button>}
+                        }
+                    }
+                    <button class="button" disabled=shown == last onclick=self.link.callback(|_|Msg::Next)>{"▶"}</button>
+                    <button class="button" disabled=shown == last onclick=self.link.callback(move |_|Msg::Scrub(last))>{"⏭"}</button>
+                </div>
+                <div class="level-item buttons has-addons">
+                {
+                    for SPEEDS.iter().map(|speed|{
+                        let speed = *speed;
+                        html!{
+                            <button class=if speed == self.speed {"button is-small is-selected is-info"} else {"button is-small"} onclick=self.link.callback(move |_|Msg::Speed(speed))>
+                                {format!("{}×", speed)}
+                            </button>
+                        }
+                    })
+                }
+                </div>
+            </div>
+        }
+    }
+}
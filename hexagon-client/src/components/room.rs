@@ -1,20 +1,31 @@
+use std::collections::HashMap;
+
+use hexagon_shared::board::{builtin_maps, BoardMap, GameHistory};
 use hexagon_shared::structures::{
-    GameType, Lobby, Player, PlayerMessage, SocketMessage, State, TeamMode,
+    BoardShape, ChatMessage, Difficulty, GameSettings, GameType, Lobby, Player, PlayerMessage, SettingsError,
+    SocketMessage, StartingLayout, State, TeamMode, TimeControl, TimeoutAction, DEFAULT_DRAW_TIME,
+    MAX_PLAYERS, MAX_RADIUS, MIN_RADIUS, TEAMS,
 };
 use yew::prelude::*;
 
+use crate::agent::notification_agent::*;
 use crate::agent::socket_agent::*;
+use crate::components::chat::{push_chat, ChatPanel};
 use crate::components::peer::PeerWidget;
 
 use crate::app::{go_to_route, AppRoute};
 
 pub struct Room {
     _socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
+    notif_agent: Box<dyn yew::Bridge<NotificationAgent>>,
     link: ComponentLink<Self>,
     lobby: Lobby,
     selfid: String,
 
     teammode: TeamMode,
+    game_type: GameType,
+    settings: GameSettings,
+    maps: Vec<BoardMap>,
 
     gamestartcb: Callback<Lobby>,
 }
@@ -23,12 +34,28 @@ pub enum Msg {
     Ignore,
     Refresh(Lobby),
     StartGame,
-    GameStarted(State),
+    Leave,
+    GameStarted(State, GameSettings),
 
     ChangeTeamMode(TeamMode),
+    AssignTeam(String, usize),
+    TeamsChanged(HashMap<String, usize>),
+    ChangeGameType(GameType),
+    ChangeRadius(u32),
+    ChangeShape(BoardShape),
+    ChangeLayout(StartingLayout),
+    ChangeClock(TimeControl),
+    ToggleTakebacks,
+    SetPublic(bool),
+    PublicChanged(bool),
+    SettingsRejected(SettingsError),
+    MapsLoaded(Vec<BoardMap>),
 
     PlayerJoined(Player),
     PlayerDisconnected(Player),
+    SpectatorJoined(Player),
+    SpectatorLeft(Player),
+    Chat(ChatMessage),
 
     LeaderChange(State), // Chat(String,String)
 }
@@ -45,13 +72,24 @@ impl Component for Room {
     type Properties = Props;
 
     fn create(_props: Self::Properties, _link: ComponentLink<Self>) -> Self {
-        let agent = SocketAgent::bridge(_link.callback(|data| match data {
+        let mut agent = SocketAgent::bridge(_link.callback(|data| match data {
             AgentOutput::SocketMessage(msg) => {
                 match msg {
                     SocketMessage::PlayerJoined(pl, _) => Msg::PlayerJoined(pl),
                     SocketMessage::PlayerDisconnected(player) => Msg::PlayerDisconnected(player),
+                    SocketMessage::PlayerAway(player) => Msg::PlayerJoined(player),
+                    SocketMessage::LobbyJoined(lobby, _) | SocketMessage::Spectating(lobby) => {
+                        Msg::Refresh(lobby)
+                    }
+                    SocketMessage::SpectatorJoined(player) => Msg::SpectatorJoined(player),
+                    SocketMessage::ChatMessage(msg) => Msg::Chat(msg),
+                    SocketMessage::SpectatorLeft(player) => Msg::SpectatorLeft(player),
                     SocketMessage::LeaderChange(leader) => Msg::LeaderChange(leader),
-                    SocketMessage::GameStart(state) => Msg::GameStarted(state),
+                    SocketMessage::GameStart(state, settings) => Msg::GameStarted(state, settings),
+                    SocketMessage::SettingsRejected(err) => Msg::SettingsRejected(err),
+                    SocketMessage::Maps(maps) => Msg::MapsLoaded(maps),
+                    SocketMessage::TeamsChanged(teams) => Msg::TeamsChanged(teams),
+                    SocketMessage::PublicChanged(public) => Msg::PublicChanged(public),
                     _ => {
                         //    log::warn!("Unexpected socket message {:#?}",msg);
                         Msg::Ignore
@@ -60,12 +98,18 @@ impl Component for Room {
             }
             _ => Msg::Ignore,
         }));
+        agent.send(AgentInput::Send(PlayerMessage::ListMaps));
+        let notif_agent = NotificationAgent::bridge(_link.callback(|_| Msg::Ignore));
         Self {
             _socket_agent: agent,
+            notif_agent,
             lobby: _props.lobby,
             link: _link,
             selfid: _props.selfid,
             teammode: TeamMode::Solo,
+            game_type: GameType::Online,
+            settings: GameSettings::default(),
+            maps: builtin_maps(),
             gamestartcb: _props.gamestartcb,
         }
     }
@@ -81,18 +125,82 @@ impl Component for Room {
                 self.teammode = mode;
                 true
             }
+            Msg::AssignTeam(id, team) => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::AssignTeam(id, team)));
+                false
+            }
+            Msg::TeamsChanged(teams) => {
+                self.lobby.teams = teams;
+                true
+            }
+            Msg::ChangeGameType(game_type) => {
+                self.game_type = game_type;
+                true
+            }
+            Msg::ChangeRadius(radius) => {
+                self.settings.radius = radius.max(MIN_RADIUS).min(MAX_RADIUS);
+                true
+            }
+            Msg::ChangeShape(shape) => {
+                self.settings.shape = shape;
+                true
+            }
+            Msg::ChangeLayout(layout) => {
+                self.settings.layout = layout;
+                true
+            }
+            Msg::ChangeClock(clock) => {
+                self.settings.clock = clock;
+                true
+            }
+            Msg::ToggleTakebacks => {
+                self.settings.takebacks = !self.settings.takebacks;
+                true
+            }
+            Msg::SetPublic(public) => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::SetPublic(public)));
+                false
+            }
+            Msg::PublicChanged(public) => {
+                self.lobby.public = public;
+                true
+            }
+            Msg::SettingsRejected(err) => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Error,
+                        content: err.to_string(),
+                    }));
+                false
+            }
+            Msg::Leave => {
+                self._socket_agent
+                    .send(AgentInput::Send(PlayerMessage::LeaveLobby));
+                crate::app::go_to_route(yew_router::route::Route::from(crate::app::AppRoute::Home));
+                false
+            }
             Msg::StartGame => {
                 self._socket_agent
                     .send(AgentInput::Send(PlayerMessage::StartGame(
-                        { GameType::TwoPlayer },
+                        self.game_type.clone(),
                         self.teammode.clone(),
+                        self.settings.clone(),
                     )));
                 false
             }
-            Msg::GameStarted(state) => {
+            Msg::MapsLoaded(maps) => {
+                self.maps = maps;
+                true
+            }
+            Msg::GameStarted(state, settings) => {
                 // crate::app::go_to_route(yew_router::route::Route::from(
                 //     crate::app::AppRoute::Game(self.lobby.id.clone()),
                 // ));
+                if let State::Game(board) = &state {
+                    self.lobby.history = Some(GameHistory::new(board.clone(), settings));
+                }
                 self.lobby.state = state;
                 self.gamestartcb.emit(self.lobby.clone());
                 true
@@ -107,6 +215,18 @@ impl Component for Room {
                 }
                 true
             }
+            Msg::Chat(msg) => {
+                push_chat(&mut self.lobby, msg);
+                true
+            }
+            Msg::SpectatorJoined(player) => {
+                self.lobby.spectators.insert(player.id.clone(), player);
+                true
+            }
+            Msg::SpectatorLeft(player) => {
+                self.lobby.spectators.remove(&player.id);
+                true
+            }
             Msg::LeaderChange(leader) => {
                 self.lobby.state = leader;
                 true
@@ -120,9 +240,21 @@ impl Component for Room {
 
     fn view(&self) -> Html {
         let state = self.lobby.state.clone();
-        let canstart = self.lobby.players.len() == 4
-            || self.lobby.players.len() == 2
-            || self.lobby.players.len() == 3;
+        let public = self.lobby.public;
+        let canstart = match self.game_type {
+            GameType::Online => {
+                self.lobby.players.len() >= 2
+                    && self.lobby.players.len() <= MAX_PLAYERS
+                    && (self.teammode == TeamMode::Solo || self.teams_are_even())
+            }
+            GameType::VsComputer(_) => self.lobby.players.len() == 1,
+        };
+        let game_types = vec![
+            ("Online", GameType::Online),
+            ("Computer (Easy)", GameType::VsComputer(Difficulty::Easy)),
+            ("Computer (Medium)", GameType::VsComputer(Difficulty::Medium)),
+            ("Computer (Hard)", GameType::VsComputer(Difficulty::Hard)),
+        ];
         html! {
             <>
                 <div class="section">
@@ -143,61 +275,41 @@ impl Component for Room {
                             })
                         }
                         </div>
-                    // {
-                    //     if self.lobby.players.len()>2{
-                    //         html!{
-                    // <div class="level is-mobile">
-                    //     <div class="level-item">
-                    //         <div class="tabs is-toggle is-toggle-rounded">
-                    //             <ul>
-                    //                 <li class= {
-                    //                     if self.teammode!=TeamMode::Solo{
-                    //                         ""
-                    //                     }else{
-                    //                         "is-active"
-                    //                     }
-                    //                 }>
-                    //                     <a
-                    //                         onclick=self.link.callback(|_|Msg::ChangeTeamMode(TeamMode::Solo))
-                    //                     >
-                    //                         <span class="icon">
-                    //                             {"Solo"}
-                    //                         </span>
-                    //                     </a>
-                    //                 </li>
-                    //                 <li class= {
-                    //                     if self.teammode==TeamMode::Solo{
-                    //                         ""
-                    //                     }else{
-                    //                         "is-active"
-                    //                     }
-                    //                 }>
-                    //                     <a class="level-item"
-                    //                         onclick=self.link.callback(|_|Msg::ChangeTeamMode(TeamMode::Team))
-                    //                     >
-                    //                         <span class="icon">
-                    //                             {"Team"}
-                    //                         </span>
-                    //                     </a>
-                    //                 </li>
-                    //             </ul>
-                    //         </div>
-                    //     </div>
-                    // </div>
-
-                    //         }
-                    //     }else{
-                    //         html!{}
-                    //     }
-                    // }
                         {
                             if self.selfid==self.lobby.state.leader(){
                                 html!{
+                                    <>
+                                    <div class="buttons has-addons is-centered">
+                                    {
+                                        for game_types.into_iter().map(|(label,game_type)|{
+                                            let selected = game_type == self.game_type;
+                                            html!{
+                                                <button class=if selected {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeGameType(game_type.clone()))>
+                                                    {label}
+                                                </button>
+                                            }
+                                        })
+                                    }
+                                    </div>
+                                    {self.view_team_mode()}
+                                    {self.view_settings()}
+                                    {self.view_clock_settings()}
+                                    <div class="buttons has-addons is-centered">
+                                        <button class=if self.settings.takebacks {"button is-selected is-info"} else {"button"} onclick=self.link.callback(|_|Msg::ToggleTakebacks)>
+                                            {"Allow takebacks"}
+                                        </button>
+                                    </div>
+                                    <div class="buttons has-addons is-centered">
+                                        <button class=if self.lobby.public {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::SetPublic(!public))>
+                                            {"Public room"}
+                                        </button>
+                                    </div>
                                     <div class="container my-4 center-div has-text-centered">
                                         <button disabled={!canstart} class="button" onclick=self.link.callback(
                                             |_|Msg::StartGame
                                         )>{"Start"}</button>
                                     </div>
+                                    </>
                                 }
                             }else{
                                 html!{
@@ -206,11 +318,228 @@ impl Component for Room {
                             }
 
                         }
+                        <div class="container center-div has-text-centered">
+                            <button class="button is-text is-small" onclick=self.link.callback(|_|Msg::Leave)>{"Leave room"}</button>
+                        </div>
                     </div>
                 </div>
                 </div>
+                <div class="center-div">
+                    <div style="min-width:300px;">
+                        <ChatPanel messages=self.lobby.chat.clone()/>
+                    </div>
+                </div>
                 </div>
             </>
         }
     }
 }
+
+impl Room {
+    fn teams_are_even(&self) -> bool {
+        let mut sizes = vec![0; TEAMS];
+        for id in self.lobby.players.keys() {
+            if let Some(size) = sizes.get_mut(self.lobby.team_of(id)) {
+                *size += 1;
+            }
+        }
+        sizes[0] > 0 && sizes.iter().all(|size| *size == sizes[0])
+    }
+
+    fn view_team_mode(&self) -> Html {
+        if self.lobby.players.len() <= 2 || self.game_type != GameType::Online {
+            return html! {};
+        }
+        let friendly_capture = matches!(
+            self.teammode,
+            TeamMode::Team {
+                friendly_capture: true
+            }
+        );
+        let mut players: Vec<&Player> = self.lobby.players.values().collect();
+        players.sort_by_key(|p| &p.id);
+        html! {
+            <>
+            <div class="buttons has-addons is-centered">
+                <button class=if self.teammode == TeamMode::Solo {"button is-selected is-info"} else {"button"} onclick=self.link.callback(|_|Msg::ChangeTeamMode(TeamMode::Solo))>
+                    {"Solo"}
+                </button>
+                <button class=if self.teammode != TeamMode::Solo {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeTeamMode(TeamMode::Team{friendly_capture}))>
+                    {"Team"}
+                </button>
+            </div>
+            {
+                if self.teammode == TeamMode::Solo {
+                    html!{}
+                } else {
+                    html!{
+                        <>
+                        <div class="buttons has-addons is-centered">
+                            <button class=if friendly_capture {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeTeamMode(TeamMode::Team{friendly_capture:!friendly_capture}))>
+                                {"Capture teammates"}
+                            </button>
+                        </div>
+                        {
+                            for players.into_iter().map(|p|{
+                                let team = self.lobby.team_of(&p.id);
+                                html!{
+                                    <div class="buttons has-addons is-centered">
+                                        <button class="button is-static">{p.name.clone()}</button>
+                                        {
+                                            for (0..TEAMS).map(|t|{
+                                                let id = p.id.clone();
+                                                html!{
+                                                    <button class=if t == team {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::AssignTeam(id.clone(),t))>
+                                                        {format!("Team {}",t+1)}
+                                                    </button>
+                                                }
+                                            })
+                                        }
+                                    </div>
+                                }
+                            })
+                        }
+                        </>
+                    }
+                }
+            }
+            </>
+        }
+    }
+
+    fn view_settings(&self) -> Html {
+        let mut shapes = vec![
+            ("Hexagon".to_string(), BoardShape::Hexagon),
+            ("Honeycomb".to_string(), BoardShape::Honeycomb),
+        ];
+        shapes.extend(
+            self.maps
+                .iter()
+                .map(|map| (map.name.clone(), BoardShape::Map(map.name.clone()))),
+        );
+        let is_map = matches!(self.settings.shape, BoardShape::Map(_));
+        let layouts = vec![
+            ("Corners", StartingLayout::Corners),
+            ("Random", StartingLayout::Random(3)),
+        ];
+        let radius = self.settings.radius;
+        html! {
+            <>
+            <div class="buttons has-addons is-centered">
+            {
+                for shapes.into_iter().map(|(label,shape)|{
+                    let selected = shape == self.settings.shape;
+                    html!{
+                        <button class=if selected {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeShape(shape.clone()))>
+                            {label}
+                        </button>
+                    }
+                })
+            }
+            </div>
+            {
+                if is_map {
+                    html!{}
+                }else{
+                    html!{
+                        <>
+                        <div class="buttons has-addons is-centered">
+                            <button class="button" disabled={radius<=MIN_RADIUS} onclick=self.link.callback(move |_|Msg::ChangeRadius(radius-1))>{"-"}</button>
+                            <button class="button is-static">{format!("Radius {}",radius)}</button>
+                            <button class="button" disabled={radius>=MAX_RADIUS} onclick=self.link.callback(move |_|Msg::ChangeRadius(radius+1))>{"+"}</button>
+                        </div>
+                        <div class="buttons has-addons is-centered">
+                        {
+                            for layouts.into_iter().map(|(label,layout)|{
+                                let selected = layout == self.settings.layout;
+                                html!{
+                                    <button class=if selected {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeLayout(layout.clone()))>
+                                        {label}
+                                    </button>
+                                }
+                            })
+                        }
+                        </div>
+                        </>
+                    }
+                }
+            }
+            </>
+        }
+    }
+
+    fn view_clock_settings(&self) -> Html {
+        let clock = &self.settings.clock;
+        let turns = vec![
+            ("No turn clock".to_string(), None),
+            ("30s turns".to_string(), Some(30)),
+            (
+                format!("{}s turns", DEFAULT_DRAW_TIME),
+                Some(DEFAULT_DRAW_TIME),
+            ),
+            ("3m turns".to_string(), Some(180)),
+        ];
+        let totals = vec![
+            ("No game clock", None),
+            ("5m games", Some(300)),
+            ("10m games", Some(600)),
+        ];
+        let actions = vec![
+            ("Random move", TimeoutAction::RandomMove),
+            ("Skip", TimeoutAction::Skip),
+            ("Forfeit", TimeoutAction::Forfeit),
+        ];
+        html! {
+            <>
+            <div class="buttons has-addons is-centered">
+            {
+                for turns.into_iter().map(|(label,turn)|{
+                    let selected = turn == clock.turn;
+                    let clock = TimeControl { turn, ..clock.clone() };
+                    html!{
+                        <button class=if selected {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeClock(clock.clone()))>
+                            {label}
+                        </button>
+                    }
+                })
+            }
+            </div>
+            <div class="buttons has-addons is-centered">
+            {
+                for totals.into_iter().map(|(label,total)|{
+                    let selected = total == clock.total;
+                    let clock = TimeControl { total, ..clock.clone() };
+                    html!{
+                        <button class=if selected {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeClock(clock.clone()))>
+                            {label}
+                        </button>
+                    }
+                })
+            }
+            </div>
+            {
+                if clock.is_off() {
+                    html!{}
+                } else {
+                    html!{
+                        <div class="buttons has-addons is-centered">
+                            <button class="button is-static">{"On timeout"}</button>
+                            {
+                                for actions.into_iter().map(|(label,on_timeout)|{
+                                    let selected = on_timeout == clock.on_timeout;
+                                    let clock = TimeControl { on_timeout, ..clock.clone() };
+                                    html!{
+                                        <button class=if selected {"button is-selected is-info"} else {"button"} onclick=self.link.callback(move |_|Msg::ChangeClock(clock.clone()))>
+                                            {label}
+                                        </button>
+                                    }
+                                })
+                            }
+                        </div>
+                    }
+                }
+            }
+            </>
+        }
+    }
+}
@@ -4,6 +4,8 @@ pub enum GameColors {
     SelectedCellColor,
     NearNeighbourColor,
     FarNeighbourColor,
+    HintCellColor,
+    BlockedCellColor,
 }
 
 impl From<GameColors> for String {
@@ -13,6 +15,8 @@ impl From<GameColors> for String {
             GameColors::SelectedCellColor => "var(--selectedCellColor)".into(),
             GameColors::NearNeighbourColor => "var(--nearNeighbourColor)".into(),
             GameColors::FarNeighbourColor => "var(--farNeighbourColor)".into(),
+            GameColors::HintCellColor => "var(--hintCellColor)".into(),
+            GameColors::BlockedCellColor => "var(--blockedCellColor)".into(),
         }
     }
 }
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use hexagon_shared::board::GameRecord;
+use log::error;
+
+use crate::{
+    ratings::Ratings,
+    storage::{MemoryStorage, Storage, Writer},
+};
+
+/// Finished games by id and the ratings they gave, shared by every lobby so
+/// games can be replayed after their lobby is gone.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    storage: Arc<dyn Storage>,
+    ratings: Ratings,
+    writer: Writer,
+    /// Games handed to the writer and not on disk yet.
+    pending: Arc<RwLock<HashMap<String, GameRecord>>>,
+}
+
+impl Default for Archive {
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryStorage::default()))
+    }
+}
+
+impl Archive {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        let writer = Writer::default();
+        Self {
+            ratings: Ratings::new(storage.clone(), writer.clone()),
+            storage,
+            writer,
+            pending: Default::default(),
+        }
+    }
+
+    pub fn ratings(&self) -> &Ratings {
+        &self.ratings
+    }
+
+    /// Stores a finished game in the background, it can be looked up
+    /// straight away.
+    pub fn store(&self, id: String, record: GameRecord) {
+        self.pending
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(id.clone(), record.clone());
+        let storage = self.storage.clone();
+        let pending = self.pending.clone();
+        self.writer.run(move || {
+            if let Err(err) = storage.save_game(&id, &record) {
+                error!("Cant store game {:#?} {:#?}", id, err);
+            }
+            pending
+                .write()
+                .unwrap_or_else(|err| err.into_inner())
+                .remove(&id);
+        });
+    }
+
+    pub fn get(&self, id: &str) -> Option<GameRecord> {
+        if let Some(record) = self
+            .pending
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(id)
+        {
+            return Some(record.clone());
+        }
+        match self.storage.load_game(id) {
+            Ok(record) => record,
+            Err(err) => {
+                error!("Cant load game {:#?} {:#?}", id, err);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use hexagon_shared::structures::{CloseCodes, Credentials, Identity};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::storage::{MemoryStorage, Storage};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Longest password in bytes, longer ones are refused before hashing.
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+pub const MAX_USERNAME_LENGTH: usize = 20;
+/// How long a session token is accepted after it was issued. Every
+/// connection gets a fresh one, so only players who stay away this long
+/// have to sign in again.
+const TOKEN_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Password checks for an account or from an address before they have to
+/// wait, see [`Attempts`].
+const FREE_ATTEMPTS: u32 = 3;
+/// Wait after the first attempt past the free ones, doubled for every one
+/// after that.
+const BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+/// Attempts are forgotten after this long without another one.
+const ATTEMPTS_KEPT: Duration = Duration::from_secs(60 * 60);
+
+/// Someone who signed up, kept by lowercase username.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    /// Argon2 hash in PHC format.
+    pub password_hash: String,
+}
+
+/// Issues and checks session tokens and keeps the accounts.
+#[derive(Clone)]
+pub struct Auth {
+    key: Arc<Vec<u8>>,
+    storage: Arc<dyn Storage>,
+    accounts: Arc<RwLock<HashMap<String, Account>>>,
+    attempts: Arc<Mutex<HashMap<String, Attempts>>>,
+}
+
+/// Password checks that count against an account or an address: failed
+/// logins for both and every new account for the address. Past
+/// [`FREE_ATTEMPTS`] the next check has to wait an exponential backoff.
+#[derive(Debug, Clone, Copy)]
+struct Attempts {
+    count: u32,
+    last: Instant,
+    until: Instant,
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("storage", &self.storage)
+            .finish()
+    }
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryStorage::default()))
+    }
+}
+
+impl Auth {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        let key = storage.session_key().unwrap_or_else(|err| {
+            error!(
+                "Cant load session key, tokens wont survive a restart {:#?}",
+                err
+            );
+            rand::random::<[u8; 32]>().to_vec()
+        });
+        let accounts = storage.load_accounts().unwrap_or_else(|err| {
+            error!("Cant load accounts {:#?}", err);
+            HashMap::new()
+        });
+        Self {
+            key: Arc::new(key),
+            storage,
+            accounts: Arc::new(RwLock::new(accounts)),
+            attempts: Arc::default(),
+        }
+    }
+
+    /// Checks `credentials` sent `from` an address and tells who they belong
+    /// to. Hashing passwords is slow, call it off the async threads.
+    pub fn identify(
+        &self,
+        credentials: Credentials,
+        from: Option<IpAddr>,
+    ) -> Result<Identity, CloseCodes> {
+        let address = from.map(|ip| format!("address {}", ip));
+        match credentials {
+            Credentials::Token(token) => {
+                let id = self
+                    .verify(&token, SystemTime::now())
+                    .ok_or(CloseCodes::InvalidSession)?;
+                let account = self.account_by_id(&id).map(|account| account.username);
+                Ok(self.identity(id, account))
+            }
+            Credentials::Guest => Ok(self.identity(Uuid::new_v4().to_simple().to_string(), None)),
+            Credentials::Login(username, password) => {
+                let user = format!("account {}", username.to_lowercase());
+                let keys: Vec<&str> = std::iter::once(user.as_str())
+                    .chain(address.as_deref())
+                    .collect();
+                self.check_attempts(&keys, Instant::now())?;
+                if password.len() > MAX_PASSWORD_LENGTH {
+                    self.attempted(&keys, Instant::now());
+                    return Err(CloseCodes::WrongPassword);
+                }
+                // Unknown names take as long as wrong passwords, so the time
+                // taken doesn't tell which accounts exist.
+                let account = self.account(&username);
+                let hash = match &account {
+                    Some(account) => account.password_hash.as_str(),
+                    None => dummy_hash(),
+                };
+                let verified = verify_password(&password, hash);
+                match account {
+                    Some(account) if verified => {
+                        self.forget_attempts(&user);
+                        Ok(self.identity(account.id, Some(account.username)))
+                    }
+                    _ => {
+                        self.attempted(&keys, Instant::now());
+                        Err(CloseCodes::WrongPassword)
+                    }
+                }
+            }
+            Credentials::Register(username, password) => {
+                let keys: Vec<&str> = address.as_deref().into_iter().collect();
+                self.check_attempts(&keys, Instant::now())?;
+                self.attempted(&keys, Instant::now());
+                let account = self.register(&username, &password)?;
+                Ok(self.identity(account.id, Some(account.username)))
+            }
+        }
+    }
+
+    /// Refuses a password check if any of `keys` is still waiting out its
+    /// backoff at `now`.
+    fn check_attempts(&self, keys: &[&str], now: Instant) -> Result<(), CloseCodes> {
+        let attempts = self.attempts.lock().unwrap_or_else(|err| err.into_inner());
+        if keys
+            .iter()
+            .filter_map(|key| attempts.get(*key))
+            .any(|attempt| attempt.until > now)
+        {
+            warn!("Too many password attempts for {:?}", keys);
+            return Err(CloseCodes::TooManyAttempts);
+        }
+        Ok(())
+    }
+
+    /// Counts an attempt against `keys` made at `now`.
+    fn attempted(&self, keys: &[&str], now: Instant) {
+        let attempts = &mut *self.attempts.lock().unwrap_or_else(|err| err.into_inner());
+        attempts.retain(|_, attempt| now.saturating_duration_since(attempt.last) < ATTEMPTS_KEPT);
+        for key in keys {
+            let attempt = attempts.entry(key.to_string()).or_insert(Attempts {
+                count: 0,
+                last: now,
+                until: now,
+            });
+            attempt.count += 1;
+            attempt.last = now;
+            if attempt.count > FREE_ATTEMPTS {
+                let doublings = (attempt.count - FREE_ATTEMPTS - 1).min(16);
+                attempt.until = now + (BACKOFF * 2u32.pow(doublings)).min(MAX_BACKOFF);
+            }
+        }
+    }
+
+    fn forget_attempts(&self, key: &str) {
+        let attempts = &mut *self.attempts.lock().unwrap_or_else(|err| err.into_inner());
+        attempts.remove(key);
+    }
+
+    fn identity(&self, id: String, account: Option<String>) -> Identity {
+        Identity {
+            token: self.sign(&id, SystemTime::now()),
+            id,
+            account,
+        }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("hmac takes keys of any length")
+    }
+
+    /// Token of `id` issued at `now`, the id and the issue time in seconds
+    /// followed by their signature.
+    fn sign(&self, id: &str, now: SystemTime) -> String {
+        let issued = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let payload = format!("{}.{}", id, issued);
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        format!("{}.{}", payload, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// Id a token was issued to, none if this server didn't sign it or it
+    /// is older than [`TOKEN_MAX_AGE`] at `now`.
+    fn verify(&self, token: &str, now: SystemTime) -> Option<String> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        let (id, issued) = payload.split_once('.')?;
+        let issued = UNIX_EPOCH + Duration::from_secs(issued.parse().ok()?);
+        match now.duration_since(issued) {
+            Ok(age) if age > TOKEN_MAX_AGE => None,
+            _ => Some(id.to_string()),
+        }
+    }
+
+    fn account(&self, username: &str) -> Option<Account> {
+        let accounts = self.accounts.read().unwrap_or_else(|err| err.into_inner());
+        accounts.get(&username.to_lowercase()).cloned()
+    }
+
+    fn account_by_id(&self, id: &str) -> Option<Account> {
+        let accounts = self.accounts.read().unwrap_or_else(|err| err.into_inner());
+        accounts.values().find(|account| account.id == id).cloned()
+    }
+
+    fn register(&self, username: &str, password: &str) -> Result<Account, CloseCodes> {
+        let valid_name = (3..=MAX_USERNAME_LENGTH).contains(&username.chars().count())
+            && username
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if !valid_name
+            || password.chars().count() < MIN_PASSWORD_LENGTH
+            || password.len() > MAX_PASSWORD_LENGTH
+        {
+            return Err(CloseCodes::InvalidAccount);
+        }
+        if self.account(username).is_some() {
+            return Err(CloseCodes::UsernameTaken);
+        }
+        let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+            .map_err(|_| CloseCodes::InvalidAccount)?;
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|_| CloseCodes::InvalidAccount)?
+            .to_string();
+        let account = Account {
+            id: Uuid::new_v4().to_simple().to_string(),
+            username: username.to_string(),
+            password_hash,
+        };
+        let accounts = &mut *self.accounts.write().unwrap_or_else(|err| err.into_inner());
+        let key = username.to_lowercase();
+        if accounts.contains_key(&key) {
+            return Err(CloseCodes::UsernameTaken);
+        }
+        accounts.insert(key, account.clone());
+        if let Err(err) = self.storage.save_accounts(accounts) {
+            error!("Cant store accounts {:#?}", err);
+        }
+        info!("Account {:#?} created", account.username);
+        Ok(account)
+    }
+}
+
+/// Hash of a password nobody has, checked against for unknown usernames.
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| {
+        let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).expect("valid salt");
+        Argon2::default()
+            .hash_password(&rand::random::<[u8; 16]>(), &salt)
+            .expect("hashing a random password")
+            .to_string()
+    })
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_and_accounts() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let auth = Auth::new(storage.clone());
+        let guest = auth.identify(Credentials::Guest, None).unwrap();
+        assert!(guest.account.is_none());
+        let again = auth.identify(Credentials::Token(guest.token.clone()), None);
+        assert_eq!(again.unwrap().id, guest.id);
+        let forged = format!("someone.{}", guest.token.split_once('.').unwrap().1);
+        assert!(auth.identify(Credentials::Token(forged), None).is_err());
+        let month_ago = SystemTime::now() - TOKEN_MAX_AGE - Duration::from_secs(60);
+        let expired = auth.sign(&guest.id, month_ago);
+        assert_eq!(
+            auth.identify(Credentials::Token(expired), None)
+                .unwrap_err(),
+            CloseCodes::InvalidSession
+        );
+        assert!(Auth::default()
+            .identify(Credentials::Token(guest.token), None)
+            .is_err());
+
+        let register = |name: &str, password: &str| {
+            auth.identify(Credentials::Register(name.into(), password.into()), None)
+        };
+        assert_eq!(
+            register("ann", "short").unwrap_err(),
+            CloseCodes::InvalidAccount
+        );
+        assert_eq!(
+            register("ann", &"x".repeat(MAX_PASSWORD_LENGTH + 1)).unwrap_err(),
+            CloseCodes::InvalidAccount
+        );
+        let ann = register("ann", "correct horse").unwrap();
+        assert_eq!(ann.account.as_deref(), Some("ann"));
+        assert_eq!(
+            register("Ann", "battery staple").unwrap_err(),
+            CloseCodes::UsernameTaken
+        );
+
+        let restarted = Auth::new(storage);
+        let login = |password: &str| {
+            restarted.identify(Credentials::Login("ANN".into(), password.into()), None)
+        };
+        assert_eq!(
+            login("wrong password").unwrap_err(),
+            CloseCodes::WrongPassword
+        );
+        assert_eq!(
+            restarted
+                .identify(
+                    Credentials::Login("nobody".into(), "correct horse".into()),
+                    None
+                )
+                .unwrap_err(),
+            CloseCodes::WrongPassword
+        );
+        assert_eq!(login("correct horse").unwrap().id, ann.id);
+        let resumed = restarted
+            .identify(Credentials::Token(ann.token), None)
+            .unwrap();
+        assert_eq!(resumed.account.as_deref(), Some("ann"));
+    }
+
+    #[test]
+    fn wrong_passwords_back_off() {
+        let auth = Auth::default();
+        let register = Credentials::Register("bob".into(), "correct horse".into());
+        auth.identify(register, None).unwrap();
+        let login = |password: &str, from: Option<IpAddr>| {
+            auth.identify(Credentials::Login("Bob".into(), password.into()), from)
+        };
+        let home: Option<IpAddr> = Some([10, 0, 0, 1].into());
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(login("guess", home).unwrap_err(), CloseCodes::WrongPassword);
+        }
+        assert_eq!(login("guess", home).unwrap_err(), CloseCodes::WrongPassword);
+        // The account waits now, even for the right password from elsewhere.
+        assert_eq!(
+            login("correct horse", None).unwrap_err(),
+            CloseCodes::TooManyAttempts
+        );
+        // So does the address, whichever account it tries.
+        let other = Credentials::Login("ann".into(), "anything".into());
+        assert_eq!(
+            auth.identify(other, home).unwrap_err(),
+            CloseCodes::TooManyAttempts
+        );
+
+        let later = Instant::now() + MAX_BACKOFF;
+        assert!(auth.check_attempts(&["account bob"], later).is_ok());
+    }
+}
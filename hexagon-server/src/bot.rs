@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use hexagon_shared::{
+    ai::{position_hash, PieceDifference, Searcher},
+    board::{Board, Outcome},
+    colors::colors::Color,
+    structures::{Difficulty, Move, Player, PlayerStatus, State},
+};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::structures::{Context, ServerLobby};
+
+pub const BOT_ID: &str = "computer";
+
+/// Delay before the bot answers so its moves don't appear instantly.
+const BOT_DELAY: Duration = Duration::from_millis(600);
+
+/// Positions the hard bot may visit per move, roughly a second of search.
+const HARD_NODE_LIMIT: u64 = 40_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bot {
+    pub color: Color,
+    pub difficulty: Difficulty,
+}
+
+impl Bot {
+    pub fn player(&self, lobbyid: &str) -> Player {
+        Player {
+            id: BOT_ID.to_string(),
+            name: format!("Computer ({:?})", self.difficulty),
+            status: PlayerStatus::JoinedLobby(lobbyid.to_string(), self.color),
+            away: false,
+        }
+    }
+
+    pub fn choose_move(&self, board: &Board) -> Option<Move> {
+        match self.difficulty {
+            Difficulty::Easy => Searcher::new(PieceDifference, 1).best_move(board),
+            Difficulty::Medium => Searcher::new(PieceDifference, 2).best_move(board),
+            Difficulty::Hard => Searcher::new(PieceDifference, 6)
+                .with_node_limit(HARD_NODE_LIMIT)
+                .best_move(board),
+        }
+    }
+}
+
+/// The game and a hash of its board, the bot only plays what it found if
+/// these are still the same once the search is done.
+fn position(lobby: &ServerLobby) -> (String, Option<u64>) {
+    let hash = match &lobby.state {
+        State::Game(board) => Some(position_hash(board)),
+        State::Lobby(_) => None,
+    };
+    (lobby.game_id.clone(), hash)
+}
+
+/// Plays the bot's turns in `lobbyid` until it is a human's turn again.
+pub async fn play_turns(context: Context, lobbyid: String) {
+    loop {
+        tokio::time::sleep(BOT_DELAY).await;
+        let (bot, board, searched) = {
+            let lobbies = &context.read().await.private_lobbies;
+            match lobbies.get(&lobbyid) {
+                Some(lobby) => match (&lobby.bot, &lobby.state) {
+                    (Some(bot), State::Game(board))
+                        if board.turn == bot.color && board.outcome() == Outcome::InProgress =>
+                    {
+                        (bot.clone(), board.clone(), position(lobby))
+                    }
+                    _ => return,
+                },
+                None => return,
+            }
+        };
+        let color = bot.color;
+        let mov = match tokio::task::spawn_blocking(move || bot.choose_move(&board)).await {
+            Ok(Some(mov)) => mov,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Bot search failed in lobby {:#?} {:#?}", lobbyid, e);
+                return;
+            }
+        };
+        let lobbies = &mut context.write().await.private_lobbies;
+        if let Some(lobby) = lobbies.get_mut(&lobbyid) {
+            if position(lobby) != searched {
+                debug!("Bot dropped a move for an old position in {:#?}", lobbyid);
+                return;
+            }
+            debug!("Bot {:#?} plays {:#?} in lobby {:#?}", color, mov, lobbyid);
+            if !lobby.play_move(color, &mov) {
+                return;
+            }
+        } else {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_difficulty_finds_a_legal_move() {
+        let board = Board::generate_hexagon(4, Color::Red, Color::Blue);
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard].iter() {
+            let bot = Bot {
+                color: Color::Red,
+                difficulty: *difficulty,
+            };
+            let mov = bot.choose_move(&board).expect("no move found");
+            assert!(board.is_move_legal(&mov));
+        }
+    }
+}
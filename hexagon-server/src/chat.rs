@@ -0,0 +1,111 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use hexagon_shared::structures::{ChatError, ChatMessage, Player, CHAT_HISTORY, MAX_CHAT_LENGTH};
+
+/// Messages a player may send within [`CHAT_WINDOW`].
+const CHAT_BURST: usize = 5;
+const CHAT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Chat of a lobby, keeps the recent messages and how often everyone sent.
+#[derive(Debug, Clone, Default)]
+pub struct Chat {
+    history: VecDeque<ChatMessage>,
+    sent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl Chat {
+    /// Chat carrying on with `history`, like after a restart.
+    pub fn restore(history: Vec<ChatMessage>) -> Self {
+        Self {
+            history: history.into(),
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Checks the message of `from` against the limits and keeps it.
+    pub fn post(
+        &mut self,
+        from: &Player,
+        text: &str,
+        now: Instant,
+    ) -> Result<ChatMessage, ChatError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ChatError::Empty);
+        }
+        let len = text.chars().count();
+        if len > MAX_CHAT_LENGTH {
+            return Err(ChatError::TooLong(len));
+        }
+        let sent = self.sent.entry(from.id.clone()).or_default();
+        while sent
+            .front()
+            .is_some_and(|time| now.saturating_duration_since(*time) >= CHAT_WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= CHAT_BURST {
+            let wait = CHAT_WINDOW.saturating_sub(now.saturating_duration_since(sent[0]));
+            return Err(ChatError::RateLimited(wait.as_secs() as u32 + 1));
+        }
+        sent.push_back(now);
+
+        let message = ChatMessage {
+            from: from.id.clone(),
+            name: from.name.clone(),
+            text: text.to_string(),
+        };
+        self.history.push_back(message.clone());
+        if self.history.len() > CHAT_HISTORY {
+            self.history.pop_front();
+        }
+        Ok(message)
+    }
+
+    pub fn history(&self) -> Vec<ChatMessage> {
+        self.history.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexagon_shared::structures::PlayerStatus;
+
+    #[test]
+    fn chat_enforces_limits_and_keeps_recent_history() {
+        let player = Player {
+            id: "a".into(),
+            name: "A".into(),
+            status: PlayerStatus::Initiated,
+            away: false,
+        };
+        let mut chat = Chat::default();
+        let start = Instant::now();
+        assert_eq!(chat.post(&player, "  ", start), Err(ChatError::Empty));
+        let long = "x".repeat(MAX_CHAT_LENGTH + 1);
+        assert_eq!(
+            chat.post(&player, &long, start),
+            Err(ChatError::TooLong(MAX_CHAT_LENGTH + 1))
+        );
+        for i in 0..CHAT_BURST {
+            assert!(chat.post(&player, &i.to_string(), start).is_ok());
+        }
+        assert_eq!(
+            chat.post(&player, "more", start + Duration::from_secs(3)),
+            Err(ChatError::RateLimited(8))
+        );
+
+        let mut now = start;
+        for i in 0..CHAT_HISTORY {
+            now += CHAT_WINDOW;
+            chat.post(&player, &format!("later {}", i), now).unwrap();
+        }
+        let history = chat.history();
+        assert_eq!(history.len(), CHAT_HISTORY);
+        assert_eq!(history[0].text, "later 0");
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use hexagon_shared::{
+    board::Outcome,
+    colors::colors::Color,
+    structures::{PlayerClock, State, TimeControl},
+};
+use log::debug;
+
+use crate::{bot, structures::Context};
+
+/// How often clocks are checked and sent to the players.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Chess clock of a running game, charges the player to move for the time
+/// since their turn started.
+#[derive(Debug, Clone)]
+pub struct GameClock {
+    pub control: TimeControl,
+    /// When the game started, tells the clocks of rematches apart.
+    pub started: Instant,
+    total: HashMap<Color, Duration>,
+    turn: Color,
+    turn_started: Instant,
+}
+
+impl GameClock {
+    pub fn new(control: TimeControl, players: &[Color], turn: Color, now: Instant) -> Self {
+        let total = control
+            .total
+            .map(|secs| {
+                players
+                    .iter()
+                    .map(|color| (*color, Duration::from_secs(secs as u64)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            control,
+            started: now,
+            total,
+            turn,
+            turn_started: now,
+        }
+    }
+
+    /// Stops the clock of the player to move and starts the one of `turn`.
+    pub fn switch(&mut self, turn: Color, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.turn_started);
+        if let Some(left) = self.total.get_mut(&self.turn) {
+            *left = left.saturating_sub(elapsed);
+        }
+        self.turn = turn;
+        self.turn_started = now;
+    }
+
+    fn turn_left(&self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.turn_started);
+        self.control
+            .turn
+            .map(|secs| Duration::from_secs(secs as u64).saturating_sub(elapsed))
+    }
+
+    fn total_left(&self, color: Color, now: Instant) -> Option<Duration> {
+        let left = *self.total.get(&color)?;
+        if color == self.turn {
+            Some(left.saturating_sub(now.saturating_duration_since(self.turn_started)))
+        } else {
+            Some(left)
+        }
+    }
+
+    /// Whether the player to move ran out of time.
+    pub fn expired(&self, now: Instant) -> bool {
+        self.turn_left(now) == Some(Duration::ZERO)
+            || self.total_left(self.turn, now) == Some(Duration::ZERO)
+    }
+
+    /// Time left for every player, in whole seconds rounded up.
+    pub fn remaining(&self, players: &[Color], now: Instant) -> Vec<PlayerClock> {
+        let secs = |left: Duration| (left.as_millis() as u32).div_ceil(1000);
+        players
+            .iter()
+            .map(|color| PlayerClock {
+                color: *color,
+                turn: self.control.turn.map(|full| {
+                    if *color == self.turn {
+                        self.turn_left(now).map_or(full, secs)
+                    } else {
+                        full
+                    }
+                }),
+                total: self.total_left(*color, now).map(secs),
+            })
+            .collect()
+    }
+}
+
+/// Runs the clock of the game in `lobbyid` started at `started` until the
+/// game ends.
+pub async fn run(context: Context, lobbyid: String, started: Instant) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        let lobbies = &mut context.write().await.private_lobbies;
+        let lobby = match lobbies.get_mut(&lobbyid) {
+            Some(lobby) => lobby,
+            None => return,
+        };
+        match (&lobby.clock, &lobby.state) {
+            (Some(clock), State::Game(board))
+                if clock.started == started && board.outcome() == Outcome::InProgress => {}
+            _ => {
+                debug!("Clock stopped in lobby {:#?}", lobbyid);
+                return;
+            }
+        }
+        if lobby.tick_clock(Instant::now()) && lobby.bot.is_some() {
+            tokio::spawn(bot::play_turns(context.clone(), lobbyid.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexagon_shared::structures::TimeoutAction;
+
+    #[test]
+    fn clock_charges_only_the_player_to_move() {
+        let control = TimeControl {
+            turn: Some(10),
+            total: Some(60),
+            on_timeout: TimeoutAction::Skip,
+        };
+        let players = [Color::Red, Color::Blue];
+        let start = Instant::now();
+        let mut clock = GameClock::new(control, &players, Color::Red, start);
+        let later = start + Duration::from_millis(4500);
+        assert_eq!(
+            clock.remaining(&players, later),
+            vec![
+                PlayerClock {
+                    color: Color::Red,
+                    turn: Some(6),
+                    total: Some(56),
+                },
+                PlayerClock {
+                    color: Color::Blue,
+                    turn: Some(10),
+                    total: Some(60),
+                },
+            ]
+        );
+        clock.switch(Color::Blue, later);
+        assert!(!clock.expired(later + Duration::from_secs(9)));
+        assert!(clock.expired(later + Duration::from_secs(10)));
+        assert_eq!(
+            clock.remaining(&players, later)[0].total,
+            Some(56),
+            "stopped clock keeps its time"
+        );
+    }
+}
@@ -1,54 +1,267 @@
+pub mod archive;
+pub mod auth;
+pub mod bot;
+pub mod chat;
+pub mod clock;
+pub mod maps;
+pub mod matchmaking;
+pub mod ratings;
+pub mod storage;
 pub mod structures;
+#[cfg(test)]
+mod testing;
 use hexagon_shared::{
     colors::colors::Color,
-    structures::{CloseCodes, Lobby, PlayerMessage, PlayerStatus, SocketMessage, State},
+    structures::{
+        CloseCodes, Credentials, GameSettings, GameType, Lobby, Player, PlayerMessage,
+        PlayerStatus, ProtocolError, SettingsError, SocketMessage, State, TeamMode, PLAYER_COLORS,
+    },
 };
-use std::collections::HashMap;
-use std::sync::Arc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use warp::filters::ws::{Message, WebSocket, Ws};
 use warp::Filter;
 
 use futures_util::future::FutureExt;
+use futures_util::stream::SplitStream;
 use futures_util::stream::StreamExt;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
-use tokio::sync::RwLock;
-
-use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedSender},
+    oneshot,
+};
 
-use log::{debug, error, info, log, trace, warn};
-use pretty_env_logger;
+use log::{debug, error, info, warn};
+use matchmaking::Seeker;
+use storage::{LobbySnapshot, Storage};
 use structures::*;
 
+/// How long a new connection has to identify itself.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an identified connection may take to pick a lobby, also while
+/// browsing them.
+const LOBBY_CHOICE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Silence after which a connection in a lobby or the match queue counts
+/// as dropped. Clients
+/// ping every second but browsers slow timers in background tabs down to
+/// once a minute, so these leave room for a few missed pings.
+const LOBBY_IDLE_TIMEOUT: Duration = Duration::from_secs(180);
+const GAME_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long the seat of a player whose connection dropped is held.
+const RECONNECT_GRACE: Duration = Duration::from_secs(60);
+/// How often people browsing lobbies get the changes.
+const LISTING_INTERVAL: Duration = Duration::from_secs(2);
+/// How often running games are saved to survive a restart.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
 
     let wsf = warp::ws();
-    let context = Context::default();
+    let storage = storage::open();
+    let context = Context::new(tokio::sync::RwLock::new(Lobbies {
+        maps: maps::load_maps(),
+        archive: archive::Archive::new(storage.clone()),
+        auth: auth::Auth::new(storage.clone()),
+        ..Default::default()
+    }));
+    restore_lobbies(&context, storage.as_ref()).await;
+    tokio::spawn(snapshot_lobbies(context.clone(), storage));
+    tokio::spawn(stream_listing(context.clone()));
     let with_context = warp::any().map(move || context.clone());
 
     let logg = warp::log("WARP");
 
+    let games = warp::path!("games" / String)
+        .and(warp::get())
+        .and(with_context.clone())
+        .and_then(game_record)
+        .with(warp::cors().allow_any_origin());
+
+    let leaderboard = warp::path!("leaderboard")
+        .and(warp::get())
+        .and(with_context.clone())
+        .and_then(leaderboard)
+        .with(warp::cors().allow_any_origin());
+
     let wshandle = wsf
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("x-forwarded-for"))
         .and(with_context)
-        .map(|ws: Ws, context| ws.on_upgrade(move |socket| user_connected(socket, context)))
+        .map(
+            |ws: Ws, remote: Option<SocketAddr>, forwarded: Option<String>, context| {
+                let from = client_address(remote, forwarded.as_deref());
+                ws.on_upgrade(move |socket| user_connected(socket, context, from))
+            },
+        )
         .with(logg);
 
-    warp::serve(wshandle)
+    warp::serve(games.or(leaderboard).or(wshandle))
         .run((
             [0, 0, 0, 0],
             std::env::var("PORT")
-                .unwrap_or("3012".to_owned())
+                .unwrap_or_else(|_| "3012".to_owned())
                 .parse()
                 .unwrap(),
         ))
         .await;
 }
 
-async fn user_connected(websocket: WebSocket, context: Context) {
+/// Brings back the games running when the server last saved them.
+async fn restore_lobbies(context: &Context, storage: &dyn Storage) {
+    let snapshots = match storage.load_lobbies() {
+        Ok(snapshots) => snapshots,
+        Err(err) => {
+            error!("Cant load saved lobbies {:#?}", err);
+            return;
+        }
+    };
+    let lobbies = &mut *context.write().await;
+    for snapshot in snapshots {
+        let lobby = ServerLobby::restore(snapshot, lobbies.archive.clone());
+        for player in lobby.players.values() {
+            if let Some(since) = player.away_since {
+                tokio::spawn(remove_after_grace(
+                    context.clone(),
+                    lobby.id.clone(),
+                    player.id.clone(),
+                    since,
+                ));
+            }
+        }
+        spawn_game_tasks(context, &lobby.id, &lobby);
+        lobbies.private_lobbies.insert(lobby.id.clone(), lobby);
+    }
+    info!("Restored {} lobbies", lobbies.private_lobbies.len());
+}
+
+/// Saves the running games every [`SNAPSHOT_INTERVAL`].
+async fn snapshot_lobbies(context: Context, storage: Arc<dyn Storage>) {
+    let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+    loop {
+        interval.tick().await;
+        let snapshots: Vec<LobbySnapshot> = context
+            .read()
+            .await
+            .private_lobbies
+            .values()
+            .filter(|lobby| matches!(lobby.state, State::Game(_)) && lobby.finished.is_none())
+            .map(ServerLobby::snapshot)
+            .collect();
+        if let Err(err) = storage.save_lobbies(&snapshots) {
+            error!("Cant save lobbies {:#?}", err);
+        }
+    }
+}
+
+/// A finished game in the text format of [`GameRecord`], for replays.
+///
+/// [`GameRecord`]: hexagon_shared::board::GameRecord
+async fn game_record(id: String, context: Context) -> Result<impl warp::Reply, warp::Rejection> {
+    match context.read().await.archive.get(&id) {
+        Some(record) => Ok(record.to_text()),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// Best rated players as json.
+async fn leaderboard(context: Context) -> Result<impl warp::Reply, warp::Rejection> {
+    let ratings = context.read().await.archive.ratings().leaderboard();
+    Ok(warp::reply::json(&ratings))
+}
+
+/// Where a connection is in the protocol, each stage takes its own messages
+/// and has its own timeout.
+#[derive(Debug)]
+enum Stage {
+    /// Waiting for [`PlayerMessage::Initialize`].
+    Connected,
+    /// Knows who the player is, waiting for them to pick a lobby. They get
+    /// the public lobbies while `browsing`.
+    Identified {
+        player: ServerPlayer,
+        browsing: bool,
+    },
+    /// Seated or watching in a lobby before its game started.
+    InLobby {
+        player: ServerPlayer,
+        lobbyid: String,
+    },
+    /// In a lobby whose game runs or is over.
+    InGame {
+        player: ServerPlayer,
+        lobbyid: String,
+    },
+    /// The connection is being closed.
+    Closed,
+}
+
+impl Stage {
+    /// Longest wait for the next message before the connection is dropped.
+    fn timeout(&self) -> Duration {
+        match self {
+            Stage::Connected => HANDSHAKE_TIMEOUT,
+            Stage::Identified { .. } => LOBBY_CHOICE_TIMEOUT,
+            Stage::InLobby { .. } => LOBBY_IDLE_TIMEOUT,
+            Stage::InGame { .. } => GAME_IDLE_TIMEOUT,
+            Stage::Closed => Duration::from_secs(0),
+        }
+    }
+
+    /// Cleans up after the connection is gone, seats in a lobby are held
+    /// like for any dropped connection.
+    async fn disconnect(self, context: &Context) {
+        match self {
+            Stage::Identified {
+                player,
+                browsing: true,
+            } => {
+                context.write().await.browsers.remove(&player.session);
+            }
+            Stage::InLobby { player, lobbyid } | Stage::InGame { player, lobbyid } => {
+                player_disconnect(&player, &lobbyid, context).await;
+            }
+            _ => {}
+        }
+    }
+}
+
+type Outgoing = UnboundedSender<Result<Message, warp::Error>>;
+
+/// Sends to a connection that may not have a player yet.
+fn send(tx: &Outgoing, message: &SocketMessage) {
+    match serde_json::to_string(message) {
+        Ok(text) => {
+            if let Err(e) = tx.send(Ok(Message::text(text))) {
+                warn!("Cant send message {:#?}", e);
+            }
+        }
+        Err(err) => error!("Cant serialize {:#?} error {:#?}", message, err),
+    }
+}
+
+fn close(tx: &Outgoing, code: CloseCodes) {
+    if let Err(e) = tx.send(Ok(Message::close_with(code.to_code(), code.to_string()))) {
+        error!("Cant close connection {:#?}", e);
+    }
+}
+
+/// Address a connection came from, the first one a proxy forwarded it for
+/// when it came through one.
+fn client_address(remote: Option<SocketAddr>, forwarded: Option<&str>) -> Option<IpAddr> {
+    forwarded
+        .and_then(|header| header.split(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+        .or_else(|| remote.map(|addr| addr.ip()))
+}
+
+async fn user_connected(websocket: WebSocket, context: Context, from: Option<IpAddr>) {
     info!("Websocket Connection Received");
-    println!("Websocket Connection Received");
     let (ws_tx, mut ws_rx) = websocket.split();
 
     let (tx, rx) = unbounded_channel();
@@ -59,215 +272,563 @@ async fn user_connected(websocket: WebSocket, context: Context) {
         }
     }));
 
-    let mut player: Option<ServerPlayer> = None;
-
-    // TODO: Add timer for closing
-    // let timeoutfuture = tokio::time::delay_for(std::time::Duration::from_secs(3));
-
-    if let Some(result) = ws_rx.next().await {
-        match result {
-            Ok(msg) => {
-                if let Ok(msg)= msg.to_str() {
-                    match serde_json::from_str(msg) {
-                        Ok(message) => match message {
-                            PlayerMessage::Initialize(id, name) => {
-                                info!("Intialize player id {:#} name {:#?}", id, name);
-                                player = Some(ServerPlayer {
-                                    id,
-                                    name,
-                                    send_channel: tx.clone(),
-                                    status: PlayerStatus::Initiated,
-                                });
-                            }
-                            _ => {
-                                warn!(
-                                    "First message not initialize, closing connection {:#?}",
-                                    msg
-                                );
-                                if let Err(e) = tx.send(Ok(Message::close_with(
-                                    CloseCodes::WrongInit as u8,
-                                    CloseCodes::WrongInit.to_string(),
-                                ))) {
-                                    error!("Cant close connection {:#?}", e);
-                                }
-                            }
-                        },
-                        Err(err) => {
-                            debug!("Received message is incorrect format, {:#}", err);
-                            if let Err(e) = tx.send(Ok(Message::close_with(
-                                CloseCodes::WrongInit as u8,
-                                CloseCodes::WrongInit.to_string(),
-                            ))) {
-                                error!("Cant close connection {:#?}", e);
-                            }
-                        }
-                    }
-                } else {
-                    error!("Binary not supported {:#?}", msg);
-                    if let Err(e) = tx.send(Ok(Message::close_with(
-                        CloseCodes::WrongInit as u8,
-                        CloseCodes::WrongInit.to_string(),
-                    ))) {
-                        error!("Cant close connection {:#?}", e);
-                    }
+    let mut stage = Stage::Connected;
+    loop {
+        let message = match tokio::time::timeout(stage.timeout(), ws_rx.next()).await {
+            Ok(Some(Ok(message))) if !message.is_close() => message,
+            Ok(Some(Err(e))) => {
+                warn!("Websocket error {:#?}", e);
+                break;
+            }
+            Ok(_) => break,
+            Err(_) => {
+                info!("Connection timed out {:#?}", stage);
+                send(&tx, &SocketMessage::ProtocolError(ProtocolError::TimedOut));
+                close(&tx, CloseCodes::TimedOut);
+                break;
+            }
+        };
+        if message.is_ping() || message.is_pong() {
+            continue;
+        }
+        let message = match message.to_str().map(serde_json::from_str::<PlayerMessage>) {
+            Ok(Ok(message)) => message,
+            _ => {
+                debug!("Received message is incorrect format {:#?}", message);
+                send(&tx, &SocketMessage::ProtocolError(ProtocolError::Malformed));
+                if let Stage::Connected = stage {
+                    close(&tx, CloseCodes::WrongInit);
+                    break;
+                }
+                continue;
+            }
+        };
+        stage = match stage {
+            Stage::Connected => match message {
+                PlayerMessage::Initialize(credentials, name) => {
+                    identify(credentials, name, from, &tx, &context).await
+                }
+                message => {
+                    warn!(
+                        "First message not initialize, closing connection {:#?}",
+                        message
+                    );
+                    send(
+                        &tx,
+                        &SocketMessage::ProtocolError(ProtocolError::NotIdentified),
+                    );
+                    close(&tx, CloseCodes::WrongInit);
+                    Stage::Closed
                 }
+            },
+            Stage::Identified { player, browsing } => {
+                choose_lobby(player, browsing, message, from, &tx, &context, &mut ws_rx).await
             }
-            Err(e) => {
-                warn!("Websocket error {:#?} player : {:#?}", e, player);
+            Stage::InLobby { player, lobbyid } | Stage::InGame { player, lobbyid } => {
+                in_lobby(player, lobbyid, message, &context).await
             }
+            Stage::Closed => Stage::Closed,
         };
+        if let Stage::Closed = stage {
+            break;
+        }
     }
+    stage.disconnect(&context).await;
+}
 
-    match &mut player {
-        Some(player) => {
-            if let Some(result) = ws_rx.next().await {
-                match result {
-                    Ok(msg) => {
-                        if let Ok(msg)=msg.to_str() {
-                            match serde_json::from_str(msg) {
-                                Ok(message) => match message {
-                                    PlayerMessage::CreateLobby => {
-                                        use rand::{distributions::Alphanumeric, Rng};
-                                        let lobbyid: String = {
-                                            rand::thread_rng()
-                                                .sample_iter(&Alphanumeric)
-                                                .take(5)
-                                                .map(char::from)
-                                                .collect()
-                                        };
-                                        let privatelobbies =
-                                            &mut context.write().await.private_lobbies;
-                                        if let Some(_lob) = privatelobbies.get(&lobbyid) {
-                                            error!(
-                                                "Lobby exist with id {:#?} {:#?}, returning error",
-                                                lobbyid, _lob
-                                            );
-                                            player.close(CloseCodes::CantCreateLobby);
-                                        } else {
-                                            player.status = PlayerStatus::JoinedLobby(
-                                                lobbyid.clone(),
-                                                Color::Red,
-                                            );
-                                            let lobby = ServerLobby::new_with_player(
-                                                lobbyid.clone(),
-                                                player.clone(),
-                                            );
-                                            privatelobbies.insert(lobbyid, lobby.clone());
-
-                                            info!("Player {:#?} joined lobby {:#?}", player, lobby);
-                                            player.send(SocketMessage::LobbyJoined(
-                                                lobby.into(),
-                                                Color::Red,
-                                            ));
-                                        }
-                                    }
-                                    PlayerMessage::JoinLobby(lobbyid) => {
-                                        let privatelobbies =
-                                            &mut context.write().await.private_lobbies;
-                                        if let Some(lobby) = privatelobbies.get_mut(&lobbyid) {
-                                            if let Some(color) =
-                                                Lobby::from(lobby.clone()).get_available_color()
-                                            {
-                                                player.status = PlayerStatus::JoinedLobby(
-                                                    lobby.id.clone(),
-                                                    color.clone(),
-                                                );
-                                                lobby.add_player(player.clone());
-                                                info!(
-                                                    "Player {:#?} joined lobby {:#?}",
-                                                    player, lobby
-                                                );
-                                                player.send(SocketMessage::LobbyJoined(
-                                                    lobby.clone().into(),
-                                                    color,
-                                                ));
-                                            } else {
-                                                player.close(CloseCodes::LobbyFull)
-                                            }
-                                        } else {
-                                            player.close(CloseCodes::CantJoinLobbyDoestExist)
-                                        }
-                                    }
-                                    _ => {}
-                                },
-                                Err(e) => {
-                                    log::warn!("Message is not player message {:#?}", e);
-                                }
-                            }
-                        } else {
-                            error!("Not Text message {:#?}", msg)
-                        }
-                    }
-                    Err(e) => {
-                        log::warn!("Websocket error {:#?}", e);
-                    }
+/// Checks the credentials of a connection, it is closed if they are wrong.
+async fn identify(
+    credentials: Credentials,
+    name: String,
+    from: Option<IpAddr>,
+    tx: &Outgoing,
+    context: &Context,
+) -> Stage {
+    let auth = context.read().await.auth.clone();
+    let identified = tokio::task::spawn_blocking(move || auth.identify(credentials, from))
+        .await
+        .unwrap_or(Err(CloseCodes::WrongInit));
+    match identified {
+        Ok(identity) => {
+            let name = match identity
+                .account
+                .clone()
+                .or_else(|| Player::display_name(&name))
+            {
+                Some(name) => name,
+                None => {
+                    warn!("Player {:#?} has no usable name", identity.id);
+                    close(tx, CloseCodes::WrongInit);
+                    return Stage::Closed;
                 }
+            };
+            info!("Intialize player id {:#} name {:#?}", identity.id, name);
+            let player = ServerPlayer {
+                id: identity.id.clone(),
+                name,
+                send_channel: tx.clone(),
+                status: PlayerStatus::Initiated,
+                session: uuid::Uuid::new_v4(),
+                away_since: None,
+            };
+            player.send(SocketMessage::Identified(identity));
+            Stage::Identified {
+                player,
+                browsing: false,
             }
         }
+        Err(code) => {
+            warn!("Player not identified {:#?}", code);
+            close(tx, code);
+            Stage::Closed
+        }
+    }
+}
+
+/// Handles a message of a player who is not in a lobby yet, they can browse
+/// the public lobbies, look for a match or create, join or rejoin one.
+async fn choose_lobby(
+    mut player: ServerPlayer,
+    browsing: bool,
+    message: PlayerMessage,
+    from: Option<IpAddr>,
+    tx: &Outgoing,
+    context: &Context,
+    ws_rx: &mut SplitStream<WebSocket>,
+) -> Stage {
+    let picks_lobby = matches!(
+        message,
+        PlayerMessage::Initialize(..)
+            | PlayerMessage::CreateLobby
+            | PlayerMessage::JoinLobby(_)
+            | PlayerMessage::Rejoin(_)
+            | PlayerMessage::FindMatch(_)
+    );
+    if browsing && picks_lobby {
+        context.write().await.browsers.remove(&player.session);
+    }
+    match message {
+        PlayerMessage::Ping => player.send(SocketMessage::Pong),
+        PlayerMessage::ListLobbies => {
+            let lobbies = &mut *context.write().await;
+            player.send(SocketMessage::Lobbies(lobbies.public_lobbies()));
+            lobbies.browsers.insert(player.session, player.clone());
+            return Stage::Identified {
+                player,
+                browsing: true,
+            };
+        }
+        PlayerMessage::Leaderboard => {
+            let ratings = context.read().await.archive.ratings().leaderboard();
+            player.send(SocketMessage::Leaderboard(ratings));
+        }
+        PlayerMessage::Initialize(credentials, name) => {
+            return identify(credentials, name, from, tx, context).await;
+        }
+        PlayerMessage::CreateLobby => {
+            create_lobby(&mut player, context).await;
+            return lobby_stage(player, context).await;
+        }
+        PlayerMessage::JoinLobby(lobbyid) => {
+            join_lobby(&mut player, &lobbyid, context).await;
+            return lobby_stage(player, context).await;
+        }
+        PlayerMessage::Rejoin(lobbyid) => {
+            rejoin_lobby(&mut player, &lobbyid, context).await;
+            return lobby_stage(player, context).await;
+        }
+        PlayerMessage::FindMatch(settings) => {
+            match find_match(&player, settings, context, ws_rx).await {
+                Ok(Some((lobbyid, color))) => {
+                    player.status = PlayerStatus::JoinedLobby(lobbyid, color);
+                    return lobby_stage(player, context).await;
+                }
+                Ok(None) => return Stage::Closed,
+                Err(err) => player.send(SocketMessage::SettingsRejected(err)),
+            }
+        }
+        message => {
+            warn!("Player {:#?} not in a lobby sent {:#?}", player.id, message);
+            player.send(SocketMessage::ProtocolError(ProtocolError::NotInLobby));
+        }
+    }
+    Stage::Identified { player, browsing }
+}
+
+/// Handles a message of a player in the lobby `lobbyid`.
+async fn in_lobby(
+    mut player: ServerPlayer,
+    lobbyid: String,
+    message: PlayerMessage,
+    context: &Context,
+) -> Stage {
+    match message {
+        PlayerMessage::LeaveLobby => {
+            leave_lobby(&mut player, &lobbyid, context).await;
+            Stage::Identified {
+                player,
+                browsing: false,
+            }
+        }
+        PlayerMessage::Initialize(..)
+        | PlayerMessage::CreateLobby
+        | PlayerMessage::JoinLobby(_)
+        | PlayerMessage::Rejoin(_)
+        | PlayerMessage::FindMatch(_)
+        | PlayerMessage::ListLobbies => {
+            warn!(
+                "Player {:#?} in lobby {:#?} sent {:#?}",
+                player.id, lobbyid, message
+            );
+            player.send(SocketMessage::ProtocolError(ProtocolError::AlreadyInLobby));
+            lobby_stage(player, context).await
+        }
+        message => {
+            player_message(&player.id, &lobbyid, context, message).await;
+            lobby_stage(player, context).await
+        }
+    }
+}
+
+/// Stage of a player after joining or acting in a lobby, back to choosing
+/// one if theirs is gone.
+async fn lobby_stage(mut player: ServerPlayer, context: &Context) -> Stage {
+    let lobbyid = match &player.status {
+        PlayerStatus::JoinedLobby(lobbyid, _) | PlayerStatus::Spectating(lobbyid) => {
+            lobbyid.clone()
+        }
+        PlayerStatus::Initiated => {
+            return Stage::Identified {
+                player,
+                browsing: false,
+            }
+        }
+    };
+    match context
+        .read()
+        .await
+        .private_lobbies
+        .get(&lobbyid)
+        .map(|l| &l.state)
+    {
+        Some(State::Game(_)) => Stage::InGame { player, lobbyid },
+        Some(State::Lobby(_)) => Stage::InLobby { player, lobbyid },
         None => {
-            warn!("Player not initialized");
-            if let Err(e) = tx.send(Ok(Message::close_with(
-                CloseCodes::WrongInit as u8,
-                CloseCodes::WrongInit.to_string(),
-            ))) {
-                error!("Cant close connection {:#?}", e);
+            player.status = PlayerStatus::Initiated;
+            Stage::Identified {
+                player,
+                browsing: false,
             }
         }
     }
+}
+
+async fn create_lobby(player: &mut ServerPlayer, context: &Context) {
+    let lobbies = &mut *context.write().await;
+    let lobbyid = lobbies.new_lobby_id();
+    player.status = PlayerStatus::JoinedLobby(lobbyid.clone(), PLAYER_COLORS[0]);
+    let lobby =
+        ServerLobby::new_with_player(lobbyid.clone(), player.clone(), lobbies.archive.clone());
+    lobbies.private_lobbies.insert(lobbyid, lobby.clone());
 
-    if let Some(player) = player {
-        if let PlayerStatus::JoinedLobby(lobbyid, _) = player.status {
-            let messageblock = websocket_msg(&player.id, &lobbyid, &context, ws_rx);
+    info!("Player {:#?} joined lobby {:#?}", player, lobby);
+    player.send(SocketMessage::LobbyJoined(lobby.into(), PLAYER_COLORS[0]));
+}
+
+async fn rejoin_lobby(player: &mut ServerPlayer, lobbyid: &str, context: &Context) {
+    let privatelobbies = &mut context.write().await.private_lobbies;
+    match privatelobbies.get_mut(lobbyid) {
+        Some(lobby) => match lobby.rejoin(player) {
+            Some(color) => {
+                info!("Player {:#?} rejoined lobby {:#?}", player.id, lobby.id);
+                player.send(SocketMessage::LobbyJoined(lobby.clone().into(), color));
+            }
+            None => lobby.add_spectator(player),
+        },
+        None => player.close(CloseCodes::CantJoinLobbyDoestExist),
+    }
+}
 
-            messageblock.await;
+async fn join_lobby(player: &mut ServerPlayer, lobbyid: &str, context: &Context) {
+    let privatelobbies = &mut context.write().await.private_lobbies;
+    if let Some(lobby) = privatelobbies.get_mut(lobbyid) {
+        if let Some(color) = lobby.rejoin(player) {
+            info!("Player {:#?} rejoined lobby {:#?}", player.id, lobby.id);
+            player.send(SocketMessage::LobbyJoined(lobby.clone().into(), color));
+        } else if let (State::Lobby(_), Some(color)) = (
+            &lobby.state,
+            Lobby::from(lobby.clone()).get_available_color(),
+        ) {
+            player.status = PlayerStatus::JoinedLobby(lobby.id.clone(), color);
+            lobby.add_player(player.clone());
+            info!("Player {:#?} joined lobby {:#?}", player, lobby);
+            player.send(SocketMessage::LobbyJoined(lobby.clone().into(), color));
+        } else {
+            info!("Player {:#?} watches lobby {:#?}", player.id, lobby.id);
+            lobby.add_spectator(player);
         }
+    } else {
+        player.close(CloseCodes::CantJoinLobbyDoestExist)
     }
 }
 
-async fn player_message(player_id: &str, lobbyid: &str, context: &Context, message: PlayerMessage) {
+/// Takes `player` out of their lobby for good, the lobby goes once nobody
+/// is left in it.
+async fn leave_lobby(player: &mut ServerPlayer, lobbyid: &str, context: &Context) {
     let lobbies = &mut context.write().await.private_lobbies;
     if let Some(lobby) = lobbies.get_mut(lobbyid) {
-        let colors = lobby
-            .players
-            .iter()
-            .map(|(id, p)| {
-                if let PlayerStatus::JoinedLobby(_, c) = p.status {
-                    Some(c)
-                } else {
-                    None
+        if lobby.leave(&player.id, player.session) {
+            info!("Player {:#?} left lobby {:#?}", player.id, lobbyid);
+        }
+    }
+    remove_if_empty(lobbies, lobbyid);
+    player.status = PlayerStatus::Initiated;
+    player.send(SocketMessage::LeftLobby);
+}
+
+/// Sends the public lobbies to everyone browsing whenever they change.
+async fn stream_listing(context: Context) {
+    let mut interval = tokio::time::interval(LISTING_INTERVAL);
+    let mut last = vec![];
+    loop {
+        interval.tick().await;
+        let lobbies = &*context.read().await;
+        if lobbies.browsers.is_empty() {
+            continue;
+        }
+        let listing = lobbies.public_lobbies();
+        if listing != last {
+            for browser in lobbies.browsers.values() {
+                browser.send(SocketMessage::Lobbies(listing.clone()));
+            }
+            last = listing;
+        }
+    }
+}
+
+/// Queues `player` for a public game and waits until they are paired,
+/// returns the lobby and color of their game or none if they gave up. Settings
+/// no game can be played with are refused before queueing.
+async fn find_match(
+    player: &ServerPlayer,
+    settings: GameSettings,
+    context: &Context,
+    ws_rx: &mut SplitStream<WebSocket>,
+) -> Result<Option<(String, Color)>, SettingsError> {
+    let (matched, mut found) = oneshot::channel();
+    {
+        let lobbies = &mut *context.write().await;
+        if let Err(err) = settings
+            .clock
+            .validate()
+            .and_then(|_| settings.resolve(&lobbies.maps))
+        {
+            warn!("Rejected match settings {:#?} {}", settings, err);
+            return Err(err);
+        }
+        let seeker = Seeker {
+            player: player.clone(),
+            settings,
+            matched,
+        };
+        match lobbies.queue.join(seeker) {
+            Some(pair) => start_match(context, lobbies, pair),
+            None => info!(
+                "Player {:#?} waits for a match, {} in queue",
+                player.id,
+                lobbies.queue.len()
+            ),
+        }
+    }
+    loop {
+        let message = tokio::select! {
+            lobby = &mut found => return Ok(lobby.ok()),
+            message = tokio::time::timeout(LOBBY_IDLE_TIMEOUT, ws_rx.next()) => message,
+        };
+        let code = match message {
+            Ok(Some(Ok(message))) if !message.is_close() => {
+                if message.is_ping() || message.is_pong() {
+                    continue;
+                }
+                match message.to_str().map(serde_json::from_str::<PlayerMessage>) {
+                    Ok(Ok(PlayerMessage::Ping)) => {
+                        player.send(SocketMessage::Pong);
+                        continue;
+                    }
+                    Ok(Ok(PlayerMessage::CancelMatch)) => CloseCodes::MatchCancelled,
+                    Ok(Ok(message)) => {
+                        warn!("Unexpected message while matching {:#?}", message);
+                        player.send(SocketMessage::ProtocolError(
+                            ProtocolError::UnexpectedMessage,
+                        ));
+                        continue;
+                    }
+                    _ => {
+                        debug!("Received message is incorrect format {:#?}", message);
+                        player.send(SocketMessage::ProtocolError(ProtocolError::Malformed));
+                        continue;
+                    }
                 }
-            })
-            .filter_map(|f| f)
-            .collect::<Vec<_>>();
+            }
+            Ok(_) => CloseCodes::MatchCancelled,
+            Err(_) => {
+                info!("Player {:#?} timed out waiting for a match", player.id);
+                player.send(SocketMessage::ProtocolError(ProtocolError::TimedOut));
+                CloseCodes::TimedOut
+            }
+        };
+        let queue = &mut context.write().await.queue;
+        if queue.remove(&player.id, Some(player.session)).is_none() {
+            // Paired in the meantime, the game goes on.
+            return Ok(found.await.ok());
+        }
+        info!("Player {:#?} left the match queue", player.id);
+        player.close(code);
+        return Ok(None);
+    }
+}
+
+/// Seats a pair from the match queue in a new lobby and starts their game.
+fn start_match(context: &Context, lobbies: &mut Lobbies, pair: (Seeker, Seeker)) {
+    let (mut first, mut second) = pair;
+    if rand::random() {
+        std::mem::swap(&mut first, &mut second);
+    }
+    let lobbyid = lobbies.new_lobby_id();
+    let mut seats = vec![(first, PLAYER_COLORS[0]), (second, PLAYER_COLORS[1])];
+    for (seeker, color) in &mut seats {
+        seeker.player.status = PlayerStatus::JoinedLobby(lobbyid.clone(), *color);
+    }
+    let (leader, settings) = (&seats[0].0.player, seats[0].0.settings.clone());
+    let mut lobby =
+        ServerLobby::new_with_player(lobbyid.clone(), leader.clone(), lobbies.archive.clone());
+    let opponent = &seats[1].0.player;
+    lobby.players.insert(opponent.id.clone(), opponent.clone());
+    lobby.start_game(
+        &leader.id,
+        GameType::Online,
+        TeamMode::Solo,
+        settings,
+        &lobbies.maps,
+    );
+    info!("Matched players in lobby {:#?}", lobbyid);
+    for (seeker, color) in seats {
+        seeker
+            .player
+            .send(SocketMessage::LobbyJoined(lobby.clone().into(), color));
+        if seeker.matched.send((lobbyid.clone(), color)).is_err() {
+            // Their connection is gone, hold the seat like for a dropped one.
+            if let Some(since) = lobby.player_away(&seeker.player.id, seeker.player.session) {
+                tokio::spawn(remove_after_grace(
+                    context.clone(),
+                    lobbyid.clone(),
+                    seeker.player.id.clone(),
+                    since,
+                ));
+            }
+        }
+    }
+    spawn_game_tasks(context, &lobbyid, &lobby);
+    lobbies.private_lobbies.insert(lobbyid, lobby);
+}
+
+async fn player_message(player_id: &str, lobbyid: &str, context: &Context, message: PlayerMessage) {
+    let context_lock = &mut *context.write().await;
+    let maps = &context_lock.maps;
+    let archive = &context_lock.archive;
+    let lobbies = &mut context_lock.private_lobbies;
+    if let Some(lobby) = lobbies.get_mut(lobbyid) {
         if let Some(player) = lobby.players.get_mut(player_id) {
             match message {
                 PlayerMessage::Ping => {
                     player.send(SocketMessage::Pong);
                 }
-                PlayerMessage::StartGame(game_type, team_mode) => {
+                PlayerMessage::AssignTeam(target, team) => {
+                    let pid = &player.id.clone();
+                    lobby.assign_team(pid, &target, team);
+                }
+                PlayerMessage::SetPublic(public) => {
                     let pid = &player.id.clone();
-                    lobby.start_game(pid, game_type, team_mode);
+                    lobby.set_public(pid, public);
+                }
+                PlayerMessage::Chat(text) => {
+                    let pid = &player.id.clone();
+                    lobby.chat(pid, &text);
+                }
+                PlayerMessage::ListMaps => {
+                    player.send(SocketMessage::Maps(maps.clone()));
+                }
+                PlayerMessage::Leaderboard => {
+                    player.send(SocketMessage::Leaderboard(archive.ratings().leaderboard()));
+                }
+                PlayerMessage::StartGame(game_type, team_mode, settings) => {
+                    let pid = &player.id.clone();
+                    let game_id = lobby.game_id.clone();
+                    lobby.start_game(pid, game_type, team_mode, settings, maps);
+                    if lobby.game_id != game_id {
+                        spawn_game_tasks(context, lobbyid, lobby);
+                    }
                 }
                 PlayerMessage::Move(mov) => {
-                    if let PlayerStatus::JoinedLobby(_, color) = &player.status {
-                        if let State::Game(board) = &mut lobby.state {
-                            if &board.turn == color {
-                                if board.is_move_legal(&mov) {
-                                    board.apply_move(&mov);
-                                    let next_color = colors
-                                        .into_iter()
-                                        .find(|c| c != &board.turn)
-                                        .unwrap_or(board.turn);
-                                    board.change_turn(next_color);
-                                    let newboard = board.clone();
-                                    lobby.broadcast(SocketMessage::Moved(newboard, mov.clone()))
-                                }
-                            }
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        if lobby.play_move(color, &mov) && lobby.bot.is_some() {
+                            tokio::spawn(bot::play_turns(context.clone(), lobbyid.to_string()));
+                        }
+                    }
+                }
+                PlayerMessage::RequestUndo => {
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        lobby.request_undo(color);
+                    }
+                }
+                PlayerMessage::AnswerUndo(accept) => {
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        lobby.answer_undo(color, accept);
+                    }
+                }
+                PlayerMessage::Resign => {
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        lobby.resign(color);
+                    }
+                }
+                PlayerMessage::OfferDraw => {
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        lobby.offer_draw(color);
+                    }
+                }
+                PlayerMessage::AcceptDraw => {
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        lobby.accept_draw(color);
+                    }
+                }
+                PlayerMessage::Rematch => {
+                    if let PlayerStatus::JoinedLobby(_, color) = player.status {
+                        let game_id = lobby.game_id.clone();
+                        lobby.rematch(color, maps);
+                        if lobby.game_id != game_id {
+                            spawn_game_tasks(context, lobbyid, lobby);
                         }
                     }
                 }
                 msg => {
                     warn!("Received Unexpected Player message {:#?}", msg);
+                    player.send(SocketMessage::ProtocolError(ProtocolError::NotAllowed));
+                }
+            }
+        } else if let Some(spectator) = lobby.spectators.get(player_id) {
+            match message {
+                PlayerMessage::Ping => {
+                    spectator.send(SocketMessage::Pong);
+                }
+                PlayerMessage::Chat(text) => {
+                    lobby.chat(player_id, &text);
+                }
+                PlayerMessage::Leaderboard => {
+                    spectator.send(SocketMessage::Leaderboard(archive.ratings().leaderboard()));
+                }
+                msg => {
+                    warn!("Spectator {:#?} cant send {:#?}", player_id, msg);
+                    spectator.send(SocketMessage::ProtocolError(ProtocolError::NotAllowed));
                 }
             }
         } else {
@@ -281,48 +842,65 @@ async fn player_message(player_id: &str, lobbyid: &str, context: &Context, messa
     }
 }
 
-async fn player_disconnect(player_id: &str, lobbyid: &str, context: &Context) {
-    log::debug!("Player Disconnected {:#?}", player_id);
+/// Starts the bot and the clock of a game that just started in `lobby`.
+fn spawn_game_tasks(context: &Context, lobbyid: &str, lobby: &ServerLobby) {
+    if lobby.bot.is_some() {
+        tokio::spawn(bot::play_turns(context.clone(), lobbyid.to_string()));
+    }
+    if let Some(clock) = &lobby.clock {
+        tokio::spawn(clock::run(
+            context.clone(),
+            lobbyid.to_string(),
+            clock.started,
+        ));
+    }
+}
+
+async fn player_disconnect(player: &ServerPlayer, lobbyid: &str, context: &Context) {
+    log::debug!("Player Disconnected {:#?}", player.id);
     let lobbies = &mut context.write().await.private_lobbies;
     if let Some(lobby) = lobbies.get_mut(lobbyid) {
-        lobby.remove_player(player_id);
-        if lobby.players.is_empty() {
-            let lobid = lobby.id.clone();
-            lobbies.remove(&lobid);
+        if lobby.remove_spectator(&player.id, player.session) {
+            return;
+        }
+        if let Some(since) = lobby.player_away(&player.id, player.session) {
+            tokio::spawn(remove_after_grace(
+                context.clone(),
+                lobbyid.to_string(),
+                player.id.clone(),
+                since,
+            ));
         }
     }
 }
 
-async fn websocket_msg(
-    player_id: &str,
-    lobbyid: &str,
-    context: &Context,
-    mut ws_rx: futures_util::stream::SplitStream<WebSocket>,
-) {
-    while let Some(msg) = ws_rx.next().await {
-        match msg {
-            Ok(message) => {
-                if message.is_close() {
-                    // player_disconnect(&player_id, &lobbyid, &context);
-                    break;
-                } else if let Ok(msg)=message.to_str() {
-                    match serde_json::from_str(msg) {
-                        Ok(player_msg) => {
-                            player_message(&player_id, &lobbyid, &context, player_msg).await;
-                        }
-                        Err(er) => {
-                            warn!("Received message not Player Message {:#?}", er);
-                        }
-                    }
-                } else {
-                    warn!("Received message not text {:#?}", message);
-                }
-            }
-            Err(er) => {
-                warn!("websocket error {:#}", er);
-            }
+/// Removes a player who went away at `since` if they haven't rejoined within
+/// [`RECONNECT_GRACE`], and the lobby once nobody is left.
+async fn remove_after_grace(context: Context, lobbyid: String, player_id: String, since: Instant) {
+    tokio::time::sleep(RECONNECT_GRACE).await;
+    let lobbies = &mut context.write().await.private_lobbies;
+    if let Some(lobby) = lobbies.get_mut(&lobbyid) {
+        let still_away = lobby
+            .players
+            .get(&player_id)
+            .is_some_and(|p| p.away_since == Some(since));
+        if still_away {
+            lobby.remove_player(&player_id);
+            remove_if_empty(lobbies, &lobbyid);
         }
     }
+}
 
-    player_disconnect(&player_id, &lobbyid, &context).await;
+/// Removes the lobby once no player is left, its spectators are sent away.
+fn remove_if_empty(lobbies: &mut HashMap<String, ServerLobby>, lobbyid: &str) {
+    if lobbies
+        .get(lobbyid)
+        .is_some_and(|lobby| lobby.players.is_empty())
+    {
+        if let Some(lobby) = lobbies.remove(lobbyid) {
+            for spectator in lobby.spectators.values() {
+                spectator.close(CloseCodes::CantJoinLobbyDoestExist);
+            }
+        }
+    }
 }
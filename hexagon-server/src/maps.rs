@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use hexagon_shared::board::{builtin_maps, BoardMap};
+use log::{info, warn};
+
+/// Directory read when `MAPS_DIR` isn't set.
+const DEFAULT_MAPS_DIR: &str = "maps";
+
+/// The built-in maps followed by every valid `.map` file in the maps
+/// directory, broken files and duplicate names are logged and skipped.
+pub fn load_maps() -> Vec<BoardMap> {
+    let dir = std::env::var("MAPS_DIR").unwrap_or_else(|_| DEFAULT_MAPS_DIR.to_owned());
+    let mut maps = builtin_maps();
+    maps.extend(read_dir(Path::new(&dir)));
+    let mut names = std::collections::HashSet::new();
+    maps.retain(|map| {
+        let unique = names.insert(map.name.clone());
+        if !unique {
+            warn!("Skipping map with duplicate name {:#?}", map.name);
+        }
+        unique
+    });
+    info!("Loaded {} maps", maps.len());
+    maps
+}
+
+fn read_dir(dir: &Path) -> Vec<BoardMap> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Cant read maps directory {:#?} {:#?}", dir, err);
+            return vec![];
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map(|ext| ext == "map").unwrap_or(false))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) => {
+                    warn!("Cant read map {:#?} {:#?}", path, err);
+                    return None;
+                }
+            };
+            match BoardMap::parse(&text) {
+                Ok(map) => Some(map),
+                Err(err) => {
+                    warn!("Invalid map {:#?} {}", path, err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_maps_are_valid() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../maps");
+        let count = std::fs::read_dir(&dir).unwrap().count();
+        assert!(count > 0);
+        assert_eq!(read_dir(&dir).len(), count);
+    }
+}
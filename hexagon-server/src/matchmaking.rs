@@ -0,0 +1,104 @@
+use hexagon_shared::{
+    colors::colors::Color,
+    structures::{CloseCodes, GameSettings},
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::structures::ServerPlayer;
+
+/// Someone waiting for an opponent, told the lobby and color of their game
+/// once they are paired.
+#[derive(Debug)]
+pub struct Seeker {
+    pub player: ServerPlayer,
+    pub settings: GameSettings,
+    pub matched: oneshot::Sender<(String, Color)>,
+}
+
+impl Seeker {
+    fn can_play(&self, other: &Seeker) -> bool {
+        self.player.id != other.player.id && self.settings == other.settings
+    }
+}
+
+/// Players looking for a public game, oldest first.
+#[derive(Debug, Default)]
+pub struct Queue {
+    waiting: Vec<Seeker>,
+}
+
+impl Queue {
+    /// Pairs `seeker` with the longest waiting player who wants the same
+    /// game, or queues them until someone does.
+    pub fn join(&mut self, seeker: Seeker) -> Option<(Seeker, Seeker)> {
+        if let Some(old) = self.remove(&seeker.player.id, None) {
+            old.player.close(CloseCodes::NewSessionOpened);
+        }
+        match self
+            .waiting
+            .iter()
+            .position(|other| other.can_play(&seeker))
+        {
+            Some(pos) => Some((self.waiting.remove(pos), seeker)),
+            None => {
+                self.waiting.push(seeker);
+                None
+            }
+        }
+    }
+
+    /// Takes `playerid` out of the queue, only if they are still waiting
+    /// from `session` when given.
+    pub fn remove(&mut self, playerid: &str, session: Option<Uuid>) -> Option<Seeker> {
+        let pos = self.waiting.iter().position(|seeker| {
+            seeker.player.id == playerid && session.is_none_or(|s| s == seeker.player.session)
+        })?;
+        Some(self.waiting.remove(pos))
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::connected;
+    use hexagon_shared::structures::{PlayerStatus, TimeControl};
+
+    fn seeker(id: &str, settings: GameSettings) -> Seeker {
+        Seeker {
+            player: connected(id, PlayerStatus::Initiated),
+            settings,
+            matched: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn players_are_paired_by_settings() {
+        let blitz = GameSettings {
+            clock: TimeControl {
+                turn: Some(30),
+                ..TimeControl::default()
+            },
+            ..GameSettings::default()
+        };
+        let mut queue = Queue::default();
+        assert!(queue.join(seeker("a", GameSettings::default())).is_none());
+        assert!(queue.join(seeker("b", blitz.clone())).is_none());
+        assert!(queue.join(seeker("a", blitz.clone())).is_some());
+        assert_eq!(queue.len(), 0);
+
+        assert!(queue.join(seeker("c", blitz.clone())).is_none());
+        let session = queue.waiting[0].player.session;
+        assert!(queue.remove("c", Some(Uuid::new_v4())).is_none());
+        assert!(queue.remove("c", Some(session)).is_some());
+        assert!(queue.is_empty());
+    }
+}
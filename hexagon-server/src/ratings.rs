@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use hexagon_shared::{
+    board::{Board, Outcome},
+    colors::colors::Color,
+    structures::Rating,
+};
+use log::error;
+
+use crate::storage::{Storage, Writer};
+
+/// Rating of a player before their first rated game.
+pub const INITIAL_RATING: i32 = 1200;
+/// Most a two player game moves a rating by, split between the opponents
+/// in bigger games.
+const K_FACTOR: f64 = 32.0;
+/// Players shown on the leaderboard.
+const LEADERBOARD_SIZE: usize = 50;
+
+/// Elo ratings of players by id, shared by every lobby.
+#[derive(Debug, Clone)]
+pub struct Ratings {
+    storage: Arc<dyn Storage>,
+    writer: Writer,
+    players: Arc<RwLock<HashMap<String, Rating>>>,
+}
+
+impl Ratings {
+    pub fn new(storage: Arc<dyn Storage>, writer: Writer) -> Self {
+        let players = storage.load_ratings().unwrap_or_else(|err| {
+            error!("Cant load ratings {:#?}", err);
+            HashMap::new()
+        });
+        Self {
+            storage,
+            writer,
+            players: Arc::new(RwLock::new(players)),
+        }
+    }
+
+    /// Rates a finished game. `players` are the ids and names of the players
+    /// with the place they finished in, 0 for first. Returns the new rating
+    /// and the change for each of them.
+    /// The table is saved in the background.
+    pub fn rate(&self, players: &[(String, String, usize)]) -> Vec<(i32, i32)> {
+        let mut guard = self.players.write().unwrap_or_else(|err| err.into_inner());
+        let table = &mut *guard;
+        let ratings: Vec<i32> = players
+            .iter()
+            .map(|(id, _, _)| table.get(id).map_or(INITIAL_RATING, |r| r.rating))
+            .collect();
+        let places: Vec<usize> = players.iter().map(|(_, _, place)| *place).collect();
+        let deltas = changes(&ratings, &places);
+        let rated = players
+            .iter()
+            .zip(deltas)
+            .map(|((id, name, _), delta)| {
+                let rating = table.entry(id.clone()).or_insert_with(|| Rating {
+                    name: name.clone(),
+                    rating: INITIAL_RATING,
+                    games: 0,
+                });
+                rating.name = name.clone();
+                rating.rating += delta;
+                rating.games += 1;
+                (rating.rating, delta)
+            })
+            .collect();
+        let snapshot = table.clone();
+        drop(guard);
+        let storage = self.storage.clone();
+        self.writer.run(move || {
+            if let Err(err) = storage.save_ratings(&snapshot) {
+                error!("Cant store ratings {:#?}", err);
+            }
+        });
+        rated
+    }
+
+    /// Best rated players, highest first.
+    pub fn leaderboard(&self) -> Vec<Rating> {
+        let table = self.players.read().unwrap_or_else(|err| err.into_inner());
+        let mut players: Vec<Rating> = table.values().cloned().collect();
+        players.sort_by(|a, b| {
+            b.rating
+                .cmp(&a.rating)
+                .then(b.games.cmp(&a.games))
+                .then(a.name.cmp(&b.name))
+        });
+        players.truncate(LEADERBOARD_SIZE);
+        players
+    }
+}
+
+/// Rating changes of players with `ratings` who finished in `places`, each
+/// of them scores against every other like in a two player game.
+fn changes(ratings: &[i32], places: &[usize]) -> Vec<i32> {
+    if ratings.len() < 2 {
+        return vec![0; ratings.len()];
+    }
+    let k = K_FACTOR / (ratings.len() - 1) as f64;
+    (0..ratings.len())
+        .map(|i| {
+            let gained: f64 = (0..ratings.len())
+                .filter(|j| *j != i)
+                .map(|j| {
+                    let expected =
+                        1.0 / (1.0 + 10f64.powf(f64::from(ratings[j] - ratings[i]) / 400.0));
+                    let score = match places[i].cmp(&places[j]) {
+                        std::cmp::Ordering::Less => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Greater => 0.0,
+                    };
+                    score - expected
+                })
+                .sum();
+            (k * gained).round() as i32
+        })
+        .collect()
+}
+
+/// Place each player finished in, 0 for first. Everyone shares it in a draw
+/// and the winning team shares first, otherwise players with as many pieces
+/// share a place unless they were eliminated.
+pub fn placements(outcome: Outcome, board: &Board) -> Vec<(Color, usize)> {
+    let rankings = board.rankings();
+    rankings
+        .iter()
+        .enumerate()
+        .map(|(i, (color, count))| {
+            let place = match outcome {
+                Outcome::Draw => 0,
+                Outcome::TeamWin(team) => {
+                    if board.teams.get(team).is_some_and(|t| t.contains(color)) {
+                        0
+                    } else {
+                        1
+                    }
+                }
+                Outcome::Win(winner) if winner == *color => 0,
+                _ if *count == 0 => i,
+                _ => rankings.iter().position(|(_, c)| c == count).unwrap_or(i),
+            };
+            (*color, place)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn ratings_follow_the_results() {
+        assert_eq!(changes(&[1200, 1200], &[0, 1]), vec![16, -16]);
+        assert_eq!(changes(&[1200, 1200], &[0, 0]), vec![0, 0]);
+        assert_eq!(changes(&[1400, 1200], &[0, 0]), vec![-8, 8]);
+        let three = changes(&[1200, 1200, 1200], &[0, 1, 1]);
+        assert_eq!(three, vec![16, -8, -8]);
+
+        let ratings = Ratings::new(Arc::new(MemoryStorage::default()), Writer::default());
+        let game = |a: usize, b: usize| {
+            vec![
+                ("a".to_string(), "Ann".to_string(), a),
+                ("b".to_string(), "Bob".to_string(), b),
+            ]
+        };
+        assert_eq!(ratings.rate(&game(0, 1)), vec![(1216, 16), (1184, -16)]);
+        ratings.rate(&game(0, 1));
+        let board = ratings.leaderboard();
+        assert_eq!(board[0].name, "Ann");
+        assert_eq!(board[0].games, 2);
+        assert!(board[0].rating > 1216 && board[1].rating < 1184);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, RwLock},
+    thread,
+};
+
+use hexagon_shared::{
+    board::{GameRecord, Outcome},
+    structures::{Lobby, Rating},
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::Account, bot::Bot};
+
+/// Directory used when `DATA_DIR` isn't set.
+const DEFAULT_DATA_DIR: &str = "data";
+const GAMES_DIR: &str = "games";
+const LOBBIES_FILE: &str = "lobbies.json";
+const RATINGS_FILE: &str = "ratings.json";
+const ACCOUNTS_FILE: &str = "accounts.json";
+const SESSION_KEY_FILE: &str = "session.key";
+
+/// Keeps finished games and snapshots of running ones across restarts.
+pub trait Storage: Debug + Send + Sync {
+    fn save_game(&self, id: &str, record: &GameRecord) -> io::Result<()>;
+    fn load_game(&self, id: &str) -> io::Result<Option<GameRecord>>;
+    /// Replaces the last snapshot of running games.
+    fn save_lobbies(&self, lobbies: &[LobbySnapshot]) -> io::Result<()>;
+    fn load_lobbies(&self) -> io::Result<Vec<LobbySnapshot>>;
+    fn save_ratings(&self, ratings: &HashMap<String, Rating>) -> io::Result<()>;
+    fn load_ratings(&self) -> io::Result<HashMap<String, Rating>>;
+    fn save_accounts(&self, accounts: &HashMap<String, Account>) -> io::Result<()>;
+    fn load_accounts(&self) -> io::Result<HashMap<String, Account>>;
+    /// Key session tokens are signed with, made the first time it is asked
+    /// for so tokens stay valid across restarts.
+    fn session_key(&self) -> io::Result<Vec<u8>>;
+}
+
+/// What a lobby needs to carry on after a restart. Connections are not
+/// kept, players get their seats held until they rejoin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LobbySnapshot {
+    pub lobby: Lobby,
+    pub game_id: String,
+    pub bot: Option<Bot>,
+    pub finished: Option<Outcome>,
+}
+
+/// Storage in the data directory, or in memory if it can't be used.
+pub fn open() -> Arc<dyn Storage> {
+    let dir = std::env::var("DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_owned());
+    match FileStorage::new(&dir) {
+        Ok(storage) => {
+            info!("Storing games in {:#?}", dir);
+            Arc::new(storage)
+        }
+        Err(err) => {
+            warn!(
+                "Cant use data directory {:#?} {:#?}, games are not kept",
+                dir, err
+            );
+            Arc::new(MemoryStorage::default())
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs writes to storage one after another on a thread of its own, so
+/// callers holding the lobby lock don't wait for the disk.
+#[derive(Debug, Clone)]
+pub struct Writer {
+    jobs: Arc<Mutex<mpsc::Sender<Job>>>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in queue {
+                job();
+            }
+        });
+        Self {
+            jobs: Arc::new(Mutex::new(jobs)),
+        }
+    }
+}
+
+impl Writer {
+    /// Queues `job` behind the writes already waiting.
+    pub fn run(&self, job: impl FnOnce() + Send + 'static) {
+        let jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        if jobs.send(Box::new(job)).is_err() {
+            error!("Storage writer stopped, write dropped");
+        }
+    }
+}
+
+/// Keeps everything in memory, gone once the server stops.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    games: RwLock<HashMap<String, GameRecord>>,
+    lobbies: RwLock<Vec<LobbySnapshot>>,
+    ratings: RwLock<HashMap<String, Rating>>,
+    accounts: RwLock<HashMap<String, Account>>,
+    session_key: RwLock<Option<Vec<u8>>>,
+}
+
+fn poisoned<T>(_: T) -> io::Error {
+    io::Error::other("storage lock poisoned")
+}
+
+impl Storage for MemoryStorage {
+    fn save_game(&self, id: &str, record: &GameRecord) -> io::Result<()> {
+        self.games
+            .write()
+            .map_err(poisoned)?
+            .insert(id.to_string(), record.clone());
+        Ok(())
+    }
+
+    fn load_game(&self, id: &str) -> io::Result<Option<GameRecord>> {
+        Ok(self.games.read().map_err(poisoned)?.get(id).cloned())
+    }
+
+    fn save_lobbies(&self, lobbies: &[LobbySnapshot]) -> io::Result<()> {
+        *self.lobbies.write().map_err(poisoned)? = lobbies.to_vec();
+        Ok(())
+    }
+
+    fn load_lobbies(&self) -> io::Result<Vec<LobbySnapshot>> {
+        Ok(self.lobbies.read().map_err(poisoned)?.clone())
+    }
+
+    fn save_ratings(&self, ratings: &HashMap<String, Rating>) -> io::Result<()> {
+        *self.ratings.write().map_err(poisoned)? = ratings.clone();
+        Ok(())
+    }
+
+    fn load_ratings(&self) -> io::Result<HashMap<String, Rating>> {
+        Ok(self.ratings.read().map_err(poisoned)?.clone())
+    }
+
+    fn save_accounts(&self, accounts: &HashMap<String, Account>) -> io::Result<()> {
+        *self.accounts.write().map_err(poisoned)? = accounts.clone();
+        Ok(())
+    }
+
+    fn load_accounts(&self) -> io::Result<HashMap<String, Account>> {
+        Ok(self.accounts.read().map_err(poisoned)?.clone())
+    }
+
+    fn session_key(&self) -> io::Result<Vec<u8>> {
+        let key = &mut *self.session_key.write().map_err(poisoned)?;
+        Ok(key
+            .get_or_insert_with(|| rand::random::<[u8; 32]>().to_vec())
+            .clone())
+    }
+}
+
+/// Keeps each finished game as a record file in `games/`, the snapshot of
+/// running games in `lobbies.json`, the ratings in `ratings.json` and the
+/// accounts in `accounts.json`.
+#[derive(Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join(GAMES_DIR))?;
+        Ok(Self { dir })
+    }
+
+    /// File of the game `id`, none for ids that aren't plain names.
+    fn game_path(&self, id: &str) -> Option<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(self.dir.join(GAMES_DIR).join(format!("{}.txt", id)))
+    }
+
+    /// Writes next to `path` first so a crash never leaves half a file.
+    fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+impl Storage for FileStorage {
+    fn save_game(&self, id: &str, record: &GameRecord) -> io::Result<()> {
+        let path = self.game_path(id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("bad game id {}", id))
+        })?;
+        Self::write(&path, record.to_text().as_bytes())
+    }
+
+    fn load_game(&self, id: &str) -> io::Result<Option<GameRecord>> {
+        let path = match self.game_path(id) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        text.parse::<GameRecord>()
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+
+    fn save_lobbies(&self, lobbies: &[LobbySnapshot]) -> io::Result<()> {
+        let json = serde_json::to_vec(lobbies)?;
+        Self::write(&self.dir.join(LOBBIES_FILE), &json)
+    }
+
+    fn load_lobbies(&self) -> io::Result<Vec<LobbySnapshot>> {
+        match fs::read(self.dir.join(LOBBIES_FILE)) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save_ratings(&self, ratings: &HashMap<String, Rating>) -> io::Result<()> {
+        let json = serde_json::to_vec(ratings)?;
+        Self::write(&self.dir.join(RATINGS_FILE), &json)
+    }
+
+    fn load_ratings(&self) -> io::Result<HashMap<String, Rating>> {
+        match fs::read(self.dir.join(RATINGS_FILE)) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save_accounts(&self, accounts: &HashMap<String, Account>) -> io::Result<()> {
+        let json = serde_json::to_vec(accounts)?;
+        Self::write(&self.dir.join(ACCOUNTS_FILE), &json)
+    }
+
+    fn load_accounts(&self) -> io::Result<HashMap<String, Account>> {
+        match fs::read(self.dir.join(ACCOUNTS_FILE)) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn session_key(&self) -> io::Result<Vec<u8>> {
+        let path = self.dir.join(SESSION_KEY_FILE);
+        match fs::read(&path) {
+            Ok(key) if !key.is_empty() => Ok(key),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty session key",
+            )),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let key = rand::random::<[u8; 32]>().to_vec();
+                Self::write(&path, &key)?;
+                Ok(key)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{archive::Archive, structures::ServerLobby, testing::player};
+    use hexagon_shared::{
+        colors::colors::Color,
+        structures::{GameSettings, GameType, State, TeamMode},
+    };
+    use uuid::Uuid;
+
+    #[test]
+    fn running_games_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("hexagon-{}", Uuid::new_v4()));
+        let storage = FileStorage::new(&dir).unwrap();
+        let archive = Archive::default();
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            archive.clone(),
+        );
+        lobby.add_player(player("b", "lobby", Color::Blue));
+        lobby.start_game(
+            "a",
+            GameType::Online,
+            TeamMode::Solo,
+            GameSettings::default(),
+            &[],
+        );
+        let mov = match &lobby.state {
+            State::Game(board) => board.legal_moves()[0].clone(),
+            State::Lobby(_) => panic!("game not started"),
+        };
+        assert!(lobby.play_move(Color::Red, &mov));
+        storage.save_lobbies(&[lobby.snapshot()]).unwrap();
+
+        let mut restored = storage.load_lobbies().unwrap();
+        assert_eq!(restored.len(), 1);
+        let restored = ServerLobby::restore(restored.remove(0), archive);
+        assert_eq!(restored.game_id, lobby.game_id);
+        assert_eq!(restored.players.len(), 2);
+        assert!(restored.players.values().all(|p| p.away_since.is_some()));
+        assert_eq!(restored.history.unwrap().moves.len(), 1);
+        match (&restored.state, &lobby.state) {
+            (State::Game(restored), State::Game(board)) => {
+                assert_eq!(restored.pieces(), board.pieces());
+                assert_eq!(restored.turn, Color::Blue);
+            }
+            _ => panic!("game not restored"),
+        }
+
+        let record = GameRecord {
+            names: vec![
+                (Color::Red, "a".to_string()),
+                (Color::Blue, "b".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            history: lobby.history.clone().unwrap(),
+            result: Outcome::InProgress,
+        };
+        storage.save_game(&lobby.game_id, &record).unwrap();
+        let loaded = storage.load_game(&lobby.game_id).unwrap().unwrap();
+        assert_eq!(loaded.names, record.names);
+        assert_eq!(loaded.history.moves.len(), 1);
+        assert!(storage.load_game("missing").unwrap().is_none());
+        assert!(storage.load_game("../lobbies").unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
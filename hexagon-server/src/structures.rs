@@ -1,22 +1,77 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use itertools::Itertools;
-use log::{debug, error, info, warn};
+use log::{error, info, warn};
 
 use hexagon_shared::{
-    board::Board,
+    board::{Board, BoardMap, GameHistory, GameRecord, MoveRecord, Outcome, Point, TurnAction},
     colors::colors::Color,
     structures::{
-        CloseCodes, GameType, Lobby, Player, PlayerStatus, SocketMessage, State, TeamMode,
+        CloseCodes, GameSettings, GameType, Lobby, LobbySummary, Move, Player, PlayerStatus,
+        RatingChange, SocketMessage, State, TeamMode, TimeoutAction, MAX_PLAYERS, PLAYER_COLORS,
+        TEAMS,
     },
 };
-use tokio::sync::{mpsc::UnboundedSender, RwLock};
+use rand::seq::SliceRandom;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedSender},
+    RwLock,
+};
 
-use serde::{Deserialize, Serialize};
 use warp::ws::Message;
+
+use uuid::Uuid;
+
+use crate::{
+    archive::Archive,
+    auth::Auth,
+    bot::{Bot, BOT_ID},
+    chat::Chat,
+    clock::GameClock,
+    matchmaking::Queue,
+    ratings,
+    storage::LobbySnapshot,
+};
 #[derive(Default)]
 pub struct Lobbies {
     pub private_lobbies: HashMap<String, ServerLobby>,
+    pub maps: Vec<BoardMap>,
+    pub archive: Archive,
+    pub queue: Queue,
+    /// Connections watching the public lobbies, by session.
+    pub browsers: HashMap<Uuid, ServerPlayer>,
+    pub auth: Auth,
+}
+
+impl Lobbies {
+    pub fn public_lobbies(&self) -> Vec<LobbySummary> {
+        let mut lobbies: Vec<LobbySummary> = self
+            .private_lobbies
+            .values()
+            .filter(|lobby| lobby.public)
+            .map(ServerLobby::summary)
+            .collect();
+        lobbies.sort_by(|a, b| a.id.cmp(&b.id));
+        lobbies
+    }
+
+    /// Random lobby id no lobby has yet.
+    pub fn new_lobby_id(&self) -> String {
+        use rand::{distributions::Alphanumeric, Rng};
+        loop {
+            let id: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(5)
+                .map(char::from)
+                .collect();
+            if !self.private_lobbies.contains_key(&id) {
+                return id;
+            }
+        }
+    }
 }
 
 pub type Context = Arc<RwLock<Lobbies>>;
@@ -26,6 +81,10 @@ pub struct ServerPlayer {
     pub name: String,
     pub send_channel: UnboundedSender<Result<Message, warp::Error>>,
     pub status: PlayerStatus,
+    /// Tells connections of the same player apart.
+    pub session: Uuid,
+    /// When the connection dropped, while the seat is held.
+    pub away_since: Option<Instant>,
 }
 
 impl From<ServerPlayer> for Player {
@@ -34,6 +93,7 @@ impl From<ServerPlayer> for Player {
             id: serverplayer.id,
             name: serverplayer.name,
             status: serverplayer.status,
+            away: serverplayer.away_since.is_some(),
         }
     }
 }
@@ -70,39 +130,191 @@ pub struct ServerLobby {
     pub id: String,
     pub players: HashMap<String, ServerPlayer>,
     pub state: State,
+    pub bot: Option<Bot>,
+    pub teams: HashMap<String, usize>,
+    pub clock: Option<GameClock>,
+    pub spectators: HashMap<String, ServerPlayer>,
+    pub chat: Chat,
+    pub history: Option<GameHistory>,
+    /// When the current game started, move times count from here.
+    pub started_at: Instant,
+    /// Id the current game is archived under once it ends.
+    pub game_id: String,
+    pub archive: Archive,
+    pub undo: Option<Proposal>,
+    pub draw_offer: Option<Proposal>,
+    /// Players who want to play again after the game.
+    pub rematch: Vec<Color>,
+    /// Outcome once the game is over, also for games ended by agreement.
+    pub finished: Option<Outcome>,
+    pub public: bool,
+}
+
+/// A takeback or draw asked for and the players who still have to accept it.
+#[derive(Debug, Clone)]
+pub struct Proposal {
+    pub from: Color,
+    pub waiting: Vec<Color>,
 }
 
 impl From<ServerLobby> for Lobby {
     fn from(lobby: ServerLobby) -> Self {
+        let mut players: HashMap<String, Player> = lobby
+            .players
+            .into_iter()
+            .map(|s| (s.0, Player::from(s.1)))
+            .collect();
+        if let Some(bot) = &lobby.bot {
+            let player = bot.player(&lobby.id);
+            players.insert(player.id.clone(), player);
+        }
         Self {
             id: lobby.id,
-            players: lobby
-                .players
+            players,
+            state: lobby.state,
+            teams: lobby.teams,
+            spectators: lobby
+                .spectators
                 .into_iter()
-                .map(|s| (s.0, Player::from(s.1)))
+                .map(|(id, spectator)| (id, Player::from(spectator)))
                 .collect(),
-            state: lobby.state,
+            chat: lobby.chat.history(),
+            history: lobby.history,
+            public: lobby.public,
         }
     }
 }
 
 impl ServerLobby {
-    pub fn new_with_player(id: String, player: ServerPlayer) -> Self {
+    pub fn new_with_player(id: String, player: ServerPlayer, archive: Archive) -> Self {
         let mut map = HashMap::new();
         map.insert(player.id.clone(), player.clone());
         Self {
             id,
             players: map,
             state: State::Lobby(player.id.clone()),
+            bot: None,
+            teams: HashMap::new(),
+            clock: None,
+            spectators: HashMap::new(),
+            chat: Chat::default(),
+            history: None,
+            started_at: Instant::now(),
+            game_id: String::new(),
+            archive,
+            undo: None,
+            draw_offer: None,
+            rematch: vec![],
+            finished: None,
+            public: false,
+        }
+    }
+
+    pub fn snapshot(&self) -> LobbySnapshot {
+        LobbySnapshot {
+            lobby: self.clone().into(),
+            game_id: self.game_id.clone(),
+            bot: self.bot.clone(),
+            finished: self.finished,
+        }
+    }
+
+    /// Lobby from a snapshot taken before a restart, every player is away
+    /// until they rejoin and the clocks start over.
+    pub fn restore(snapshot: LobbySnapshot, archive: Archive) -> Self {
+        let now = Instant::now();
+        let lobby = snapshot.lobby;
+        let players = lobby
+            .players
+            .into_iter()
+            .filter(|(id, _)| id != BOT_ID)
+            .map(|(id, player)| {
+                let player = ServerPlayer {
+                    id: player.id,
+                    name: player.name,
+                    send_channel: unbounded_channel().0,
+                    status: player.status,
+                    session: Uuid::new_v4(),
+                    away_since: Some(now),
+                };
+                (id, player)
+            })
+            .collect();
+        let clock = match (&lobby.state, &lobby.history) {
+            (State::Game(board), Some(history))
+                if snapshot.finished.is_none() && !history.settings.clock.is_off() =>
+            {
+                Some(GameClock::new(
+                    history.settings.clock.clone(),
+                    &board.players,
+                    board.turn,
+                    now,
+                ))
+            }
+            _ => None,
+        };
+        let played = lobby
+            .history
+            .as_ref()
+            .and_then(|history| history.moves.last())
+            .map_or(0, |record| record.time);
+        Self {
+            id: lobby.id,
+            players,
+            state: lobby.state,
+            bot: snapshot.bot,
+            teams: lobby.teams,
+            clock,
+            spectators: HashMap::new(),
+            chat: Chat::restore(lobby.chat),
+            history: lobby.history,
+            started_at: now
+                .checked_sub(Duration::from_millis(played))
+                .unwrap_or(now),
+            game_id: snapshot.game_id,
+            archive,
+            undo: None,
+            draw_offer: None,
+            rematch: vec![],
+            finished: snapshot.finished,
+            public: lobby.public,
+        }
+    }
+
+    pub fn summary(&self) -> LobbySummary {
+        let host = match &self.state {
+            State::Lobby(leader) => self.players.get(leader).map(|p| p.name.clone()),
+            State::Game(board) => board
+                .players
+                .first()
+                .and_then(|color| self.names().remove(color)),
+        };
+        LobbySummary {
+            id: self.id.clone(),
+            host: host.unwrap_or_default(),
+            players: self.players.len() + self.bot.iter().count(),
+            settings: match &self.state {
+                State::Game(_) => self.history.as_ref().map(|h| h.settings.clone()),
+                State::Lobby(_) => None,
+            },
+            in_game: matches!(self.state, State::Game(_)),
+        }
+    }
+
+    /// Lists the lobby for people browsing if `playerid` leads it.
+    pub fn set_public(&mut self, playerid: &str, public: bool) {
+        match &self.state {
+            State::Lobby(pid) if pid == playerid => {
+                self.public = public;
+                self.broadcast(SocketMessage::PublicChanged(public));
+            }
+            _ => warn!("Only the leader can list the lobby"),
         }
     }
 
     pub fn add_player(&mut self, player: ServerPlayer) -> Self {
         if let PlayerStatus::JoinedLobby(_, color) = &player.status {
-            self.broadcast(SocketMessage::PlayerJoined(
-                player.clone().into(),
-                color.clone(),
-            ));
+            self.broadcast(SocketMessage::PlayerJoined(player.clone().into(), *color));
         }
         if let Some(oldplayer) = self.players.insert(player.id.clone(), player.clone()) {
             log::warn!("Old player {:#?} replaced by {:#?}", oldplayer, player);
@@ -111,17 +323,68 @@ impl ServerLobby {
         self.clone()
     }
 
+    /// Everyone connected to the lobby, players and spectators.
+    fn connected(&self) -> impl Iterator<Item = &ServerPlayer> {
+        self.players
+            .values()
+            .chain(self.spectators.values())
+            .filter(|p| p.away_since.is_none())
+    }
+
     pub fn broadcast(&self, message: SocketMessage) {
-        for p in self.players.iter() {
-            p.1.send(message.clone());
+        for p in self.connected() {
+            p.send(message.clone());
         }
     }
 
     pub fn broadcast_except(&self, id: &str, message: SocketMessage) {
-        for p in self.players.iter() {
-            if p.0 != id {
-                p.1.send(message.clone());
+        for p in self.connected() {
+            if p.id != id {
+                p.send(message.clone());
+            }
+        }
+    }
+
+    /// Lets `player` watch the lobby without a seat.
+    pub fn add_spectator(&mut self, player: &mut ServerPlayer) {
+        player.status = PlayerStatus::Spectating(self.id.clone());
+        self.broadcast(SocketMessage::SpectatorJoined(player.clone().into()));
+        if let Some(old) = self.spectators.insert(player.id.clone(), player.clone()) {
+            old.close(CloseCodes::NewSessionOpened);
+        }
+        player.send(SocketMessage::Spectating(self.clone().into()));
+    }
+
+    /// Posts `text` from a player or spectator to everyone in the lobby, or
+    /// tells the sender why it was rejected.
+    pub fn chat(&mut self, playerid: &str, text: &str) {
+        let sender = match self
+            .players
+            .get(playerid)
+            .or_else(|| self.spectators.get(playerid))
+        {
+            Some(sender) => sender.clone(),
+            None => return,
+        };
+        match self.chat.post(&sender.clone().into(), text, Instant::now()) {
+            Ok(message) => self.broadcast(SocketMessage::ChatMessage(message)),
+            Err(err) => {
+                warn!("Rejected chat from {:#?} {}", playerid, err);
+                sender.send(SocketMessage::ChatRejected(err));
+            }
+        }
+    }
+
+    /// Removes the spectator `playerid` if `session` is still theirs.
+    pub fn remove_spectator(&mut self, playerid: &str, session: Uuid) -> bool {
+        match self.spectators.get(playerid) {
+            Some(spectator) if spectator.session == session => {
+                if let Some(spectator) = self.spectators.remove(playerid) {
+                    self.broadcast(SocketMessage::SpectatorLeft(spectator.into()));
+                }
+                true
             }
+            _ => false,
         }
     }
 
@@ -155,45 +418,248 @@ impl ServerLobby {
         if playerid == self.state.leader() {
             self.assignnewleader();
         }
+        self.teams.remove(playerid);
         if let Some(player) = self.players.remove(playerid) {
             log::debug!("Player removed {:#?}", player);
             self.broadcast(SocketMessage::PlayerDisconnected(Player::from(player)));
         }
     }
 
-    pub fn start_game(&mut self, playerid: &str, game_type: GameType, team_mode: TeamMode) {
+    /// Takes the player or spectator with the connection `session` out of
+    /// the lobby for good, a game they play in goes on without them. Returns
+    /// whether they were in it.
+    pub fn leave(&mut self, playerid: &str, session: Uuid) -> bool {
+        if self.remove_spectator(playerid, session) {
+            return true;
+        }
+        let color = match self.players.get(playerid) {
+            Some(player) if player.session == session => match &player.status {
+                PlayerStatus::JoinedLobby(_, color) => Some(*color),
+                _ => None,
+            },
+            _ => return false,
+        };
+        if let Some(color) = color {
+            self.resign(color);
+            self.rematch.retain(|c| *c != color);
+        }
+        self.remove_player(playerid);
+        true
+    }
+
+    /// Holds the seat of `playerid` after the connection `session` dropped,
+    /// returns when they went away unless they have connected again since.
+    pub fn player_away(&mut self, playerid: &str, session: Uuid) -> Option<Instant> {
+        let player = self
+            .players
+            .get_mut(playerid)
+            .filter(|p| p.session == session)?;
+        let now = Instant::now();
+        player.away_since = Some(now);
+        let player = Player::from(player.clone());
+        self.broadcast(SocketMessage::PlayerAway(player));
+        Some(now)
+    }
+
+    /// Gives `player` back the seat held for their id, returns its color.
+    pub fn rejoin(&mut self, player: &mut ServerPlayer) -> Option<Color> {
+        let seat = self.players.get(&player.id)?;
+        let color = match &seat.status {
+            PlayerStatus::JoinedLobby(_, color) => *color,
+            _ => return None,
+        };
+        if seat.away_since.is_none() {
+            seat.close(CloseCodes::NewSessionOpened);
+        }
+        player.status = seat.status.clone();
+        player.away_since = None;
+        self.players.insert(player.id.clone(), player.clone());
+        self.broadcast_except(
+            &player.id,
+            SocketMessage::PlayerJoined(player.clone().into(), color),
+        );
+        Some(color)
+    }
+
+    /// Colors of the players in the lobby in seating order, the order they
+    /// were handed out starting from `first`.
+    fn seating(&self, first: Color) -> Vec<Color> {
+        let mut colors: Vec<Color> = PLAYER_COLORS
+            .iter()
+            .copied()
+            .filter(|color| {
+                self.players
+                    .values()
+                    .any(|p| matches!(&p.status, PlayerStatus::JoinedLobby(_, c) if c == color))
+            })
+            .collect();
+        if let Some(pos) = colors.iter().position(|c| *c == first) {
+            colors.rotate_left(pos);
+        }
+        colors
+    }
+
+    /// Colors of both teams in seating order with the team of `first` first,
+    /// none unless the teams are the same size.
+    fn team_seating(&self, first: Color) -> Option<Vec<Vec<Color>>> {
+        let lobby = Lobby::from(self.clone());
+        let mut teams = vec![vec![]; TEAMS];
+        for color in self.seating(first) {
+            let team = self
+                .players
+                .values()
+                .find(|p| matches!(&p.status, PlayerStatus::JoinedLobby(_, c) if *c == color))
+                .map(|p| lobby.team_of(&p.id))?;
+            teams.get_mut(team)?.push(color);
+        }
+        let size = teams[0].len();
+        if size == 0 || teams.iter().any(|team| team.len() != size) {
+            return None;
+        }
+        if let Some(pos) = teams.iter().position(|team| team.contains(&first)) {
+            teams.rotate_left(pos);
+        }
+        Some(teams)
+    }
+
+    /// Moves `target` to `team` if `playerid` leads the lobby.
+    pub fn assign_team(&mut self, playerid: &str, target: &str, team: usize) {
+        match &self.state {
+            State::Lobby(pid) if pid == playerid => {
+                if team >= TEAMS || !self.players.contains_key(target) {
+                    warn!("Cant assign {:#?} to team {}", target, team);
+                    return;
+                }
+                self.teams.insert(target.to_string(), team);
+                self.broadcast(SocketMessage::TeamsChanged(self.teams.clone()));
+            }
+            _ => {
+                warn!("Only the leader can assign teams before the game");
+            }
+        }
+    }
+
+    pub fn start_game(
+        &mut self,
+        playerid: &str,
+        game_type: GameType,
+        team_mode: TeamMode,
+        settings: GameSettings,
+        maps: &[BoardMap],
+    ) {
+        self.start_game_with(playerid, game_type, team_mode, settings, maps, false);
+    }
+
+    /// Starts the game like [`ServerLobby::start_game`], in games against
+    /// the computer the bot moves first if `bot_first`.
+    fn start_game_with(
+        &mut self,
+        playerid: &str,
+        game_type: GameType,
+        team_mode: TeamMode,
+        settings: GameSettings,
+        maps: &[BoardMap],
+        bot_first: bool,
+    ) {
         match &self.state {
             State::Lobby(pid) => {
                 if playerid == pid {
                     if let Some(player) = self.players.get(pid) {
                         if let PlayerStatus::JoinedLobby(_, color) = player.status {
-                            let othercolor = {
-                                let op = self.players.values().find(|p| {
-                                    if let PlayerStatus::JoinedLobby(_, ncolor) = p.status {
-                                        color != ncolor
-                                    } else {
-                                        false
+                            let mut teams = vec![];
+                            let (colors, bot) = match &game_type {
+                                GameType::Online => {
+                                    if self.players.len() < 2 || self.players.len() > MAX_PLAYERS {
+                                        warn!(
+                                            "Cant start game with {} players",
+                                            self.players.len()
+                                        );
+                                        return;
                                     }
-                                });
-                                if let Some(p) = op {
-                                    if let PlayerStatus::JoinedLobby(_, c) = p.status {
-                                        c
+                                    if let TeamMode::Team { .. } = team_mode {
+                                        teams = match self.team_seating(color) {
+                                            Some(teams) => teams,
+                                            None => {
+                                                warn!("Cant start team game with uneven teams");
+                                                return;
+                                            }
+                                        };
+                                        // Seats alternate between the teams.
+                                        let colors = (0..teams[0].len())
+                                            .flat_map(|i| teams.iter().map(move |team| team[i]))
+                                            .collect();
+                                        (colors, None)
                                     } else {
-                                        Color::DarkRed
+                                        (self.seating(color), None)
+                                    }
+                                }
+                                GameType::VsComputer(difficulty) => {
+                                    if self.players.len() > 1 {
+                                        warn!("Cant play against computer with other players in lobby");
+                                        return;
                                     }
-                                } else {
-                                    Color::DarkRed
+                                    let botcolor = Lobby::from(self.clone())
+                                        .get_available_color()
+                                        .unwrap_or(Color::DarkRed);
+                                    let bot = Bot {
+                                        color: botcolor,
+                                        difficulty: *difficulty,
+                                    };
+                                    let colors = if bot_first {
+                                        vec![botcolor, color]
+                                    } else {
+                                        vec![color, botcolor]
+                                    };
+                                    (colors, Some(bot))
                                 }
                             };
 
-                            self.state = State::Game({
-                                match game_type {
-                                    GameType::TwoPlayer => {
-                                        Board::generate_hexagon(6, color, othercolor)
-                                    }
+                            let mut board = match settings
+                                .clock
+                                .validate()
+                                .and_then(|_| settings.resolve(maps))
+                                .and_then(|map| map.board(&colors))
+                            {
+                                Ok(board) => board,
+                                Err(err) => {
+                                    warn!("Rejected game settings {:#?} {}", settings, err);
+                                    player.send(SocketMessage::SettingsRejected(err));
+                                    return;
                                 }
-                            });
-                            self.broadcast(SocketMessage::GameStart(self.state.clone()));
+                            };
+                            board.teams = teams;
+                            board.friendly_capture = matches!(
+                                team_mode,
+                                TeamMode::Team {
+                                    friendly_capture: true
+                                }
+                            );
+                            if let Some(bot) = bot {
+                                self.broadcast(SocketMessage::PlayerJoined(
+                                    bot.player(&self.id),
+                                    bot.color,
+                                ));
+                                self.bot = Some(bot);
+                            }
+                            self.clock = if settings.clock.is_off() {
+                                None
+                            } else {
+                                Some(GameClock::new(
+                                    settings.clock.clone(),
+                                    &board.players,
+                                    board.turn,
+                                    Instant::now(),
+                                ))
+                            };
+                            self.history = Some(GameHistory::new(board.clone(), settings.clone()));
+                            self.started_at = Instant::now();
+                            self.game_id = Uuid::new_v4().to_simple().to_string();
+                            self.undo = None;
+                            self.draw_offer = None;
+                            self.rematch.clear();
+                            self.finished = None;
+                            self.state = State::Game(board);
+                            self.broadcast(SocketMessage::GameStart(self.state.clone(), settings));
                         }
                     }
                 } else {
@@ -205,4 +671,660 @@ impl ServerLobby {
             }
         }
     }
+
+    /// Applies a move for `color` if it is their turn, hands the turn over and
+    /// broadcasts the new board, returns whether the move was played.
+    pub fn play_move(&mut self, color: Color, mov: &Move) -> bool {
+        let board = match &mut self.state {
+            State::Game(board) => board,
+            State::Lobby(_) => return false,
+        };
+        if board.turn != color
+            || self.finished.is_some()
+            || board.outcome() != Outcome::InProgress
+            || !board.is_move_legal(mov)
+        {
+            return false;
+        }
+        let flipped = board.captures(mov);
+        board.apply_move(mov);
+        let skipped = board.end_turn(color);
+        let board = board.clone();
+        self.broadcast(SocketMessage::Moved(board, mov.clone()));
+        self.record(color, TurnAction::Move(mov.clone()), flipped);
+        self.turn_ended(skipped);
+        true
+    }
+
+    /// Names of the seated players and the bot by color.
+    fn names(&self) -> HashMap<Color, String> {
+        let mut names: HashMap<Color, String> = self
+            .players
+            .values()
+            .filter_map(|player| match &player.status {
+                PlayerStatus::JoinedLobby(_, color) => Some((*color, player.name.clone())),
+                _ => None,
+            })
+            .collect();
+        if let Some(bot) = &self.bot {
+            names.insert(bot.color, bot.player(&self.id).name);
+        }
+        names
+    }
+
+    /// Adds a turn to the move log and sends it to everyone.
+    fn record(&mut self, color: Color, action: TurnAction, flipped: Vec<Point>) {
+        let record = MoveRecord {
+            color,
+            action,
+            flipped,
+            time: self.started_at.elapsed().as_millis() as u64,
+        };
+        if let Some(history) = &mut self.history {
+            history.moves.push(record.clone());
+        }
+        self.undo = None;
+        if self
+            .draw_offer
+            .as_ref()
+            .is_some_and(|offer| offer.from != color)
+        {
+            self.draw_offer = None;
+        }
+        self.broadcast(SocketMessage::TurnRecorded(record));
+    }
+
+    fn in_progress(&self) -> bool {
+        match &self.state {
+            State::Game(board) => self.finished.is_none() && board.outcome() == Outcome::InProgress,
+            State::Lobby(_) => false,
+        }
+    }
+
+    /// Colors of the seated players still in the game other than `color`.
+    fn others(&self, color: Color) -> Vec<Color> {
+        let board = match &self.state {
+            State::Game(board) => board,
+            State::Lobby(_) => return vec![],
+        };
+        self.players
+            .values()
+            .filter_map(|p| match &p.status {
+                PlayerStatus::JoinedLobby(_, c) if *c != color && board.players.contains(c) => {
+                    Some(*c)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Takes `color` out of the game, the game goes on without them when
+    /// more than one player is left.
+    pub fn resign(&mut self, color: Color) {
+        if !self.in_progress() {
+            return;
+        }
+        let board = match &mut self.state {
+            State::Game(board) if board.players.contains(&color) => board,
+            _ => return,
+        };
+        info!("{:#?} resigned in lobby {:#?}", color, self.id);
+        let skipped = board.forfeit(color);
+        let board = board.clone();
+        self.broadcast(SocketMessage::Forfeited(color, board));
+        self.record(color, TurnAction::Forfeit, vec![]);
+        self.turn_ended(skipped);
+    }
+
+    /// Offers the other players a draw, it stands until one of them plays a
+    /// turn. The bot never takes a draw.
+    pub fn offer_draw(&mut self, color: Color) {
+        if !self.in_progress() || self.bot.is_some() || self.draw_offer.is_some() {
+            warn!("{:#?} cant offer a draw in lobby {:#?}", color, self.id);
+            return;
+        }
+        self.draw_offer = Some(Proposal {
+            from: color,
+            waiting: self.others(color),
+        });
+        self.broadcast(SocketMessage::DrawOffered(color));
+    }
+
+    /// Ends the game in a draw once everyone took the offer.
+    pub fn accept_draw(&mut self, color: Color) {
+        let offer = match &mut self.draw_offer {
+            Some(offer) if offer.waiting.contains(&color) => offer,
+            _ => return,
+        };
+        offer.waiting.retain(|c| *c != color);
+        if offer.waiting.is_empty() {
+            info!("Draw agreed in lobby {:#?}", self.id);
+            self.game_over(Outcome::Draw);
+        }
+    }
+
+    /// Counts the vote of `color` to play again, once every seated player
+    /// wants to the game starts over with the same settings and the next
+    /// player moving first.
+    pub fn rematch(&mut self, color: Color, maps: &[BoardMap]) {
+        if self.finished.is_none() || self.rematch.contains(&color) {
+            return;
+        }
+        self.rematch.push(color);
+        self.broadcast(SocketMessage::RematchRequested(color));
+        let everyone = self.players.values().all(|p| match &p.status {
+            PlayerStatus::JoinedLobby(_, c) => self.rematch.contains(c),
+            _ => true,
+        });
+        if !everyone {
+            return;
+        }
+        let (start, settings) = match &self.history {
+            Some(history) => (history.start.clone(), history.settings.clone()),
+            None => return,
+        };
+        let game_type = match &self.bot {
+            Some(bot) => GameType::VsComputer(bot.difficulty),
+            None => GameType::Online,
+        };
+        let team_mode = if start.teams.is_empty() {
+            TeamMode::Solo
+        } else {
+            TeamMode::Team {
+                friendly_capture: start.friendly_capture,
+            }
+        };
+        let seat_of = |color: &Color| {
+            self.players
+                .values()
+                .find(|p| matches!(&p.status, PlayerStatus::JoinedLobby(_, c) if c == color))
+        };
+        let first = match start
+            .players
+            .iter()
+            .cycle()
+            .skip(1)
+            .take(start.players.len())
+            .find_map(seat_of)
+        {
+            Some(player) => player.id.clone(),
+            None => return,
+        };
+        // The bot has no seat, it takes its turn to go first here.
+        let bot_first = self
+            .bot
+            .as_ref()
+            .is_some_and(|bot| start.players.iter().cycle().nth(1) == Some(&bot.color));
+        info!("Rematch in lobby {:#?}", self.id);
+        let finished = self.state.clone();
+        let bot = self.bot.take();
+        self.state = State::Lobby(first.clone());
+        self.start_game_with(&first, game_type, team_mode, settings, maps, bot_first);
+        if let State::Lobby(_) = self.state {
+            warn!("Cant start rematch in lobby {:#?}", self.id);
+            self.state = finished;
+            self.bot = bot;
+        }
+    }
+
+    /// Index of the last move `color` played, the position a takeback by
+    /// them goes back to. None if there is nothing to take back or a player
+    /// forfeited since.
+    fn undo_point(&self, color: Color) -> Option<usize> {
+        let moves = &self.history.as_ref()?.moves;
+        let point = moves
+            .iter()
+            .rposition(|r| r.color == color && matches!(r.action, TurnAction::Move(_)))?;
+        if moves[point..]
+            .iter()
+            .any(|r| r.action == TurnAction::Forfeit)
+        {
+            return None;
+        }
+        Some(point)
+    }
+
+    /// Asks the other players to take back the last move of `color`, taken
+    /// back right away when nobody else has to agree, like against the bot.
+    pub fn request_undo(&mut self, color: Color) {
+        if !self.in_progress() {
+            return;
+        }
+        if !self.history.as_ref().is_some_and(|h| h.settings.takebacks) {
+            warn!("Takebacks are off in lobby {:#?}", self.id);
+            return;
+        }
+        if self.undo.is_some() || self.undo_point(color).is_none() {
+            warn!("{:#?} cant take back a move in lobby {:#?}", color, self.id);
+            return;
+        }
+        let waiting = self.others(color);
+        if waiting.is_empty() {
+            self.take_back(color);
+        } else {
+            self.undo = Some(Proposal {
+                from: color,
+                waiting,
+            });
+            self.broadcast(SocketMessage::UndoRequested(color));
+        }
+    }
+
+    /// Answer of `color` to the takeback asked for, one decline is enough to
+    /// turn it down.
+    pub fn answer_undo(&mut self, color: Color, accept: bool) {
+        let request = match &mut self.undo {
+            Some(request) if request.waiting.contains(&color) => request,
+            _ => return,
+        };
+        if !accept {
+            self.undo = None;
+            self.broadcast(SocketMessage::UndoDeclined(color));
+            return;
+        }
+        request.waiting.retain(|c| *c != color);
+        if request.waiting.is_empty() {
+            let from = request.from;
+            self.undo = None;
+            self.take_back(from);
+        }
+    }
+
+    /// Rewinds the game to before the last move of `color`.
+    fn take_back(&mut self, color: Color) {
+        let point = match self.undo_point(color) {
+            Some(point) => point,
+            None => return,
+        };
+        let board = match &mut self.history {
+            Some(history) => {
+                history.moves.truncate(point);
+                history.board_at(point)
+            }
+            None => return,
+        };
+        info!(
+            "Took back {:#?}'s move {} in lobby {:#?}",
+            color, point, self.id
+        );
+        self.state = State::Game(board.clone());
+        self.broadcast(SocketMessage::Undone(board.clone(), point));
+        if let Some(clock) = &mut self.clock {
+            let now = Instant::now();
+            clock.switch(board.turn, now);
+            let clocks = clock.remaining(&board.players, now);
+            self.broadcast(SocketMessage::Clocks(clocks));
+        }
+    }
+
+    /// Announces skipped players, restarts the clock for the player to move
+    /// and ends the game if it is over.
+    fn turn_ended(&mut self, skipped: Vec<Color>) {
+        let board = match &self.state {
+            State::Game(board) => board.clone(),
+            State::Lobby(_) => return,
+        };
+        for skipped in skipped {
+            info!(
+                "{:#?} has no legal move in lobby {:#?}, turn skipped",
+                skipped, self.id
+            );
+            self.broadcast(SocketMessage::TurnSkipped(skipped, board.clone()));
+        }
+        let outcome = board.outcome();
+        if outcome != Outcome::InProgress {
+            self.game_over(outcome);
+        } else if let Some(clock) = &mut self.clock {
+            let now = Instant::now();
+            clock.switch(board.turn, now);
+            let clocks = clock.remaining(&board.players, now);
+            self.broadcast(SocketMessage::Clocks(clocks));
+        }
+    }
+
+    /// Stops the game, archives it and sends the result to everyone.
+    fn game_over(&mut self, outcome: Outcome) {
+        let board = match &self.state {
+            State::Game(board) => board,
+            State::Lobby(_) => return,
+        };
+        info!("Game over in lobby {:#?} {:#?}", self.id, outcome);
+        self.finished = Some(outcome);
+        self.clock = None;
+        self.undo = None;
+        self.draw_offer = None;
+        if let Some(history) = &self.history {
+            let record = GameRecord {
+                names: self.names(),
+                history: history.clone(),
+                result: outcome,
+            };
+            self.archive.store(self.game_id.clone(), record);
+        }
+        let changes = self.rate(outcome, board);
+        self.broadcast(SocketMessage::GameOver(
+            outcome,
+            board.rankings(),
+            self.game_id.clone(),
+        ));
+        if !changes.is_empty() {
+            self.broadcast(SocketMessage::RatingsChanged(changes));
+        }
+    }
+
+    /// Rates the game for the seated players, games against the bot and
+    /// players who already left aren't rated.
+    fn rate(&self, outcome: Outcome, board: &Board) -> Vec<RatingChange> {
+        if self.bot.is_some() {
+            return vec![];
+        }
+        let seats: HashMap<Color, &ServerPlayer> = self
+            .players
+            .values()
+            .filter_map(|player| match &player.status {
+                PlayerStatus::JoinedLobby(_, color) => Some((*color, player)),
+                _ => None,
+            })
+            .collect();
+        let (colors, players): (Vec<Color>, Vec<(String, String, usize)>) =
+            ratings::placements(outcome, board)
+                .into_iter()
+                .filter_map(|(color, place)| {
+                    let player = seats.get(&color)?;
+                    Some((color, (player.id.clone(), player.name.clone(), place)))
+                })
+                .unzip();
+        if players.len() < 2 {
+            return vec![];
+        }
+        info!("Rating game in lobby {:#?}", self.id);
+        self.archive
+            .ratings()
+            .rate(&players)
+            .into_iter()
+            .zip(colors)
+            .map(|((rating, delta), color)| RatingChange {
+                color,
+                rating,
+                delta,
+            })
+            .collect()
+    }
+
+    /// Sends the clocks to the players and applies the timeout action if the
+    /// player to move ran out of time, returns whether they did.
+    pub fn tick_clock(&mut self, now: Instant) -> bool {
+        let (clock, board) = match (&self.clock, &mut self.state) {
+            (Some(clock), State::Game(board)) => (clock, board),
+            _ => return false,
+        };
+        if !clock.expired(now) {
+            let clocks = clock.remaining(&board.players, now);
+            self.broadcast(SocketMessage::Clocks(clocks));
+            return false;
+        }
+        let color = board.turn;
+        info!("{:#?} ran out of time in lobby {:#?}", color, self.id);
+        match clock.control.on_timeout {
+            TimeoutAction::RandomMove => {
+                let mov = board.legal_moves().choose(&mut rand::thread_rng()).cloned();
+                match mov {
+                    Some(mov) => {
+                        self.play_move(color, &mov);
+                    }
+                    None => {
+                        let skipped = board.end_turn(color);
+                        self.record(color, TurnAction::Pass, vec![]);
+                        self.turn_ended(skipped);
+                    }
+                }
+            }
+            TimeoutAction::Skip => {
+                let mut skipped = vec![color];
+                skipped.extend(board.end_turn(color));
+                self.record(color, TurnAction::Pass, vec![]);
+                self.turn_ended(skipped);
+            }
+            TimeoutAction::Forfeit => {
+                let skipped = board.forfeit(color);
+                let board = board.clone();
+                self.broadcast(SocketMessage::Forfeited(color, board));
+                self.record(color, TurnAction::Forfeit, vec![]);
+                self.turn_ended(skipped);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::player;
+    use hexagon_shared::structures::{BoardShape, Difficulty, StartingLayout};
+
+    #[test]
+    fn seat_is_held_for_a_player_who_comes_back() {
+        let first = player("a", "lobby", Color::Red);
+        let mut lobby =
+            ServerLobby::new_with_player("lobby".into(), first.clone(), Archive::default());
+        lobby.add_player(player("b", "lobby", Color::Blue));
+
+        assert!(lobby.player_away("a", Uuid::new_v4()).is_none());
+        assert!(lobby.player_away("a", first.session).is_some());
+        assert!(Lobby::from(lobby.clone()).players["a"].away);
+
+        let mut again = player("a", "", Color::Green);
+        assert_eq!(lobby.rejoin(&mut again), Some(Color::Red));
+        assert!(!Lobby::from(lobby.clone()).players["a"].away);
+        assert!(lobby.player_away("a", first.session).is_none());
+        assert_eq!(lobby.rejoin(&mut player("c", "", Color::Green)), None);
+    }
+
+    #[test]
+    fn spectators_watch_without_a_seat() {
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            Archive::default(),
+        );
+        let mut watcher = player("b", "", Color::Blue);
+        lobby.add_spectator(&mut watcher);
+        let view = Lobby::from(lobby.clone());
+        assert_eq!(view.players.len(), 1);
+        assert_eq!(view.spectators.len(), 1);
+        assert!(matches!(watcher.status, PlayerStatus::Spectating(_)));
+        assert!(lobby.player_away("b", watcher.session).is_none());
+        assert!(!lobby.remove_spectator("b", Uuid::new_v4()));
+        assert!(lobby.remove_spectator("b", watcher.session));
+        assert!(lobby.spectators.is_empty());
+    }
+
+    #[test]
+    fn only_public_lobbies_are_listed() {
+        let mut lobbies = Lobbies::default();
+        for id in ["one", "two"].iter() {
+            let mut lobby = ServerLobby::new_with_player(
+                id.to_string(),
+                player("a", id, Color::Red),
+                Archive::default(),
+            );
+            lobby.add_player(player("b", id, Color::Blue));
+            lobbies.private_lobbies.insert(id.to_string(), lobby);
+        }
+        let two = lobbies.private_lobbies.get_mut("two").unwrap();
+        two.set_public("b", true);
+        assert!(lobbies.public_lobbies().is_empty());
+        let two = lobbies.private_lobbies.get_mut("two").unwrap();
+        two.set_public("a", true);
+        assert_eq!(
+            lobbies.public_lobbies(),
+            vec![LobbySummary {
+                id: "two".to_string(),
+                host: "a".to_string(),
+                players: 2,
+                settings: None,
+                in_game: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn finished_games_are_archived() {
+        let archive = Archive::default();
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            archive.clone(),
+        );
+        lobby.add_player(player("b", "lobby", Color::Blue));
+        let settings = GameSettings {
+            shape: BoardShape::Custom(vec![(0, 0), (1, 0), (2, 0)]),
+            layout: StartingLayout::Custom(vec![((0, 0), 0), ((2, 0), 1)]),
+            ..GameSettings::default()
+        };
+        lobby.start_game("a", GameType::Online, TeamMode::Solo, settings, &[]);
+        let mov = Move {
+            from: (0, 0),
+            to: (1, 0),
+        };
+        assert!(lobby.play_move(Color::Red, &mov));
+
+        let record = archive.get(&lobby.game_id).expect("game not archived");
+        assert_eq!(record.result, Outcome::Win(Color::Red));
+        assert_eq!(record.names[&Color::Blue], "b");
+        assert_eq!(record.history.moves.len(), 1);
+        let leaderboard = archive.ratings().leaderboard();
+        assert_eq!(leaderboard.len(), 2);
+        assert_eq!(leaderboard[0].name, "a");
+        assert_eq!(leaderboard[0].rating, 1216);
+    }
+
+    #[test]
+    fn takebacks_need_the_others_to_agree() {
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            Archive::default(),
+        );
+        lobby.add_player(player("b", "lobby", Color::Blue));
+        lobby.start_game(
+            "a",
+            GameType::Online,
+            TeamMode::Solo,
+            GameSettings::default(),
+            &[],
+        );
+        let board = |lobby: &ServerLobby| match &lobby.state {
+            State::Game(board) => board.clone(),
+            State::Lobby(_) => panic!("game not started"),
+        };
+        let start = board(&lobby);
+        for color in [Color::Red, Color::Blue] {
+            let mov = board(&lobby).legal_moves()[0].clone();
+            assert!(lobby.play_move(color, &mov));
+        }
+
+        lobby.request_undo(Color::Red);
+        lobby.answer_undo(Color::Red, true);
+        lobby.answer_undo(Color::Blue, false);
+        assert!(lobby.undo.is_none());
+        assert_eq!(lobby.history.as_ref().unwrap().moves.len(), 2);
+
+        lobby.request_undo(Color::Red);
+        lobby.answer_undo(Color::Blue, true);
+        assert!(lobby.history.as_ref().unwrap().moves.is_empty());
+        assert_eq!(board(&lobby).pieces(), start.pieces());
+        assert_eq!(board(&lobby).turn, Color::Red);
+        lobby.request_undo(Color::Red);
+        assert!(lobby.undo.is_none());
+    }
+
+    #[test]
+    fn draws_resignations_and_rematches() {
+        let archive = Archive::default();
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            archive.clone(),
+        );
+        lobby.add_player(player("b", "lobby", Color::Blue));
+        lobby.start_game(
+            "a",
+            GameType::Online,
+            TeamMode::Solo,
+            GameSettings::default(),
+            &[],
+        );
+        lobby.offer_draw(Color::Red);
+        lobby.accept_draw(Color::Red);
+        assert!(lobby.finished.is_none());
+        lobby.accept_draw(Color::Blue);
+        assert_eq!(lobby.finished, Some(Outcome::Draw));
+        let drawn = lobby.game_id.clone();
+        assert_eq!(archive.get(&drawn).unwrap().result, Outcome::Draw);
+
+        lobby.rematch(Color::Red, &[]);
+        assert_eq!(lobby.game_id, drawn);
+        lobby.rematch(Color::Blue, &[]);
+        assert_ne!(lobby.game_id, drawn);
+        assert!(lobby.finished.is_none());
+        match &lobby.state {
+            State::Game(board) => assert_eq!(board.turn, Color::Blue),
+            State::Lobby(_) => panic!("rematch not started"),
+        }
+
+        lobby.resign(Color::Blue);
+        assert_eq!(lobby.finished, Some(Outcome::Win(Color::Red)));
+    }
+
+    #[test]
+    fn rematches_against_the_computer_alternate_the_first_move() {
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            Archive::default(),
+        );
+        lobby.start_game(
+            "a",
+            GameType::VsComputer(Difficulty::Easy),
+            TeamMode::Solo,
+            GameSettings::default(),
+            &[],
+        );
+        let bot = lobby.bot.clone().unwrap().color;
+        let mut first = Color::Red;
+        for _ in 0..3 {
+            match &lobby.state {
+                State::Game(board) => assert_eq!(board.turn, first),
+                State::Lobby(_) => panic!("game not started"),
+            }
+            lobby.resign(Color::Red);
+            lobby.rematch(Color::Red, &[]);
+            first = if first == Color::Red { bot } else { Color::Red };
+        }
+    }
+
+    #[test]
+    fn leaving_resigns_the_game() {
+        let mut lobby = ServerLobby::new_with_player(
+            "lobby".into(),
+            player("a", "lobby", Color::Red),
+            Archive::default(),
+        );
+        lobby.add_player(player("b", "lobby", Color::Blue));
+        lobby.start_game(
+            "a",
+            GameType::Online,
+            TeamMode::Solo,
+            GameSettings::default(),
+            &[],
+        );
+        let session = lobby.players["b"].session;
+        assert!(!lobby.leave("b", Uuid::new_v4()));
+        assert!(lobby.leave("b", session));
+        assert_eq!(lobby.finished, Some(Outcome::Win(Color::Red)));
+        assert!(!lobby.players.contains_key("b"));
+        assert!(!lobby.leave("b", session));
+    }
 }
@@ -0,0 +1,24 @@
+//! Helpers shared by the unit tests.
+
+use hexagon_shared::{colors::colors::Color, structures::PlayerStatus};
+use tokio::sync::mpsc::unbounded_channel;
+use uuid::Uuid;
+
+use crate::structures::ServerPlayer;
+
+/// A player named after their id whose messages go nowhere.
+pub fn connected(id: &str, status: PlayerStatus) -> ServerPlayer {
+    ServerPlayer {
+        id: id.to_string(),
+        name: id.to_string(),
+        send_channel: unbounded_channel().0,
+        status,
+        session: Uuid::new_v4(),
+        away_since: None,
+    }
+}
+
+/// A player seated as `color` in `lobbyid`.
+pub fn player(id: &str, lobbyid: &str, color: Color) -> ServerPlayer {
+    connected(id, PlayerStatus::JoinedLobby(lobbyid.to_string(), color))
+}
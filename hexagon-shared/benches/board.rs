@@ -0,0 +1,197 @@
+//! Compares move generation and `apply_move` of [`Board`] with the previous
+//! `HashMap` based board, kept below as `LegacyBoard`.
+//!
+//! Run with `cargo bench -p hexagon-shared`.
+
+use std::collections::{HashMap, HashSet};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use hexagon_shared::{
+    ai,
+    board::{Board, Point},
+    colors::colors::Color,
+    models::{AxialCoord, Cube},
+    structures::Move,
+};
+
+#[derive(Clone)]
+struct LegacyBoard {
+    points: HashMap<Point, AxialCoord>,
+    turn: Color,
+    pieces: HashMap<Point, Color>,
+}
+
+impl LegacyBoard {
+    fn from_board(board: &Board) -> Self {
+        Self {
+            points: board
+                .points()
+                .iter()
+                .map(|pt| (*pt, AxialCoord::from(*pt)))
+                .collect(),
+            turn: board.turn,
+            pieces: board.pieces().into_iter().collect(),
+        }
+    }
+
+    fn get_neighbours(&self, point: &Point) -> Vec<Point> {
+        let cube_directions = [
+            Cube { x: 1, y: -1, z: 0 },
+            Cube { x: 1, y: 0, z: -1 },
+            Cube { x: 0, y: 1, z: -1 },
+            Cube { x: -1, y: 1, z: 0 },
+            Cube { x: -1, y: 0, z: 1 },
+            Cube { x: 0, y: -1, z: 1 },
+        ];
+        let mut neighbours = vec![];
+        if let Some(pt) = self.points.get(point) {
+            let cb = Cube::from(pt.clone());
+            for dir in cube_directions.iter() {
+                let neighbour: Cube = cb.clone() + dir.clone();
+                let neighbour_ax = AxialCoord::from(neighbour);
+                let pt = (neighbour_ax.q, neighbour_ax.r);
+                if self.points.contains_key(&pt) {
+                    neighbours.push(pt);
+                }
+            }
+        }
+        neighbours
+    }
+
+    fn get_secondary_neighbours(&self, point: &Point) -> Vec<Point> {
+        let mut secondaryneighbours = vec![];
+        for pts in self.get_neighbours(point).iter() {
+            secondaryneighbours.append(&mut self.get_neighbours(pts));
+        }
+        secondaryneighbours
+    }
+
+    fn is_move_legal(&self, mov: &Move) -> bool {
+        match self.pieces.get(&mov.from) {
+            Some(piece) if *piece == self.turn => {
+                self.get_secondary_neighbours(&mov.from).contains(&mov.to)
+                    && mov.to != mov.from
+                    && !self.pieces.contains_key(&mov.to)
+            }
+            _ => false,
+        }
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = vec![];
+        for (from, color) in self.pieces.iter() {
+            if *color != self.turn {
+                continue;
+            }
+            for to in self.get_secondary_neighbours(from) {
+                let mov = Move { from: *from, to };
+                if self.is_move_legal(&mov) {
+                    moves.push(mov);
+                }
+            }
+        }
+        moves
+    }
+
+    fn apply_move(&mut self, mov: &Move) -> bool {
+        if !self.is_move_legal(mov) {
+            return false;
+        }
+        if !self.get_neighbours(&mov.from).contains(&mov.to) {
+            self.pieces.remove(&mov.from);
+        }
+        self.pieces.insert(mov.to, self.turn);
+        for point in self.get_neighbours(&mov.to).iter() {
+            if self.pieces.contains_key(point) {
+                self.pieces.insert(*point, self.turn);
+            }
+        }
+        true
+    }
+}
+
+/// A position some moves into a game so both sides have grown.
+fn midgame(radius: u32) -> Board {
+    let mut board = Board::generate_hexagon(radius, Color::Red, Color::Blue);
+    for _ in 0..16 {
+        match ai::candidate_moves(&board).into_iter().next() {
+            Some(mov) => board = ai::play(&board, &mov),
+            None => break,
+        }
+    }
+    board
+}
+
+const ITERATIONS: u32 = 200;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// Times `apply` over fresh copies of `board`, copying is not measured.
+fn time_apply<B: Clone>(
+    board: &B,
+    moves: &[Move],
+    apply: impl Fn(&mut B, &Move) -> bool,
+) -> Duration {
+    let mut total = Duration::default();
+    for _ in 0..ITERATIONS {
+        let mut boards = vec![board.clone(); moves.len()];
+        let start = Instant::now();
+        for (board, mov) in boards.iter_mut().zip(moves.iter()) {
+            black_box(apply(board, mov));
+        }
+        total += start.elapsed();
+    }
+    total / ITERATIONS / moves.len().max(1) as u32
+}
+
+fn report(name: &str, legacy: Duration, current: Duration) {
+    println!(
+        "{:<28} legacy {:>12?}  bitboard {:>12?}  {:>8.1}x",
+        name,
+        legacy,
+        current,
+        legacy.as_nanos() as f64 / current.as_nanos().max(1) as f64
+    );
+}
+
+fn main() {
+    for radius in [5, 6, 9].iter() {
+        let board = midgame(*radius);
+        let legacy = LegacyBoard::from_board(&board);
+        let moves = board.legal_moves();
+        let legacy_moves: HashSet<Move> = legacy.legal_moves().into_iter().collect();
+        assert_eq!(moves.len(), legacy_moves.len());
+
+        report(
+            &format!("move generation r={}", radius),
+            time(|| {
+                black_box(legacy.legal_moves());
+            }),
+            time(|| {
+                black_box(board.legal_moves());
+            }),
+        );
+        report(
+            &format!("apply_move r={}", radius),
+            time_apply(&legacy, &moves, |board, mov| board.apply_move(mov)),
+            time_apply(&board, &moves, |board, mov| board.apply_move(mov)),
+        );
+        report(
+            &format!("clone r={}", radius),
+            time(|| {
+                black_box(legacy.clone());
+            }),
+            time(|| {
+                black_box(board.clone());
+            }),
+        );
+    }
+}
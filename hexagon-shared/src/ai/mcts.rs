@@ -0,0 +1,155 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{candidate_moves, play, Evaluator};
+use crate::{
+    board::{Board, Outcome},
+    colors::colors::Color,
+    structures::Move,
+};
+
+struct Node {
+    board: Board,
+    mov: Option<Move>,
+    /// Player who made `mov`, rewards are counted for them.
+    mover: Color,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    reward: f64,
+}
+
+/// Monte-Carlo tree search with UCT selection. Playouts are cut after
+/// `playout_depth` plies and scored with the evaluator.
+pub struct Mcts<E> {
+    evaluator: E,
+    iterations: u32,
+    playout_depth: u32,
+    exploration: f64,
+    rng: StdRng,
+}
+
+impl<E: Evaluator> Mcts<E> {
+    pub fn new(evaluator: E, iterations: u32) -> Self {
+        Self {
+            evaluator,
+            iterations,
+            playout_depth: 8,
+            exploration: 1.4,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn with_playout_depth(mut self, playout_depth: u32) -> Self {
+        self.playout_depth = playout_depth;
+        self
+    }
+
+    pub fn best_move(&mut self, board: &Board) -> Option<Move> {
+        let untried = candidate_moves(board);
+        if untried.len() <= 1 {
+            return untried.into_iter().next();
+        }
+        let mut nodes = vec![Node {
+            board: board.clone(),
+            mov: None,
+            mover: board.turn,
+            parent: None,
+            children: vec![],
+            untried,
+            visits: 0,
+            reward: 0.0,
+        }];
+
+        for _ in 0..self.iterations {
+            let mut idx = 0;
+            while nodes[idx].untried.is_empty() && !nodes[idx].children.is_empty() {
+                idx = self.select_child(&nodes, idx);
+            }
+
+            if !nodes[idx].untried.is_empty() {
+                let pick = self.rng.gen_range(0..nodes[idx].untried.len());
+                let mov = nodes[idx].untried.swap_remove(pick);
+                let next = play(&nodes[idx].board, &mov);
+                let child = Node {
+                    untried: if next.outcome() == Outcome::InProgress {
+                        candidate_moves(&next)
+                    } else {
+                        vec![]
+                    },
+                    mover: nodes[idx].board.turn,
+                    board: next,
+                    mov: Some(mov),
+                    parent: Some(idx),
+                    children: vec![],
+                    visits: 0,
+                    reward: 0.0,
+                };
+                nodes.push(child);
+                let child_idx = nodes.len() - 1;
+                nodes[idx].children.push(child_idx);
+                idx = child_idx;
+            }
+
+            let end = self.rollout(&nodes[idx].board);
+            let mut current = Some(idx);
+            while let Some(i) = current {
+                let reward = self.reward(&end, nodes[i].mover);
+                nodes[i].visits += 1;
+                nodes[i].reward += reward;
+                current = nodes[i].parent;
+            }
+        }
+
+        nodes[0]
+            .children
+            .iter()
+            .max_by_key(|child| nodes[**child].visits)
+            .and_then(|child| nodes[*child].mov.clone())
+    }
+
+    fn select_child(&self, nodes: &[Node], idx: usize) -> usize {
+        let parent_visits = (nodes[idx].visits.max(1) as f64).ln();
+        let uct = |child: usize| {
+            let node = &nodes[child];
+            let visits = node.visits.max(1) as f64;
+            node.reward / visits + self.exploration * (parent_visits / visits).sqrt()
+        };
+        nodes[idx]
+            .children
+            .iter()
+            .copied()
+            .max_by(|a, b| {
+                uct(*a)
+                    .partial_cmp(&uct(*b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(idx)
+    }
+
+    fn rollout(&mut self, board: &Board) -> Board {
+        let mut board = board.clone();
+        for _ in 0..self.playout_depth {
+            if board.outcome() != Outcome::InProgress {
+                break;
+            }
+            let moves = candidate_moves(&board);
+            match moves.choose(&mut self.rng) {
+                Some(mov) => board = play(&board, mov),
+                None => break,
+            }
+        }
+        board
+    }
+
+    fn reward(&self, board: &Board, color: Color) -> f64 {
+        let score = self.evaluator.evaluate(board, color);
+        if score > 0 {
+            1.0
+        } else if score == 0 {
+            0.5
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Game tree search over [`Board`], shared by the server bot, the client hint
+//! and the animated backdrop. Searches are bounded by depth, node and
+//! iteration counts rather than wall clock time so they run the same in WASM.
+
+pub mod mcts;
+pub mod search;
+
+pub use mcts::Mcts;
+pub use search::Searcher;
+
+use crate::{
+    board::{Board, Outcome},
+    colors::colors::Color,
+    structures::Move,
+};
+
+pub const WIN_SCORE: i32 = 10_000;
+
+/// Scores a position from the point of view of `color`, higher is better.
+pub trait Evaluator {
+    fn evaluate(&self, board: &Board, color: Color) -> i32;
+}
+
+/// Own and teammates' pieces minus everyone else's, finished games score
+/// `WIN_SCORE` either way.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PieceDifference;
+
+impl Evaluator for PieceDifference {
+    fn evaluate(&self, board: &Board, color: Color) -> i32 {
+        match board.outcome() {
+            Outcome::Win(winner) if winner == color => return WIN_SCORE,
+            Outcome::Win(_) => return -WIN_SCORE,
+            Outcome::TeamWin(team) if board.team_of(color) == Some(team) => return WIN_SCORE,
+            Outcome::TeamWin(_) => return -WIN_SCORE,
+            Outcome::Draw => return 0,
+            Outcome::InProgress => {}
+        }
+        board
+            .piece_counts()
+            .into_iter()
+            .map(|(c, count)| {
+                if board.is_friendly(c, color) {
+                    count as i32
+                } else {
+                    -(count as i32)
+                }
+            })
+            .sum()
+    }
+}
+
+/// Legal moves with duplicate clones removed, see [`Board::unique_moves`].
+pub fn candidate_moves(board: &Board) -> Vec<Move> {
+    board.unique_moves()
+}
+
+/// The position after `mov` with the turn handed over, see
+/// [`Board::end_turn`].
+pub fn play(board: &Board, mov: &Move) -> Board {
+    let mut next = board.clone();
+    let mover = next.turn;
+    next.apply_move(mov);
+    next.end_turn(mover);
+    next
+}
+
+/// Order independent hash of the pieces and the side to move.
+pub fn position_hash(board: &Board) -> u64 {
+    let pieces = board.occupancy().fold(0, |hash, (color, set)| {
+        set.words()
+            .iter()
+            .enumerate()
+            .fold(hash, |hash, (i, word)| {
+                let key = ((color as u64) << 56) ^ ((i as u64) << 40);
+                hash ^ mix(key ^ mix(*word))
+            })
+    });
+    pieces ^ mix(board.turn as u64 ^ 0xA5A5_A5A5_0000_0000)
+}
+
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture_position() -> Board {
+        let mut board = Board::generate_hexagon(3, Color::Red, Color::Blue);
+        board.clear_pieces();
+        board.set_piece((0, 0), Some(Color::Red));
+        board.set_piece((2, -1), Some(Color::Blue));
+        board.set_piece((2, 0), Some(Color::Blue));
+        board.set_piece((-2, 2), Some(Color::Blue));
+        board
+    }
+
+    #[test]
+    fn searcher_takes_the_capture() {
+        let board = capture_position();
+        let mov = Searcher::new(PieceDifference, 1).best_move(&board).unwrap();
+        let after = play(&board, &mov);
+        assert_eq!(PieceDifference.evaluate(&after, Color::Red), 3);
+    }
+
+    #[test]
+    fn deeper_search_returns_legal_move() {
+        let board = Board::generate_hexagon(4, Color::Red, Color::Blue);
+        let mut searcher = Searcher::new(PieceDifference, 4).with_node_limit(20_000);
+        let mov = searcher.best_move(&board).unwrap();
+        assert!(board.is_move_legal(&mov));
+    }
+
+    #[test]
+    fn mcts_returns_legal_move() {
+        let board = Board::generate_hexagon(4, Color::Red, Color::Blue);
+        let mov = Mcts::new(PieceDifference, 200).best_move(&board).unwrap();
+        assert!(board.is_move_legal(&mov));
+    }
+
+    #[test]
+    fn hash_ignores_insertion_order() {
+        let board = capture_position();
+        let mut other = board.clone();
+        other.clear_pieces();
+        for (pt, color) in board.pieces().into_iter().rev() {
+            other.set_piece(pt, Some(color));
+        }
+        assert_eq!(position_hash(&board), position_hash(&other));
+        other.change_turn(Color::Blue);
+        assert_ne!(position_hash(&board), position_hash(&other));
+    }
+}
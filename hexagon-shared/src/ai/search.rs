@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use super::{candidate_moves, play, position_hash, Evaluator, WIN_SCORE};
+use crate::{
+    board::{Board, Outcome},
+    colors::colors::Color,
+    structures::Move,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best: Option<Move>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best: Move,
+    pub score: i32,
+    /// Deepest iteration that completed within the node limit.
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+/// Raised when the node limit is hit in the middle of an iteration.
+struct Aborted;
+
+/// Alpha-beta with iterative deepening and a transposition table.
+///
+/// With more than two players the search is paranoid: every other player is
+/// assumed to play against the side to move at the root.
+pub struct Searcher<E> {
+    evaluator: E,
+    max_depth: u32,
+    node_limit: u64,
+    table: HashMap<u64, Entry>,
+    nodes: u64,
+}
+
+impl<E: Evaluator> Searcher<E> {
+    pub fn new(evaluator: E, max_depth: u32) -> Self {
+        Self {
+            evaluator,
+            max_depth,
+            node_limit: u64::MAX,
+            table: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// Stops deepening once this many positions were visited, the best move
+    /// of the last completed iteration is returned.
+    pub fn with_node_limit(mut self, node_limit: u64) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    pub fn best_move(&mut self, board: &Board) -> Option<Move> {
+        self.search(board).map(|result| result.best)
+    }
+
+    pub fn search(&mut self, board: &Board) -> Option<SearchResult> {
+        self.table.clear();
+        self.nodes = 0;
+        let color = board.turn;
+        let mut result = None;
+        for depth in 1..=self.max_depth.max(1) {
+            match self.root(board, depth, color) {
+                Ok(Some((best, score))) => {
+                    result = Some(SearchResult {
+                        best,
+                        score,
+                        depth,
+                        nodes: self.nodes,
+                    });
+                    if score.abs() >= WIN_SCORE {
+                        break;
+                    }
+                }
+                Ok(None) => return None,
+                Err(Aborted) => break,
+            }
+        }
+        result
+    }
+
+    /// Children ordered with the remembered best move first, then by their
+    /// static score for the side to move.
+    fn children(&self, board: &Board, hash: u64) -> Vec<(Move, Board)> {
+        let turn = board.turn;
+        let remembered = self.table.get(&hash).and_then(|entry| entry.best.clone());
+        candidate_moves(board)
+            .into_iter()
+            .map(|mov| {
+                let next = play(board, &mov);
+                let key = if Some(&mov) == remembered.as_ref() {
+                    i32::MIN
+                } else {
+                    -self.evaluator.evaluate(&next, turn)
+                };
+                (key, mov, next)
+            })
+            .sorted_by_key(|(key, _, _)| *key)
+            .map(|(_, mov, next)| (mov, next))
+            .collect()
+    }
+
+    fn root(
+        &mut self,
+        board: &Board,
+        depth: u32,
+        color: Color,
+    ) -> Result<Option<(Move, i32)>, Aborted> {
+        let hash = position_hash(board);
+        let mut alpha = -WIN_SCORE - 1;
+        let mut best = None;
+        for (mov, next) in self.children(board, hash) {
+            let score = self.alphabeta(&next, depth - 1, alpha, WIN_SCORE + 1, color)?;
+            if score > alpha {
+                alpha = score;
+                best = Some(mov);
+            }
+        }
+        self.table.insert(
+            hash,
+            Entry {
+                depth,
+                score: alpha,
+                bound: Bound::Exact,
+                best: best.clone(),
+            },
+        );
+        Ok(best.map(|mov| (mov, alpha)))
+    }
+
+    fn alphabeta(
+        &mut self,
+        board: &Board,
+        depth: u32,
+        mut alpha: i32,
+        mut beta: i32,
+        color: Color,
+    ) -> Result<i32, Aborted> {
+        self.nodes += 1;
+        if self.nodes > self.node_limit {
+            return Err(Aborted);
+        }
+        if depth == 0 || board.outcome() != Outcome::InProgress {
+            return Ok(self.evaluator.evaluate(board, color));
+        }
+
+        let hash = position_hash(board);
+        if let Some(entry) = self.table.get(&hash) {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return Ok(entry.score),
+                    Bound::Lower => alpha = alpha.max(entry.score),
+                    Bound::Upper => beta = beta.min(entry.score),
+                }
+                if alpha >= beta {
+                    return Ok(entry.score);
+                }
+            }
+        }
+
+        let (alpha_start, beta_start) = (alpha, beta);
+        let maximizing = board.is_friendly(board.turn, color);
+        let mut best_score = if maximizing { i32::MIN } else { i32::MAX };
+        let mut best_move = None;
+        for (mov, next) in self.children(board, hash) {
+            let score = self.alphabeta(&next, depth - 1, alpha, beta, color)?;
+            if maximizing {
+                if score > best_score {
+                    best_score = score;
+                    best_move = Some(mov);
+                }
+                alpha = alpha.max(score);
+            } else {
+                if score < best_score {
+                    best_score = score;
+                    best_move = Some(mov);
+                }
+                beta = beta.min(score);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        if best_move.is_none() {
+            return Ok(self.evaluator.evaluate(board, color));
+        }
+
+        let bound = if best_score <= alpha_start {
+            Bound::Upper
+        } else if best_score >= beta_start {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(
+            hash,
+            Entry {
+                depth,
+                score: best_score,
+                bound,
+                best: best_move,
+            },
+        );
+        Ok(best_score)
+    }
+}
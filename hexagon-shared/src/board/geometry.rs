@@ -0,0 +1,333 @@
+use std::convert::TryFrom;
+
+use super::Point;
+use crate::{
+    models::{AxialCoord, Cube},
+    structures::MAX_CUSTOM_CELLS,
+};
+
+const CUBE_DIRECTIONS: [(i32, i32, i32); 6] = [
+    (1, -1, 0),
+    (1, 0, -1),
+    (0, 1, -1),
+    (-1, 1, 0),
+    (-1, 0, 1),
+    (0, -1, 1),
+];
+
+/// Words a [`CellSet`] keeps inline, enough for the biggest board players
+/// can set up so copying a board doesn't allocate for its sets.
+const INLINE_WORDS: usize = MAX_CUSTOM_CELLS.div_ceil(64);
+
+/// Fixed size bitset over the cell indices of a board.
+#[derive(Clone)]
+pub struct CellSet {
+    words: Words,
+}
+
+/// Storage of a [`CellSet`], on the heap only for boards bigger than
+/// [`INLINE_WORDS`] words.
+#[derive(Clone)]
+enum Words {
+    Inline([u64; INLINE_WORDS], usize),
+    Heap(Vec<u64>),
+}
+
+impl Default for CellSet {
+    fn default() -> Self {
+        Self::with_capacity(0)
+    }
+}
+
+impl PartialEq for CellSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.words() == other.words()
+    }
+}
+
+impl Eq for CellSet {}
+
+impl std::hash::Hash for CellSet {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.words().hash(state);
+    }
+}
+
+impl std::fmt::Debug for CellSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl CellSet {
+    pub fn with_capacity(cells: usize) -> Self {
+        let len = cells.div_ceil(64);
+        let words = if len <= INLINE_WORDS {
+            Words::Inline([0; INLINE_WORDS], len)
+        } else {
+            Words::Heap(vec![0; len])
+        };
+        Self { words }
+    }
+
+    pub fn insert(&mut self, idx: usize) {
+        self.words_mut()[idx / 64] |= 1 << (idx % 64);
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        self.words_mut()[idx / 64] &= !(1 << (idx % 64));
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        self.words()[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words().iter().all(|w| *w == 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words().iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.words_mut().iter_mut().for_each(|w| *w = 0);
+    }
+
+    pub fn union_with(&mut self, other: &CellSet) {
+        for (w, o) in self.words_mut().iter_mut().zip(other.words()) {
+            *w |= *o;
+        }
+    }
+
+    pub fn difference_with(&mut self, other: &CellSet) {
+        for (w, o) in self.words_mut().iter_mut().zip(other.words()) {
+            *w &= !*o;
+        }
+    }
+
+    pub fn intersect_with(&mut self, other: &CellSet) {
+        for (w, o) in self.words_mut().iter_mut().zip(other.words()) {
+            *w &= *o;
+        }
+    }
+
+    pub fn is_subset(&self, other: &CellSet) -> bool {
+        self.words()
+            .iter()
+            .zip(other.words())
+            .all(|(w, o)| w & !o == 0)
+    }
+
+    pub fn words(&self) -> &[u64] {
+        match &self.words {
+            Words::Inline(words, len) => &words[..*len],
+            Words::Heap(words) => words,
+        }
+    }
+
+    pub(crate) fn words_mut(&mut self) -> &mut [u64] {
+        match &mut self.words {
+            Words::Inline(words, len) => &mut words[..*len],
+            Words::Heap(words) => words,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words().iter().enumerate().flat_map(|(i, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    None
+                } else {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(i * 64 + bit)
+                }
+            })
+        })
+    }
+}
+
+/// Cell index table with the neighbour and jump masks of every cell,
+/// computed once per board shape and shared between clones.
+pub struct Geometry {
+    cells: Vec<Point>,
+    blocked: Vec<Point>,
+    index: IndexTable,
+    neighbours: Vec<CellSet>,
+    jumps: Vec<CellSet>,
+    reach: Vec<CellSet>,
+}
+
+/// Dense lookup from a point to its cell index over the bounding box of
+/// the board, avoids hashing on every lookup.
+struct IndexTable {
+    min: Point,
+    width: usize,
+    height: usize,
+    table: Vec<u32>,
+}
+
+impl IndexTable {
+    const NONE: u32 = u32::MAX;
+
+    fn new(cells: &[Point]) -> Self {
+        let min_q = cells.iter().map(|p| p.0).min().unwrap_or(0);
+        let max_q = cells.iter().map(|p| p.0).max().unwrap_or(-1);
+        let min_r = cells.iter().map(|p| p.1).min().unwrap_or(0);
+        let max_r = cells.iter().map(|p| p.1).max().unwrap_or(-1);
+        let width = (i64::from(max_q) - i64::from(min_q) + 1) as usize;
+        let height = (i64::from(max_r) - i64::from(min_r) + 1) as usize;
+        let mut table = vec![
+            Self::NONE;
+            width
+                .checked_mul(height)
+                .expect("bounding box of the board fits in memory")
+        ];
+        for (i, (q, r)) in cells.iter().enumerate() {
+            let q = (i64::from(*q) - i64::from(min_q)) as usize;
+            let r = (i64::from(*r) - i64::from(min_r)) as usize;
+            table[r * width + q] = i as u32;
+        }
+        Self {
+            min: (min_q, min_r),
+            width,
+            height,
+            table,
+        }
+    }
+
+    fn get(&self, pt: &Point) -> Option<usize> {
+        let q = usize::try_from(i64::from(pt.0) - i64::from(self.min.0)).ok()?;
+        let r = usize::try_from(i64::from(pt.1) - i64::from(self.min.1)).ok()?;
+        if q >= self.width || r >= self.height {
+            return None;
+        }
+        match self.table[r * self.width + q] {
+            Self::NONE => None,
+            idx => Some(idx as usize),
+        }
+    }
+}
+
+impl std::fmt::Debug for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Geometry")
+            .field("cells", &self.cells.len())
+            .finish()
+    }
+}
+
+impl Geometry {
+    /// Indexes `points` except the `blocked` ones. Blocked cells are holes,
+    /// pieces can jump over them but never land on them.
+    pub fn new(
+        points: impl IntoIterator<Item = Point>,
+        blocked: impl IntoIterator<Item = Point>,
+    ) -> Self {
+        let mut blocked: Vec<Point> = blocked.into_iter().collect();
+        blocked.sort_unstable();
+        blocked.dedup();
+        let mut cells: Vec<Point> = points
+            .into_iter()
+            .filter(|pt| blocked.binary_search(pt).is_err())
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        let index = IndexTable::new(&cells);
+
+        let mut neighbours = vec![CellSet::with_capacity(cells.len()); cells.len()];
+        let mut jumps = vec![CellSet::with_capacity(cells.len()); cells.len()];
+        for (i, pt) in cells.iter().enumerate() {
+            let cube = Cube::from(AxialCoord::from(*pt));
+            for (x, y, z) in CUBE_DIRECTIONS.iter() {
+                let ax = AxialCoord::from(
+                    cube.clone()
+                        + Cube {
+                            x: *x,
+                            y: *y,
+                            z: *z,
+                        },
+                );
+                if let Some(idx) = index.get(&(ax.q, ax.r)) {
+                    neighbours[i].insert(idx);
+                }
+            }
+            // Cells at distance two are found by offset rather than through
+            // neighbours, so a jump can cross a hole.
+            for dq in -2i32..=2 {
+                for dr in -2i32..=2 {
+                    if dq.abs().max(dr.abs()).max((dq + dr).abs()) != 2 {
+                        continue;
+                    }
+                    if let Some(idx) = index.get(&(pt.0 + dq, pt.1 + dr)) {
+                        jumps[i].insert(idx);
+                    }
+                }
+            }
+        }
+        let reach = neighbours
+            .iter()
+            .zip(jumps.iter())
+            .map(|(near, far)| {
+                let mut set = near.clone();
+                set.union_with(far);
+                set
+            })
+            .collect();
+
+        Self {
+            cells,
+            blocked,
+            index,
+            neighbours,
+            jumps,
+            reach,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cells(&self) -> &[Point] {
+        &self.cells
+    }
+
+    /// Holes inside the board, sorted.
+    pub fn blocked(&self) -> &[Point] {
+        &self.blocked
+    }
+
+    pub fn point(&self, idx: usize) -> Point {
+        self.cells[idx]
+    }
+
+    pub fn index(&self, pt: &Point) -> Option<usize> {
+        self.index.get(pt)
+    }
+
+    /// Cells at distance one.
+    pub fn neighbours(&self, idx: usize) -> &CellSet {
+        &self.neighbours[idx]
+    }
+
+    /// Cells at distance exactly two.
+    pub fn jumps(&self, idx: usize) -> &CellSet {
+        &self.jumps[idx]
+    }
+
+    /// Cells at distance one or two.
+    pub fn reach(&self, idx: usize) -> &CellSet {
+        &self.reach[idx]
+    }
+
+    pub fn empty_set(&self) -> CellSet {
+        CellSet::with_capacity(self.cells.len())
+    }
+}
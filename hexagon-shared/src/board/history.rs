@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Board, Point};
+use crate::{
+    colors::colors::Color,
+    structures::{GameSettings, Move},
+};
+
+/// What a player did with their turn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TurnAction {
+    Move(Move),
+    /// The turn passed without a move, after the clock ran out.
+    Pass,
+    /// The player left the game and their pieces were removed.
+    Forfeit,
+}
+
+/// One entry of the move log of a game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MoveRecord {
+    pub color: Color,
+    pub action: TurnAction,
+    /// Cells taken from other players by the move.
+    pub flipped: Vec<Point>,
+    /// Milliseconds since the game started.
+    pub time: u64,
+}
+
+/// The starting board of a game and everything played since.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameHistory {
+    pub start: Board,
+    /// Settings the game was started with.
+    #[serde(default)]
+    pub settings: GameSettings,
+    pub moves: Vec<MoveRecord>,
+}
+
+impl MoveRecord {
+    /// Plays the record on `board` the way the server did, including the
+    /// turn handover. Returns the players whose turn was skipped.
+    pub fn replay(&self, board: &mut Board) -> Vec<Color> {
+        match &self.action {
+            TurnAction::Move(mov) => {
+                board.turn = self.color;
+                board.apply_move(mov);
+                board.end_turn(self.color)
+            }
+            TurnAction::Pass => board.end_turn(self.color),
+            TurnAction::Forfeit => board.forfeit(self.color),
+        }
+    }
+}
+
+impl GameHistory {
+    pub fn new(start: Board, settings: GameSettings) -> Self {
+        Self {
+            start,
+            settings,
+            moves: vec![],
+        }
+    }
+
+    /// The board after the first `moves` records.
+    pub fn board_at(&self, moves: usize) -> Board {
+        let mut board = self.start.clone();
+        for record in self.moves.iter().take(moves) {
+            record.replay(&mut board);
+        }
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_replays_to_the_same_board() {
+        let mut board = Board::generate_hexagon(4, Color::Red, Color::Blue);
+        let mut history = GameHistory::new(board.clone(), GameSettings::default());
+        for _ in 0..6 {
+            let mov = board.legal_moves()[0].clone();
+            let color = board.turn;
+            let flipped = board.captures(&mov);
+            board.apply_move(&mov);
+            board.end_turn(color);
+            history.moves.push(MoveRecord {
+                color,
+                action: TurnAction::Move(mov),
+                flipped,
+                time: 0,
+            });
+        }
+        let color = board.turn;
+        board.forfeit(color);
+        history.moves.push(MoveRecord {
+            color,
+            action: TurnAction::Forfeit,
+            flipped: vec![],
+            time: 0,
+        });
+
+        let replayed = history.board_at(history.moves.len());
+        assert_eq!(replayed.pieces(), board.pieces());
+        assert_eq!(replayed.turn, board.turn);
+        assert_eq!(history.board_at(0).pieces(), history.start.pieces());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+
+use rand::seq::SliceRandom;
+
+use super::{builtin_maps, Board, BoardMap, Point};
+use crate::{
+    colors::colors::Color,
+    models::{AxialCoord, Cube, OffsetCoord},
+    structures::{
+        BoardShape, GameSettings, SettingsError, StartingLayout, MAX_CUSTOM_CELLS,
+        MAX_CUSTOM_COORD, MAX_RADIUS, MIN_RADIUS,
+    },
+};
+
+/// Directions of the six corners of a hexagon, in the order the corners are
+/// handed out to the players.
+const CORNERS: [(i32, i32, i32); 6] = [
+    (1, -1, 0),
+    (1, 0, -1),
+    (0, 1, -1),
+    (-1, 1, 0),
+    (-1, 0, 1),
+    (0, -1, 1),
+];
+
+/// Cells of a hexagon with `size` cells along each side.
+pub fn hexagon_points(size: u32) -> Vec<Point> {
+    let size = size as i32;
+    let mut points = vec![];
+    for q in -size + 1..size {
+        for r in -size + 1..size {
+            if (q + r).abs() < size {
+                points.push((q, r));
+            }
+        }
+    }
+    points
+}
+
+/// Cells of a rectangle of `2 * radius - 1` offset rows and columns.
+pub fn honeycomb_points(radius: u32) -> Vec<Point> {
+    let radius = radius as i32;
+    let mut points = vec![];
+    for row in -radius + 1..radius {
+        for col in -radius + 1..radius {
+            let ax = AxialCoord::from(Cube::from(OffsetCoord { row, col }));
+            points.push((ax.q, ax.r));
+        }
+    }
+    points
+}
+
+/// Smallest `max_size` the board view needs to fit `points`.
+fn fitting_size(points: &[Point]) -> u32 {
+    points
+        .iter()
+        .map(|pt| {
+            let off = OffsetCoord::from(AxialCoord::from(*pt));
+            off.row.abs().max(off.col.abs()) as u32 + 1
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+/// Starting cells for `players` players, handed out in turn. The six
+/// corners when they split evenly, otherwise two cells on every edge, a
+/// third of the way in from either end, which are mirror images of each
+/// other. Cells already taken are skipped on boards that aren't hexagons.
+fn corner_points(points: &[Point], players: usize) -> Vec<Point> {
+    let dot = |pt: &Point, (x, y, z): &(i32, i32, i32)| {
+        let cube = Cube::from(AxialCoord::from(*pt));
+        cube.x * x + cube.y * y + cube.z * z
+    };
+    let mut taken: Vec<Point> = vec![];
+    if players == 0 || CORNERS.len().is_multiple_of(players) {
+        for corner in CORNERS.iter() {
+            let best = points
+                .iter()
+                .filter(|pt| !taken.contains(*pt))
+                .max_by_key(|pt| (dot(pt, corner), std::cmp::Reverse(**pt)));
+            if let Some(pt) = best {
+                taken.push(*pt);
+            }
+        }
+        return taken;
+    }
+    for (i, corner) in CORNERS.iter().enumerate() {
+        let next = &CORNERS[(i + 1) % CORNERS.len()];
+        let along = |pt: &Point| dot(pt, corner) + dot(pt, next);
+        let edge = match points
+            .iter()
+            .filter(|pt| !taken.contains(*pt))
+            .map(along)
+            .max()
+        {
+            Some(edge) => edge,
+            None => break,
+        };
+        let mut cells: Vec<Point> = points
+            .iter()
+            .filter(|pt| !taken.contains(*pt) && along(pt) == edge)
+            .copied()
+            .collect();
+        cells.sort_by_key(|pt| (dot(pt, next) - dot(pt, corner), *pt));
+        let third = cells.len() / 3;
+        taken.push(cells[third]);
+        if cells.len() - 1 - third != third {
+            taken.push(cells[cells.len() - 1 - third]);
+        }
+    }
+    taken
+}
+
+impl GameSettings {
+    /// The map these settings describe, named maps are looked up in `maps`.
+    pub fn resolve(&self, maps: &[BoardMap]) -> Result<BoardMap, SettingsError> {
+        let points = match &self.shape {
+            BoardShape::Map(name) => {
+                return maps
+                    .iter()
+                    .find(|map| &map.name == name)
+                    .cloned()
+                    .ok_or_else(|| SettingsError::UnknownMap(name.clone()));
+            }
+            BoardShape::Custom(points) => points.clone(),
+            _ if self.radius < MIN_RADIUS || self.radius > MAX_RADIUS => {
+                return Err(SettingsError::RadiusOutOfRange(self.radius));
+            }
+            BoardShape::Hexagon => hexagon_points(self.radius),
+            BoardShape::Honeycomb => honeycomb_points(self.radius),
+        };
+        Ok(BoardMap {
+            name: String::new(),
+            points,
+            blocked: vec![],
+            layout: self.layout.clone(),
+            author: None,
+            players: None,
+        })
+    }
+
+    /// Checks the settings make a playable board for `players` players,
+    /// named maps are looked up in `maps` as the server announced them.
+    pub fn validate(&self, maps: &[BoardMap], players: usize) -> Result<(), SettingsError> {
+        self.clock.validate()?;
+        self.resolve(maps)?.validate(players)
+    }
+}
+
+impl BoardMap {
+    /// Playable cells, sorted.
+    fn cells(&self) -> Vec<Point> {
+        let mut cells: Vec<Point> = self
+            .points
+            .iter()
+            .filter(|pt| !self.blocked.contains(pt))
+            .copied()
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        cells
+    }
+
+    /// Checks the map makes a playable board for `players` players.
+    pub fn validate(&self, players: usize) -> Result<(), SettingsError> {
+        if players == 0 {
+            return Err(SettingsError::UnknownPlayer(0));
+        }
+        let points = self.cells();
+        if points.len() > MAX_CUSTOM_CELLS {
+            return Err(SettingsError::TooManyCells(points.len()));
+        }
+        if points.is_empty() {
+            return Err(SettingsError::EmptyBoard);
+        }
+        if let Some(pt) = self
+            .points
+            .iter()
+            .chain(self.blocked.iter())
+            .find(|(q, r)| q.abs() > MAX_CUSTOM_COORD || r.abs() > MAX_CUSTOM_COORD)
+        {
+            return Err(SettingsError::CellOutOfRange(*pt));
+        }
+        match &self.layout {
+            StartingLayout::Corners => {
+                let starts = corner_points(&points, players).len();
+                if starts < players {
+                    return Err(SettingsError::TooManyPieces);
+                }
+                if !starts.is_multiple_of(players) {
+                    return Err(SettingsError::UnevenStart);
+                }
+            }
+            StartingLayout::Random(count) => {
+                if *count == 0 {
+                    return Err(SettingsError::PlayerWithoutPieces(0));
+                }
+                if *count as usize * players > points.len() {
+                    return Err(SettingsError::TooManyPieces);
+                }
+            }
+            StartingLayout::Custom(pieces) => {
+                let mut seen = HashSet::new();
+                for (pt, player) in pieces {
+                    if points.binary_search(pt).is_err() {
+                        return Err(SettingsError::PieceOffBoard(*pt));
+                    }
+                    if !seen.insert(pt) {
+                        return Err(SettingsError::DuplicatePiece(*pt));
+                    }
+                    if *player >= players {
+                        return Err(SettingsError::UnknownPlayer(*player));
+                    }
+                }
+                if let Some(idx) =
+                    (0..players).find(|idx| pieces.iter().all(|(_, player)| player != idx))
+                {
+                    return Err(SettingsError::PlayerWithoutPieces(idx));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the starting board, the first of `players` moves first.
+    pub fn board(&self, players: &[Color]) -> Result<Board, SettingsError> {
+        self.validate(players.len())?;
+        let mut board = Board::with_blocked(
+            self.points.clone(),
+            self.blocked.clone(),
+            fitting_size(&self.points),
+            players[0],
+            players.to_vec(),
+        );
+        let points = board.points().to_vec();
+        match &self.layout {
+            StartingLayout::Corners => {
+                for (i, pt) in corner_points(&points, players.len())
+                    .into_iter()
+                    .enumerate()
+                {
+                    board.set_piece(pt, Some(players[i % players.len()]));
+                }
+            }
+            StartingLayout::Random(count) => {
+                let mut cells = points;
+                cells.shuffle(&mut rand::thread_rng());
+                let mut cells = cells.into_iter();
+                for _ in 0..*count {
+                    for color in players {
+                        if let Some(pt) = cells.next() {
+                            board.set_piece(pt, Some(*color));
+                        }
+                    }
+                }
+            }
+            StartingLayout::Custom(pieces) => {
+                for (pt, player) in pieces {
+                    board.set_piece(*pt, Some(players[*player]));
+                }
+            }
+        }
+        Ok(board)
+    }
+}
+
+impl Board {
+    /// Builds the starting board described by `settings` using the built-in
+    /// maps, the first of `players` moves first.
+    pub fn from_settings(
+        settings: &GameSettings,
+        players: &[Color],
+    ) -> Result<Self, SettingsError> {
+        settings.resolve(&builtin_maps())?.board(players)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings_match_classic_board() {
+        let players = [Color::Red, Color::Blue];
+        let board = Board::from_settings(&GameSettings::default(), &players).unwrap();
+        let classic = Board::generate_hexagon(6, Color::Red, Color::Blue);
+        assert_eq!(board.points(), classic.points());
+        assert_eq!(board.pieces(), classic.pieces());
+        assert_eq!(board.max_size, classic.max_size);
+    }
+
+    #[test]
+    fn honeycomb_corners_are_distinct() {
+        let settings = GameSettings {
+            radius: 4,
+            shape: BoardShape::Honeycomb,
+            layout: StartingLayout::Corners,
+            ..GameSettings::default()
+        };
+        let board = Board::from_settings(&settings, &[Color::Red, Color::Blue]).unwrap();
+        assert_eq!(
+            board.piece_counts(),
+            vec![(Color::Red, 3), (Color::Blue, 3)]
+        );
+    }
+
+    #[test]
+    fn every_player_count_gets_even_corners() {
+        let colors = [Color::Red, Color::Blue, Color::Green, Color::Yellow];
+        for players in 2..=colors.len() {
+            let board = Board::from_settings(&GameSettings::default(), &colors[..players]).unwrap();
+            let counts = board.piece_counts();
+            assert_eq!(counts.len(), players);
+            assert!(counts.iter().all(|(_, count)| *count == counts[0].1));
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let players = [Color::Red, Color::Blue];
+        let maps = builtin_maps();
+        let settings = GameSettings {
+            radius: MAX_RADIUS + 1,
+            ..GameSettings::default()
+        };
+        assert_eq!(
+            settings.validate(&maps, 2),
+            Err(SettingsError::RadiusOutOfRange(MAX_RADIUS + 1))
+        );
+        let settings = GameSettings {
+            radius: 3,
+            layout: StartingLayout::Random(10),
+            ..GameSettings::default()
+        };
+        assert_eq!(
+            settings.validate(&maps, 2),
+            Err(SettingsError::TooManyPieces)
+        );
+        let settings = GameSettings {
+            shape: BoardShape::Map("Nowhere".into()),
+            ..GameSettings::default()
+        };
+        assert_eq!(
+            settings.validate(&maps, 2),
+            Err(SettingsError::UnknownMap("Nowhere".into()))
+        );
+        let loaded = BoardMap {
+            name: "Nowhere".into(),
+            ..GameSettings::default().resolve(&maps).unwrap()
+        };
+        assert_eq!(settings.validate(&[loaded], 2), Ok(()));
+        let settings = GameSettings {
+            layout: StartingLayout::Custom(vec![((0, 0), 0), ((20, 0), 1)]),
+            ..GameSettings::default()
+        };
+        assert_eq!(
+            Board::from_settings(&settings, &players).err(),
+            Some(SettingsError::PieceOffBoard((20, 0)))
+        );
+        let settings = GameSettings {
+            layout: StartingLayout::Custom(vec![((0, 0), 0), ((1, 0), 0)]),
+            ..GameSettings::default()
+        };
+        assert_eq!(
+            settings.validate(&maps, 2),
+            Err(SettingsError::PlayerWithoutPieces(1))
+        );
+        let settings = GameSettings {
+            shape: BoardShape::Custom(vec![(0, 0), (1, 0), (60000, 60000)]),
+            layout: StartingLayout::Random(1),
+            ..GameSettings::default()
+        };
+        assert_eq!(
+            settings.validate(&maps, 2),
+            Err(SettingsError::CellOutOfRange((60000, 60000)))
+        );
+        let mut settings = GameSettings::default();
+        settings.clock.total = Some(1);
+        assert_eq!(
+            settings.validate(&maps, 2),
+            Err(SettingsError::ClockTooShort(1))
+        );
+    }
+
+    #[test]
+    fn builtin_maps_are_playable() {
+        for map in builtin_maps() {
+            let board = map.board(&[Color::Red, Color::Blue]).unwrap();
+            assert_eq!(board.blocked().len(), map.blocked.len(), "{}", map.name);
+            assert!(board.pieces().iter().all(|(pt, _)| !board.is_blocked(pt)));
+        }
+    }
+}
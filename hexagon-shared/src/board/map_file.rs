@@ -0,0 +1,375 @@
+//! Text format for [`BoardMap`]s.
+//!
+//! A map file is a header of `key: value` lines, a `---` separator and the
+//! board drawn in ASCII:
+//!
+//! ```text
+//! name: Crater
+//! author: someone
+//! players: 2
+//! ---
+//!     1 . . 2
+//!    . . . . .
+//!   . . # # . .
+//!  2 . # # # . 1
+//!   . . # # . .
+//!    . . . . .
+//!     1 . . 2
+//! ```
+//!
+//! Each line is a row of the board and neighbouring cells in a row are two
+//! characters apart, so the rows above and below sit half a cell to the side.
+//! `.` is an empty cell, `#` a hole and the digits `1` to `9` a starting piece
+//! of that player. Maps without pieces start from the corners, or take
+//! `layout: random <count>` for random pieces. The board is centred when
+//! parsed.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{BoardMap, Point};
+use crate::structures::{SettingsError, StartingLayout};
+
+pub(super) const SEPARATOR: &str = "---";
+const EMPTY: char = '.';
+const BLOCKED: char = '#';
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MapError {
+    MissingSeparator,
+    MissingName,
+    UnknownKey(String),
+    InvalidValue(String),
+    /// Character and line of a cell that isn't part of the format.
+    UnknownCell(char, usize),
+    /// Line and column of a cell that is not on the grid of the first cell.
+    MisalignedCell(usize, usize),
+    Invalid(SettingsError),
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MapError::MissingSeparator => write!(f, "Missing {} before the board", SEPARATOR),
+            MapError::MissingName => write!(f, "Map has no name"),
+            MapError::UnknownKey(key) => write!(f, "Unknown key {}", key),
+            MapError::InvalidValue(key) => write!(f, "Invalid value for {}", key),
+            MapError::UnknownCell(c, line) => write!(f, "Unknown cell {:?} on line {}", c, line),
+            MapError::MisalignedCell(line, col) => {
+                write!(f, "Cell on line {} column {} is off the grid", line, col)
+            }
+            MapError::Invalid(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<SettingsError> for MapError {
+    fn from(err: SettingsError) -> Self {
+        MapError::Invalid(err)
+    }
+}
+
+impl FromStr for BoardMap {
+    type Err = MapError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        BoardMap::parse(text)
+    }
+}
+
+impl BoardMap {
+    /// Parses and validates a map file.
+    pub fn parse(text: &str) -> Result<Self, MapError> {
+        let mut lines = text.lines().enumerate();
+
+        let mut name = None;
+        let mut author = None;
+        let mut players = None;
+        let mut random = None;
+        let mut separated = false;
+        for (_, line) in &mut lines {
+            let line = line.trim();
+            if line == SEPARATOR {
+                separated = true;
+                break;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(':') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return Err(MapError::InvalidValue(line.to_string())),
+            };
+            match key {
+                "name" if !value.is_empty() => name = Some(value.to_string()),
+                "author" => author = Some(value.to_string()),
+                "players" => {
+                    players = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| MapError::InvalidValue(key.to_string()))?,
+                    )
+                }
+                "layout" => {
+                    random = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                        ["corners"] => None,
+                        ["random", count] => Some(
+                            count
+                                .parse::<u32>()
+                                .map_err(|_| MapError::InvalidValue(key.to_string()))?,
+                        ),
+                        _ => return Err(MapError::InvalidValue(key.to_string())),
+                    }
+                }
+                "name" => return Err(MapError::MissingName),
+                _ => return Err(MapError::UnknownKey(key.to_string())),
+            }
+        }
+        if !separated {
+            return Err(MapError::MissingSeparator);
+        }
+        let name = name.ok_or(MapError::MissingName)?;
+
+        let Drawing {
+            points,
+            blocked,
+            pieces,
+        } = Drawing::parse(lines)?;
+        let layout = if !pieces.is_empty() {
+            StartingLayout::Custom(pieces)
+        } else if let Some(count) = random {
+            StartingLayout::Random(count)
+        } else {
+            StartingLayout::Corners
+        };
+
+        let map = BoardMap {
+            name,
+            points,
+            blocked,
+            layout,
+            author,
+            players,
+        };
+        map.validate(map.slots())?;
+        Ok(map)
+    }
+
+    /// Number of players the map is checked against, the recommended count
+    /// or else the highest player with starting pieces.
+    fn slots(&self) -> usize {
+        let used = match &self.layout {
+            StartingLayout::Custom(pieces) => {
+                pieces.iter().map(|(_, slot)| slot + 1).max().unwrap_or(0)
+            }
+            _ => 0,
+        };
+        self.players
+            .map(|p| p as usize)
+            .unwrap_or(used)
+            .max(used)
+            .max(2)
+    }
+
+    /// Draws the map in the format read by [`BoardMap::parse`].
+    pub fn to_ascii(&self) -> String {
+        let mut text = format!("name: {}\n", self.name);
+        if let Some(author) = &self.author {
+            text.push_str(&format!("author: {}\n", author));
+        }
+        if let Some(players) = self.players {
+            text.push_str(&format!("players: {}\n", players));
+        }
+        if let StartingLayout::Random(count) = self.layout {
+            text.push_str(&format!("layout: random {}\n", count));
+        }
+        text.push_str(SEPARATOR);
+        text.push('\n');
+
+        let pieces = match &self.layout {
+            StartingLayout::Custom(pieces) => pieces.clone(),
+            _ => vec![],
+        };
+        text.push_str(&draw(&self.points, &self.blocked, &pieces));
+        text
+    }
+}
+
+/// Cells of a board drawn in ASCII, the part of a map file after the
+/// separator.
+pub(super) struct Drawing {
+    /// Every cell, holes included, sorted.
+    pub points: Vec<Point>,
+    pub blocked: Vec<Point>,
+    /// Numbered pieces by player index, sorted.
+    pub pieces: Vec<(Point, usize)>,
+}
+
+impl Drawing {
+    /// Reads rows from `lines` until they run out or a separator, the board
+    /// is centred. Line numbers in errors count from one.
+    pub fn parse<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Self, MapError> {
+        let mut points = vec![];
+        let mut blocked = vec![];
+        let mut pieces = vec![];
+        let mut parity = None;
+        let rows = lines.take_while(|(_, line)| line.trim() != SEPARATOR);
+        for (row, (lineno, line)) in rows.enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                if c.is_whitespace() {
+                    continue;
+                }
+                let (r, x) = (row as i32, col as i32);
+                let parity = *parity.get_or_insert((x - r).rem_euclid(2));
+                if (x - r).rem_euclid(2) != parity {
+                    return Err(MapError::MisalignedCell(lineno + 1, col + 1));
+                }
+                let pt = ((x - r - parity) / 2, r);
+                match c {
+                    EMPTY => {}
+                    BLOCKED => blocked.push(pt),
+                    '1'..='9' => pieces.push((pt, c as usize - '1' as usize)),
+                    _ => return Err(MapError::UnknownCell(c, lineno + 1)),
+                }
+                points.push(pt);
+            }
+        }
+
+        let (dq, dr) = centre_offset(&points);
+        let shift = |(q, r): Point| (q + dq, r + dr);
+        let mut points: Vec<Point> = points.into_iter().map(shift).collect();
+        points.sort_unstable();
+        let mut blocked: Vec<Point> = blocked.into_iter().map(shift).collect();
+        blocked.sort_unstable();
+        let mut pieces: Vec<(Point, usize)> = pieces
+            .into_iter()
+            .map(|(pt, slot)| (shift(pt), slot))
+            .collect();
+        pieces.sort_unstable();
+        Ok(Self {
+            points,
+            blocked,
+            pieces,
+        })
+    }
+}
+
+/// Draws `points` in the format read by [`Drawing::parse`], one line per row.
+pub(super) fn draw(points: &[Point], blocked: &[Point], pieces: &[(Point, usize)]) -> String {
+    let blocked: HashSet<&Point> = blocked.iter().collect();
+    let column = |(q, r): &Point| 2 * q + r;
+    let min_x = points.iter().map(column).min().unwrap_or(0);
+    let min_r = points.iter().map(|pt| pt.1).min().unwrap_or(0);
+    let max_r = points.iter().map(|pt| pt.1).max().unwrap_or(-1);
+    let mut text = String::new();
+    for r in min_r..=max_r {
+        let mut row: Vec<(i32, char)> = points
+            .iter()
+            .filter(|pt| pt.1 == r)
+            .map(|pt| {
+                let c = if blocked.contains(pt) {
+                    BLOCKED
+                } else if let Some((_, slot)) = pieces.iter().find(|(p, _)| p == pt) {
+                    std::char::from_digit(*slot as u32 + 1, 10).unwrap_or(EMPTY)
+                } else {
+                    EMPTY
+                };
+                (column(pt) - min_x, c)
+            })
+            .collect();
+        row.sort_unstable();
+        let mut line = String::new();
+        for (x, c) in row {
+            while (line.len() as i32) < x {
+                line.push(' ');
+            }
+            line.push(c);
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+/// Offset that moves the middle of `points` to the origin.
+fn centre_offset(points: &[Point]) -> Point {
+    if points.is_empty() {
+        return (0, 0);
+    }
+    let min_r = points.iter().map(|pt| pt.1).min().unwrap_or(0);
+    let max_r = points.iter().map(|pt| pt.1).max().unwrap_or(0);
+    let dr = -(min_r + max_r).div_euclid(2);
+    let column = |(q, r): &Point| 2 * q + r + dr;
+    let min_x = points.iter().map(column).min().unwrap_or(0);
+    let max_x = points.iter().map(column).max().unwrap_or(0);
+    let dq = -(min_x + max_x).div_euclid(4);
+    (dq, dr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::builtin_maps, colors::colors::Color};
+
+    const CRATER: &str = "name: Crater
+author: someone
+players: 2
+---
+   1 . . 2
+  . . . . .
+ . . # # . .
+2 . # # # . 1
+ . . # # . .
+  . . . . .
+   1 . . 2
+";
+
+    #[test]
+    fn map_roundtrips_through_ascii() {
+        let map: BoardMap = CRATER.parse().unwrap();
+        assert_eq!(map.blocked.len(), 7);
+        assert_eq!(map.to_ascii(), CRATER);
+        let board = map.board(&[Color::Red, Color::Blue]).unwrap();
+        assert_eq!(board.points().len(), 30);
+        assert_eq!(
+            board.piece_counts(),
+            vec![(Color::Red, 3), (Color::Blue, 3)]
+        );
+        assert_eq!(board.piece_at(&(0, 0)), None);
+    }
+
+    #[test]
+    fn builtin_maps_roundtrip_through_ascii() {
+        for map in builtin_maps() {
+            let parsed = BoardMap::parse(&map.to_ascii()).unwrap();
+            let mut blocked = map.blocked.clone();
+            blocked.sort_unstable();
+            assert_eq!(parsed.points, map.points, "{}", map.name);
+            assert_eq!(parsed.blocked, blocked, "{}", map.name);
+            assert_eq!(parsed.to_ascii(), map.to_ascii());
+        }
+    }
+
+    #[test]
+    fn broken_maps_are_rejected() {
+        assert_eq!(
+            BoardMap::parse("name: a\n"),
+            Err(MapError::MissingSeparator)
+        );
+        assert_eq!(BoardMap::parse("---\n. .\n"), Err(MapError::MissingName));
+        assert_eq!(
+            BoardMap::parse("name: a\n---\n. .\n x\n"),
+            Err(MapError::UnknownCell('x', 4))
+        );
+        assert_eq!(
+            BoardMap::parse("name: a\n---\n. .\n. .\n"),
+            Err(MapError::MisalignedCell(4, 1))
+        );
+        assert_eq!(
+            BoardMap::parse("name: a\n---\n1 . .\n . . .\n"),
+            Err(MapError::Invalid(SettingsError::PlayerWithoutPieces(1)))
+        );
+    }
+}
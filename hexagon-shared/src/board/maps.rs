@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use super::{hexagon_points, Point};
+use crate::structures::StartingLayout;
+
+/// A named board with its holes and starting pieces.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardMap {
+    pub name: String,
+    /// Every cell of the board, holes included.
+    pub points: Vec<Point>,
+    pub blocked: Vec<Point>,
+    pub layout: StartingLayout,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Number of players the map was made for.
+    #[serde(default)]
+    pub players: Option<u32>,
+}
+
+/// Maps every lobby can pick without the server loading anything.
+pub fn builtin_maps() -> Vec<BoardMap> {
+    vec![
+        BoardMap {
+            name: "Classic".into(),
+            points: hexagon_points(5),
+            blocked: vec![(1, 0), (0, -1), (-1, 1)],
+            layout: StartingLayout::Corners,
+            author: None,
+            players: Some(2),
+        },
+        BoardMap {
+            name: "Donut".into(),
+            points: hexagon_points(6),
+            blocked: vec![(0, 0), (1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)],
+            layout: StartingLayout::Corners,
+            author: None,
+            players: Some(2),
+        },
+        BoardMap {
+            name: "Scattered".into(),
+            points: hexagon_points(6),
+            blocked: vec![(3, 0), (3, -3), (0, -3), (-3, 0), (-3, 3), (0, 3)],
+            layout: StartingLayout::Corners,
+            author: None,
+            players: Some(2),
+        },
+    ]
+}
+
+/// The built-in map called `name`.
+pub fn builtin_map(name: &str) -> Option<BoardMap> {
+    builtin_maps().into_iter().find(|map| map.name == name)
+}
@@ -1,4 +1,11 @@
-use std::{collections::HashMap, hash::Hash, ops::Index};
+mod geometry;
+mod history;
+mod layout;
+mod map_file;
+mod maps;
+mod record;
+
+use std::sync::Arc;
 
 use crate::{colors::colors::Color, models::*, structures::Move};
 use itertools::Itertools;
@@ -6,6 +13,13 @@ use rand::seq::SliceRandom;
 
 use serde::{Deserialize, Serialize};
 
+pub use geometry::{CellSet, Geometry};
+pub use history::{GameHistory, MoveRecord, TurnAction};
+pub use layout::{hexagon_points, honeycomb_points};
+pub use map_file::MapError;
+pub use maps::{builtin_map, builtin_maps, BoardMap};
+pub use record::{GameRecord, Notation, RecordError};
+
 pub type Point = (i32, i32);
 
 impl From<Point> for AxialCoord {
@@ -20,12 +34,88 @@ impl From<AxialCoord> for Point {
     }
 }
 
+/// Board cells are indexed once into a shared [`Geometry`] and the pieces of
+/// every color are kept as a [`CellSet`] over those indices. Read cells and
+/// pieces with [`Board::points`], [`Board::piece_at`] and
+/// [`Board::iter_pieces`], change them with [`Board::set_piece`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "BoardData", into = "BoardData")]
 pub struct Board {
-    pub points: HashMap<Point, AxialCoord>,
+    geometry: Arc<Geometry>,
     pub max_size: u32,
     pub turn: Color,
-    pub pieces: HashMap<Point, Color>,
+    occupancy: Vec<(Color, CellSet)>,
+    /// Seating order, turns go round in this order.
+    pub players: Vec<Color>,
+    /// Players who lost all their pieces, in the order they went out.
+    pub eliminated: Vec<Color>,
+    /// Players grouped in teams, empty when everyone plays for themselves.
+    pub teams: Vec<Vec<Color>>,
+    /// Whether moves capture pieces of teammates too.
+    pub friendly_capture: bool,
+}
+
+/// Wire format of [`Board`], points and pieces are sent as plain lists.
+#[derive(Serialize, Deserialize)]
+struct BoardData {
+    points: Vec<Point>,
+    max_size: u32,
+    turn: Color,
+    pieces: Vec<(Point, Color)>,
+    #[serde(default)]
+    players: Vec<Color>,
+    #[serde(default)]
+    blocked: Vec<Point>,
+    #[serde(default)]
+    eliminated: Vec<Color>,
+    #[serde(default)]
+    teams: Vec<Vec<Color>>,
+    #[serde(default)]
+    friendly_capture: bool,
+}
+
+impl From<Board> for BoardData {
+    fn from(board: Board) -> Self {
+        Self {
+            points: board.points().to_vec(),
+            pieces: board.pieces(),
+            max_size: board.max_size,
+            turn: board.turn,
+            blocked: board.blocked().to_vec(),
+            players: board.players,
+            eliminated: board.eliminated,
+            teams: board.teams,
+            friendly_capture: board.friendly_capture,
+        }
+    }
+}
+
+impl From<BoardData> for Board {
+    fn from(data: BoardData) -> Self {
+        let mut board = Board::with_blocked(
+            data.points,
+            data.blocked,
+            data.max_size,
+            data.turn,
+            data.players,
+        );
+        board.eliminated = data.eliminated;
+        board.teams = data.teams;
+        board.friendly_capture = data.friendly_capture;
+        for (pt, color) in data.pieces {
+            board.set_piece(pt, Some(color));
+        }
+        board
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Outcome {
+    InProgress,
+    Win(Color),
+    /// The team at this index of [`Board::teams`] won.
+    TeamWin(usize),
+    Draw,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -36,13 +126,43 @@ pub enum Axis {
 }
 
 impl Board {
+    /// An empty board over `points`.
+    pub fn new(
+        points: impl IntoIterator<Item = Point>,
+        max_size: u32,
+        turn: Color,
+        players: Vec<Color>,
+    ) -> Self {
+        Self::with_blocked(points, vec![], max_size, turn, players)
+    }
+
+    /// An empty board over `points` with holes at the `blocked` points.
+    pub fn with_blocked(
+        points: impl IntoIterator<Item = Point>,
+        blocked: impl IntoIterator<Item = Point>,
+        max_size: u32,
+        turn: Color,
+        players: Vec<Color>,
+    ) -> Self {
+        Self {
+            geometry: Arc::new(Geometry::new(points, blocked)),
+            max_size,
+            turn,
+            occupancy: vec![],
+            players,
+            eliminated: vec![],
+            teams: vec![],
+            friendly_capture: false,
+        }
+    }
+
     pub fn generate_hexagon(size: u32, first_turn: Color, second_color: Color) -> Self {
-        let mut points = HashMap::new();
+        let mut points = vec![];
 
-        for axis1 in vec![Axis::X, Axis::Y, Axis::Z] {
+        for axis1 in [Axis::X, Axis::Y, Axis::Z].iter().copied() {
             for iu in 0..size as i32 {
                 for i in [-iu, iu].iter() {
-                    for axis2 in vec![Axis::X, Axis::Y, Axis::Z] {
+                    for axis2 in [Axis::X, Axis::Y, Axis::Z].iter().copied() {
                         if axis1 == axis2 {
                             continue;
                         }
@@ -78,84 +198,82 @@ impl Board {
                             }
 
                             let axial = AxialCoord::from(cube);
-                            let point = (axial.q, axial.r);
-                            points.entry(point).or_insert(axial);
+                            points.push((axial.q, axial.r));
                         }
                     }
                 }
             }
         }
 
-        let mut pieces = HashMap::new();
-        for i in [0, 1, 2].into_iter() {
+        let mut pieces = vec![];
+        for i in [0, 1, 2].iter() {
             let c1 = *i;
             let c2 = (*i + 1) % 3;
             let c3 = (*i + 2) % 3;
 
             let size = size - 1;
-            pieces
-                .entry(
-                    AxialCoord::from(Cube {
-                        x: if c1 == 0 {
-                            size as i32
-                        } else if c1 == 1 {
-                            -(size as i32)
-                        } else {
-                            0
-                        },
-                        y: if c2 == 0 {
-                            size as i32
-                        } else if c2 == 1 {
-                            -(size as i32)
-                        } else {
-                            0
-                        },
-                        z: if c3 == 0 {
-                            size as i32
-                        } else if c3 == 1 {
-                            -(size as i32)
-                        } else {
-                            0
-                        },
-                    })
-                    .into(),
-                )
-                .or_insert(first_turn);
-            pieces
-                .entry(
-                    AxialCoord::from(Cube {
-                        x: if c1 == 0 {
-                            size as i32
-                        } else if c1 == 2 {
-                            -(size as i32)
-                        } else {
-                            0
-                        },
-                        y: if c2 == 0 {
-                            size as i32
-                        } else if c2 == 2 {
-                            -(size as i32)
-                        } else {
-                            0
-                        },
-                        z: if c3 == 0 {
-                            size as i32
-                        } else if c3 == 2 {
-                            -(size as i32)
-                        } else {
-                            0
-                        },
-                    })
-                    .into(),
-                )
-                .or_insert(second_color);
+            pieces.push((
+                AxialCoord::from(Cube {
+                    x: if c1 == 0 {
+                        size as i32
+                    } else if c1 == 1 {
+                        -(size as i32)
+                    } else {
+                        0
+                    },
+                    y: if c2 == 0 {
+                        size as i32
+                    } else if c2 == 1 {
+                        -(size as i32)
+                    } else {
+                        0
+                    },
+                    z: if c3 == 0 {
+                        size as i32
+                    } else if c3 == 1 {
+                        -(size as i32)
+                    } else {
+                        0
+                    },
+                })
+                .into(),
+                first_turn,
+            ));
+            pieces.push((
+                AxialCoord::from(Cube {
+                    x: if c1 == 0 {
+                        size as i32
+                    } else if c1 == 2 {
+                        -(size as i32)
+                    } else {
+                        0
+                    },
+                    y: if c2 == 0 {
+                        size as i32
+                    } else if c2 == 2 {
+                        -(size as i32)
+                    } else {
+                        0
+                    },
+                    z: if c3 == 0 {
+                        size as i32
+                    } else if c3 == 2 {
+                        -(size as i32)
+                    } else {
+                        0
+                    },
+                })
+                .into(),
+                second_color,
+            ));
         }
-        Self {
-            points,
-            max_size: size,
-            turn: first_turn,
-            pieces,
+        let mut board = Self::new(points, size, first_turn, vec![first_turn, second_color]);
+        for (pt, color) in pieces {
+            if board.piece_at(&pt).is_none() {
+                board.set_piece(pt, Some(color));
+            }
         }
+        board
     }
 
     pub fn generate_honeycomb(
@@ -165,132 +283,694 @@ impl Board {
         first_turn: Color,
         second_color: Color,
     ) -> Self {
-        let mut points = HashMap::new();
+        let mut points = vec![];
         for i in -width..width {
             for j in -height - 2..height + 2 {
                 let ax = AxialCoord::from(Cube::from(OffsetCoord { row: j, col: i }));
-                points.insert((ax.q, ax.r), ax);
+                points.push((ax.q, ax.r));
             }
         }
-        let random_colors = 5;
-
-        let mut pieces = HashMap::new();
-        for i in 0..fill_per_color {
+        let mut board = Self::new(
+            points,
+            width as u32,
+            first_turn,
+            vec![first_turn, second_color],
+        );
+        for _ in 0..fill_per_color {
             let colors = vec![first_turn, second_color];
             for color in colors {
-                let rp = points
-                    .iter()
-                    .filter(|(p, c)| !pieces.contains_key(*p))
-                    .collect_vec();
+                let rp = board.empty_points();
                 let rp = rp.choose(&mut rand::thread_rng());
                 if let Some(p) = rp {
-                    pieces.insert(*p.0, color);
+                    board.set_piece(*p, Some(color));
                 }
             }
         }
-        Self {
-            points,
-            pieces,
-            turn: first_turn,
-            max_size: width as u32,
+        board
+    }
+
+    /// Every playable cell of the board.
+    pub fn points(&self) -> &[Point] {
+        self.geometry.cells()
+    }
+
+    /// Holes in the board, they are never part of a move.
+    pub fn blocked(&self) -> &[Point] {
+        self.geometry.blocked()
+    }
+
+    pub fn is_blocked(&self, point: &Point) -> bool {
+        self.geometry.blocked().binary_search(point).is_ok()
+    }
+
+    pub fn contains_point(&self, point: &Point) -> bool {
+        self.geometry.index(point).is_some()
+    }
+
+    pub fn piece_at(&self, point: &Point) -> Option<Color> {
+        let idx = self.geometry.index(point)?;
+        self.occupancy
+            .iter()
+            .find(|(_, set)| set.contains(idx))
+            .map(|(color, _)| *color)
+    }
+
+    /// All pieces on the board ordered by point. Allocates, prefer
+    /// [`Board::iter_pieces`] when the order doesn't matter.
+    pub fn pieces(&self) -> Vec<(Point, Color)> {
+        self.iter_pieces().sorted().collect()
+    }
+
+    /// All pieces on the board grouped by color.
+    pub fn iter_pieces(&self) -> impl Iterator<Item = (Point, Color)> + '_ {
+        self.occupancy.iter().flat_map(move |(color, set)| {
+            set.iter()
+                .map(move |idx| (self.geometry.point(idx), *color))
+        })
+    }
+
+    pub fn pieces_of(&self, color: Color) -> Vec<Point> {
+        self.cells_of(color)
+            .map(|set| set.iter().map(|idx| self.geometry.point(idx)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Places or removes the piece on `point`, points outside the board are ignored.
+    pub fn set_piece(&mut self, point: Point, color: Option<Color>) {
+        if let Some(idx) = self.geometry.index(&point) {
+            for (_, set) in self.occupancy.iter_mut() {
+                set.remove(idx);
+            }
+            if let Some(color) = color {
+                self.cells_of_mut(color).insert(idx);
+            }
         }
     }
 
-    pub fn get_neighbours(&self, point: &Point) -> Vec<Point> {
-        let cube_directions = [
-            Cube { x: 1, y: -1, z: 0 },
-            Cube { x: 1, y: 0, z: -1 },
-            Cube { x: 0, y: 1, z: -1 },
-            Cube { x: -1, y: 1, z: 0 },
-            Cube { x: -1, y: 0, z: 1 },
-            Cube { x: 0, y: -1, z: 1 },
-        ];
-        let mut neighbours = vec![];
-        if let Some(pt) = self.points.get(&point) {
-            let cb = Cube::from(pt.clone());
-            for dir in cube_directions.iter() {
-                let neighbour: Cube = cb.clone() + dir.clone();
-                let neighbour_ax = AxialCoord::from(neighbour);
-                let pt = (neighbour_ax.q, neighbour_ax.r);
-                if self.points.contains_key(&pt) {
-                    neighbours.push(pt);
-                }
+    pub fn clear_pieces(&mut self) {
+        self.occupancy.clear();
+    }
+
+    /// Number of pieces on the board.
+    pub fn piece_count(&self) -> usize {
+        self.occupancy.iter().map(|(_, set)| set.len()).sum()
+    }
+
+    /// Piece sets per color, for hashing positions.
+    pub fn occupancy(&self) -> impl Iterator<Item = (Color, &CellSet)> {
+        self.occupancy
+            .iter()
+            .filter(|(_, set)| !set.is_empty())
+            .map(|(color, set)| (*color, set))
+    }
+
+    fn has_pieces(&self, color: Color) -> bool {
+        self.cells_of(color).is_some_and(|set| !set.is_empty())
+    }
+
+    fn cells_of(&self, color: Color) -> Option<&CellSet> {
+        self.occupancy
+            .iter()
+            .find(|(c, _)| *c == color)
+            .map(|(_, set)| set)
+    }
+
+    fn cells_of_mut(&mut self, color: Color) -> &mut CellSet {
+        let pos = match self.occupancy.iter().position(|(c, _)| *c == color) {
+            Some(pos) => pos,
+            None => {
+                self.occupancy.push((color, self.geometry.empty_set()));
+                self.occupancy.len() - 1
             }
+        };
+        &mut self.occupancy[pos].1
+    }
+
+    fn occupied(&self) -> CellSet {
+        let mut occupied = self.geometry.empty_set();
+        for (_, set) in self.occupancy.iter() {
+            occupied.union_with(set);
+        }
+        occupied
+    }
+
+    fn to_points(&self, set: &CellSet) -> Vec<Point> {
+        set.iter().map(|idx| self.geometry.point(idx)).collect()
+    }
+
+    pub fn get_neighbours(&self, point: &Point) -> Vec<Point> {
+        match self.geometry.index(point) {
+            Some(idx) => self.to_points(self.geometry.neighbours(idx)),
+            None => vec![],
         }
-        neighbours
     }
 
+    /// Cells one or two steps away from `point`, the cells a piece can move to.
     pub fn get_secondary_neighbours(&self, point: &Point) -> Vec<Point> {
-        let mut secondaryneighbours = vec![];
-        let neighbourpts = self.get_neighbours(point);
-        for pts in neighbourpts.iter() {
-            secondaryneighbours.append(&mut self.get_neighbours(pts));
+        match self.geometry.index(point) {
+            Some(idx) => self.to_points(self.geometry.reach(idx)),
+            None => vec![],
         }
-        secondaryneighbours
     }
 
     pub fn is_move_legal(&self, mov: &Move) -> bool {
-        if let Some(piece) = self.pieces.get(&mov.from) {
-            if *piece != self.turn {
-                false
-            } else {
-                let secondaryneighbours = self.get_secondary_neighbours(&mov.from);
-                if secondaryneighbours.contains(&mov.to) {
-                    if mov.to == mov.from {
-                        false
-                    } else {
-                        if self.pieces.get(&mov.to).is_some() {
-                            false
-                        } else {
-                            true
-                        }
-                    }
-                } else {
-                    false
-                }
-            }
+        self.move_indices(mov).is_some()
+    }
+
+    /// Cell indices of a legal move.
+    fn move_indices(&self, mov: &Move) -> Option<(usize, usize)> {
+        let from = self.geometry.index(&mov.from)?;
+        let to = self.geometry.index(&mov.to)?;
+        let legal = self
+            .cells_of(self.turn)
+            .map(|set| set.contains(from))
+            .unwrap_or(false)
+            && self.geometry.reach(from).contains(to)
+            && !self.occupancy.iter().any(|(_, set)| set.contains(to));
+        if legal {
+            Some((from, to))
         } else {
-            false
+            None
         }
     }
 
     pub fn get_legal_moves(&self, pt: &Point) -> Vec<Point> {
-        let secn = self.get_secondary_neighbours(pt);
-        secn.into_iter()
-            .filter(|m| self.is_move_legal(&Move { from: *pt, to: *m }))
+        match self.geometry.index(pt) {
+            Some(idx)
+                if self
+                    .cells_of(self.turn)
+                    .map(|set| set.contains(idx))
+                    .unwrap_or(false) =>
+            {
+                let mut targets = self.geometry.reach(idx).clone();
+                targets.difference_with(&self.occupied());
+                self.to_points(&targets)
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Every legal move for the side to move.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let occupied = self.occupied();
+        let own = match self.cells_of(self.turn) {
+            Some(set) => set,
+            None => return vec![],
+        };
+        let mut moves = vec![];
+        for from in own.iter() {
+            let mut targets = self.geometry.reach(from).clone();
+            targets.difference_with(&occupied);
+            let from_pt = self.geometry.point(from);
+            moves.extend(targets.iter().map(|to| Move {
+                from: from_pt,
+                to: self.geometry.point(to),
+            }));
+        }
+        moves
+    }
+
+    /// Legal moves with a single clone per target cell, cloning into a cell
+    /// gives the same position whichever neighbour it came from.
+    pub fn unique_moves(&self) -> Vec<Move> {
+        let occupied = self.occupied();
+        let own = match self.cells_of(self.turn) {
+            Some(set) => set,
+            None => return vec![],
+        };
+        let mut cloned = self.geometry.empty_set();
+        let mut moves = vec![];
+        for from in own.iter() {
+            let mut clones = self.geometry.neighbours(from).clone();
+            clones.difference_with(&occupied);
+            clones.difference_with(&cloned);
+            cloned.union_with(&clones);
+            let mut jumps = self.geometry.jumps(from).clone();
+            jumps.difference_with(&occupied);
+            let from_pt = self.geometry.point(from);
+            moves.extend(clones.iter().chain(jumps.iter()).map(|to| Move {
+                from: from_pt,
+                to: self.geometry.point(to),
+            }));
+        }
+        moves
+    }
+
+    pub fn is_clone_move(&self, mov: &Move) -> bool {
+        match (self.geometry.index(&mov.from), self.geometry.index(&mov.to)) {
+            (Some(from), Some(to)) => self.geometry.neighbours(from).contains(to),
+            _ => false,
+        }
+    }
+
+    /// Cells of other players `mov` would take, teammates are left alone
+    /// unless friendly capture is on.
+    pub fn captures(&self, mov: &Move) -> Vec<Point> {
+        self.get_neighbours(&mov.to)
+            .into_iter()
+            .filter(|pt| match self.piece_at(pt) {
+                Some(color) => {
+                    color != self.turn
+                        && (self.friendly_capture || !self.is_friendly(color, self.turn))
+                }
+                None => false,
+            })
             .collect()
     }
 
     pub fn apply_move(&mut self, mov: &Move) -> bool {
-        if self.is_move_legal(mov) {
-            let neighbour = self.get_neighbours(&mov.from);
-            if neighbour.contains(&mov.to) {
-                self.pieces.insert(mov.to, self.turn);
-                let neighours = self.get_neighbours(&mov.to);
-                for point in neighours.iter() {
-                    if self.pieces.get(point).is_some() {
-                        self.pieces.insert(*point, self.turn);
-                    }
-                }
-                true
-            } else if self.get_secondary_neighbours(&mov.from).contains(&mov.to) {
-                self.pieces.remove(&mov.from);
-                self.pieces.insert(mov.to, self.turn);
-                let neighours = self.get_neighbours(&mov.to);
-                for point in neighours.iter() {
-                    if self.pieces.get(point).is_some() {
-                        self.pieces.insert(*point, self.turn);
-                    }
+        let (from, to) = match self.move_indices(mov) {
+            Some(indices) => indices,
+            None => return false,
+        };
+        let turn = self.turn;
+        let is_clone = self.geometry.neighbours(from).contains(to);
+        let own = self.cells_of_mut(turn);
+        if !is_clone {
+            own.remove(from);
+        }
+        own.insert(to);
+
+        let mut captured = self.geometry.empty_set();
+        let neighbours = self.geometry.neighbours(to).words();
+        for i in 0..self.occupancy.len() {
+            let color = self.occupancy[i].0;
+            if color == turn || (!self.friendly_capture && self.is_friendly(color, turn)) {
+                continue;
+            }
+            let captured = captured.words_mut();
+            let words = self.occupancy[i].1.words_mut();
+            for (w, mask) in neighbours.iter().enumerate() {
+                if *mask != 0 {
+                    captured[w] |= words[w] & mask;
+                    words[w] &= !mask;
                 }
-                true
-            } else {
-                false
             }
-        } else {
-            false
         }
+        self.cells_of_mut(turn).union_with(&captured);
+        true
     }
+
     pub fn change_turn(&mut self, next_color: Color) {
         self.turn = next_color;
     }
+
+    pub fn has_any_legal_move(&self, color: Color) -> bool {
+        let occupied = self.occupied();
+        match self.cells_of(color) {
+            Some(own) => own
+                .iter()
+                .any(|idx| !self.geometry.reach(idx).is_subset(&occupied)),
+            None => false,
+        }
+    }
+
+    pub fn empty_points(&self) -> Vec<Point> {
+        let occupied = self.occupied();
+        (0..self.geometry.len())
+            .filter(|idx| !occupied.contains(*idx))
+            .map(|idx| self.geometry.point(idx))
+            .collect()
+    }
+
+    /// The player after the one to move in the order of `player_colors`,
+    /// players without pieces are skipped.
+    pub fn next_player(&self) -> Color {
+        let colors = self.player_colors();
+        let start = match colors.iter().position(|c| *c == self.turn) {
+            Some(i) => i,
+            None => return self.turn,
+        };
+        (1..=colors.len())
+            .map(|offset| colors[(start + offset) % colors.len()])
+            .find(|color| self.has_pieces(*color))
+            .unwrap_or(self.turn)
+    }
+
+    /// Players that still have pieces, in seating order.
+    pub fn alive_players(&self) -> Vec<Color> {
+        self.player_colors()
+            .into_iter()
+            .filter(|color| self.has_pieces(*color))
+            .collect()
+    }
+
+    /// Hands the turn over after `mover` played and records players who
+    /// lost their last piece. Boxed in players are skipped, once none of the
+    /// opponents of `mover` can move the classic rule fills the board for
+    /// `mover`. Returns the players whose turn was skipped.
+    pub fn end_turn(&mut self, mover: Color) -> Vec<Color> {
+        self.record_eliminated();
+        let mut skipped = vec![];
+        self.turn = mover;
+        for _ in 0..self.player_colors().len() {
+            self.turn = self.next_player();
+            if self.turn == mover || self.has_any_legal_move(self.turn) {
+                break;
+            }
+            skipped.push(self.turn);
+        }
+        let opponents_stuck = self
+            .alive_players()
+            .iter()
+            .filter(|color| !self.is_friendly(**color, mover))
+            .all(|color| skipped.contains(color));
+        if opponents_stuck && !skipped.is_empty() && self.has_pieces(mover) {
+            self.fill_empty(mover);
+        }
+        skipped
+    }
+
+    /// Takes `color` out of the game, their pieces are removed and the turn
+    /// moves on if it was theirs. Returns the players whose turn was skipped.
+    pub fn forfeit(&mut self, color: Color) -> Vec<Color> {
+        let empty = self.geometry.empty_set();
+        if let Some((_, set)) = self.occupancy.iter_mut().find(|(c, _)| *c == color) {
+            *set = empty;
+        }
+        if self.turn == color {
+            self.end_turn(color)
+        } else {
+            self.record_eliminated();
+            vec![]
+        }
+    }
+
+    fn record_eliminated(&mut self) {
+        for color in self.player_colors() {
+            if !self.eliminated.contains(&color) && !self.has_pieces(color) {
+                self.eliminated.push(color);
+            }
+        }
+    }
+
+    /// Classic rule for a player who cannot move: every empty cell goes to
+    /// the opponent. Returns the cells that were filled.
+    pub fn fill_empty(&mut self, color: Color) -> Vec<Point> {
+        let empty = self.empty_points();
+        for pt in empty.iter() {
+            self.set_piece(*pt, Some(color));
+        }
+        empty
+    }
+
+    /// Colors taking part in the game, falls back to the colors on the board
+    /// for boards created without a player list.
+    pub fn player_colors(&self) -> Vec<Color> {
+        if self.players.is_empty() {
+            self.occupancy()
+                .map(|(color, _)| color)
+                .unique()
+                .sorted()
+                .collect()
+        } else {
+            self.players.clone()
+        }
+    }
+
+    pub fn piece_counts(&self) -> Vec<(Color, u32)> {
+        self.player_colors()
+            .into_iter()
+            .map(|color| {
+                let count = self.cells_of(color).map(|set| set.len()).unwrap_or(0);
+                (color, count as u32)
+            })
+            .collect()
+    }
+
+    /// Players from first to last with their piece counts. Players still on
+    /// the board rank by pieces, eliminated players behind them with the last
+    /// one out ranking highest.
+    pub fn rankings(&self) -> Vec<(Color, u32)> {
+        let mut counts = self.piece_counts();
+        counts.sort_by_key(|(color, count)| {
+            let out = self
+                .eliminated
+                .iter()
+                .position(|c| c == color)
+                .map_or(0, |i| self.eliminated.len() - i);
+            (out, std::cmp::Reverse(*count))
+        });
+        counts
+    }
+
+    /// The game is over when the board is full, when only one side has
+    /// pieces left, or when the player to move has no legal move.
+    /// The side with the most pieces wins.
+    pub fn outcome(&self) -> Outcome {
+        let sides = self.sides();
+        let counts: Vec<u32> = sides
+            .iter()
+            .map(|side| {
+                side.iter()
+                    .map(|color| self.cells_of(*color).map_or(0, |set| set.len() as u32))
+                    .sum()
+            })
+            .collect();
+        let alive = counts.iter().filter(|count| **count > 0).count();
+        let full = self.piece_count() >= self.geometry.len();
+        if alive > 1 && !full && self.has_any_legal_move(self.turn) {
+            return Outcome::InProgress;
+        }
+        let best = counts.iter().copied().max().unwrap_or(0);
+        let mut leaders = (0..sides.len()).filter(|i| counts[*i] == best);
+        match (leaders.next(), leaders.next()) {
+            (Some(i), None) if self.teams.is_empty() => Outcome::Win(sides[i][0]),
+            (Some(i), None) => Outcome::TeamWin(i),
+            _ => Outcome::Draw,
+        }
+    }
+
+    pub fn team_of(&self, color: Color) -> Option<usize> {
+        self.teams.iter().position(|team| team.contains(&color))
+    }
+
+    /// Whether `a` and `b` are the same player or teammates.
+    pub fn is_friendly(&self, a: Color, b: Color) -> bool {
+        a == b || (self.team_of(a).is_some() && self.team_of(a) == self.team_of(b))
+    }
+
+    /// Piece count of every team.
+    pub fn team_counts(&self) -> Vec<u32> {
+        self.teams
+            .iter()
+            .map(|team| {
+                team.iter()
+                    .map(|color| self.cells_of(*color).map_or(0, |set| set.len() as u32))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Players that win or lose together, the teams or every player alone.
+    fn sides(&self) -> Vec<Vec<Color>> {
+        if self.teams.is_empty() {
+            self.player_colors()
+                .into_iter()
+                .map(|color| vec![color])
+                .collect()
+        } else {
+            self.teams.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_game_is_in_progress() {
+        let board = Board::generate_hexagon(5, Color::Red, Color::Blue);
+        assert_eq!(board.outcome(), Outcome::InProgress);
+        assert_eq!(
+            board.piece_counts(),
+            vec![(Color::Red, 3), (Color::Blue, 3)]
+        );
+    }
+
+    #[test]
+    fn player_without_pieces_loses() {
+        let mut board = Board::generate_hexagon(5, Color::Red, Color::Blue);
+        for pt in board.pieces_of(Color::Blue) {
+            board.set_piece(pt, None);
+        }
+        assert_eq!(board.outcome(), Outcome::Win(Color::Red));
+    }
+
+    #[test]
+    fn full_board_with_equal_pieces_is_draw() {
+        let hexagon = Board::generate_hexagon(2, Color::Red, Color::Blue);
+        let points = hexagon.points()[1..].to_vec();
+        let mut board = Board::new(points.clone(), 2, Color::Red, hexagon.players);
+        for (i, pt) in points.iter().enumerate() {
+            let color = if i % 2 == 0 { Color::Red } else { Color::Blue };
+            board.set_piece(*pt, Some(color));
+        }
+        assert_eq!(board.outcome(), Outcome::Draw);
+    }
+
+    #[test]
+    fn boxed_in_player_has_no_legal_move() {
+        let mut board = Board::generate_hexagon(3, Color::Red, Color::Blue);
+        assert!(board.has_any_legal_move(Color::Blue));
+        for pt in board.empty_points() {
+            board.set_piece(pt, Some(Color::Red));
+        }
+        assert!(!board.has_any_legal_move(Color::Blue));
+        assert!(board.fill_empty(Color::Red).is_empty());
+    }
+
+    #[test]
+    fn board_roundtrips_through_json() {
+        let board = Board::generate_hexagon(4, Color::Red, Color::Blue);
+        let json = serde_json::to_string(&board).unwrap();
+        let parsed: Board = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.pieces(), board.pieces());
+        assert_eq!(parsed.points(), board.points());
+    }
+
+    #[test]
+    fn neighbours_have_no_duplicates() {
+        let board = Board::generate_hexagon(6, Color::Red, Color::Blue);
+        assert_eq!(board.get_neighbours(&(0, 0)).len(), 6);
+        assert_eq!(board.get_secondary_neighbours(&(0, 0)).len(), 18);
+        assert_eq!(board.get_secondary_neighbours(&(5, 0)).len(), 8);
+    }
+
+    #[test]
+    fn jump_leaves_origin_and_flips_neighbours() {
+        // Big boards keep their cell sets on the heap.
+        for size in [4, 16].iter().copied() {
+            let mut board = Board::generate_hexagon(size, Color::Red, Color::Blue);
+            board.clear_pieces();
+            board.set_piece((0, 0), Some(Color::Red));
+            board.set_piece((2, -1), Some(Color::Blue));
+            board.set_piece((3, -1), Some(Color::Blue));
+            assert!(board.apply_move(&Move {
+                from: (0, 0),
+                to: (2, -2)
+            }));
+            assert_eq!(board.piece_at(&(0, 0)), None);
+            assert_eq!(board.piece_at(&(2, -1)), Some(Color::Red));
+            assert_eq!(board.piece_at(&(3, -1)), Some(Color::Blue));
+            assert_eq!(
+                board.piece_counts(),
+                vec![(Color::Red, 2), (Color::Blue, 1)]
+            );
+            assert!(!board.apply_move(&Move {
+                from: (2, -2),
+                to: (3, -1)
+            }));
+        }
+    }
+
+    #[test]
+    fn jumps_cross_holes_but_never_land_on_them() {
+        let points = hexagon_points(4);
+        let mut board = Board::with_blocked(points, vec![(1, 0)], 4, Color::Red, vec![]);
+        board.set_piece((0, 0), Some(Color::Red));
+        board.set_piece((1, 0), Some(Color::Blue));
+        assert!(board.is_blocked(&(1, 0)));
+        assert_eq!(board.piece_at(&(1, 0)), None);
+        assert!(!board.get_neighbours(&(0, 0)).contains(&(1, 0)));
+        assert!(!board.is_move_legal(&Move {
+            from: (0, 0),
+            to: (1, 0)
+        }));
+        assert!(board.is_move_legal(&Move {
+            from: (0, 0),
+            to: (2, 0)
+        }));
+        let json = serde_json::to_string(&board).unwrap();
+        let parsed: Board = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.blocked(), board.blocked());
+    }
+
+    #[test]
+    fn turns_skip_eliminated_and_boxed_in_players() {
+        let players = vec![Color::Red, Color::Blue, Color::Green];
+        let mut board = Board::new(hexagon_points(4), 4, Color::Red, players);
+        board.set_piece((0, 0), Some(Color::Red));
+        board.set_piece((1, 0), Some(Color::Blue));
+        board.set_piece((3, -3), Some(Color::Green));
+        board.set_piece((-3, 3), Some(Color::Green));
+        assert!(board.apply_move(&Move {
+            from: (0, 0),
+            to: (0, 1)
+        }));
+        assert_eq!(board.end_turn(Color::Red), vec![]);
+        assert_eq!(board.eliminated, vec![Color::Blue]);
+        assert_eq!(board.turn, Color::Green);
+        assert_eq!(board.alive_players(), vec![Color::Red, Color::Green]);
+        assert_eq!(
+            board.rankings(),
+            vec![(Color::Red, 3), (Color::Green, 2), (Color::Blue, 0)]
+        );
+
+        let mut board = Board::new(hexagon_points(3), 3, Color::Red, vec![]);
+        board.players = vec![Color::Red, Color::Blue, Color::Green];
+        for pt in board.points().to_vec() {
+            board.set_piece(pt, Some(Color::Red));
+        }
+        board.set_piece((2, -2), Some(Color::Blue));
+        board.set_piece((-2, 2), Some(Color::Green));
+        board.set_piece((-2, 0), None);
+        assert_eq!(board.end_turn(Color::Red), vec![Color::Blue]);
+        assert_eq!(board.turn, Color::Green);
+        assert_eq!(board.piece_at(&(-2, 0)), None);
+    }
+
+    #[test]
+    fn teammates_are_spared_and_win_together() {
+        let players = vec![Color::Red, Color::Blue, Color::Green, Color::Yellow];
+        let mut board = Board::new(hexagon_points(4), 4, Color::Red, players);
+        board.teams = vec![
+            vec![Color::Red, Color::Green],
+            vec![Color::Blue, Color::Yellow],
+        ];
+        board.set_piece((0, 0), Some(Color::Red));
+        board.set_piece((1, 0), Some(Color::Green));
+        board.set_piece((0, 2), Some(Color::Blue));
+        board.set_piece((3, -3), Some(Color::Yellow));
+        assert!(board.apply_move(&Move {
+            from: (0, 0),
+            to: (0, 1)
+        }));
+        assert_eq!(board.piece_at(&(1, 0)), Some(Color::Green));
+        assert_eq!(board.piece_at(&(0, 2)), Some(Color::Red));
+        assert_eq!(board.team_counts(), vec![4, 1]);
+
+        board.set_piece((3, -3), Some(Color::Green));
+        board.end_turn(Color::Red);
+        assert_eq!(board.outcome(), Outcome::TeamWin(0));
+
+        let players = vec![Color::Red, Color::Blue, Color::Green];
+        let mut board = Board::new(hexagon_points(4), 4, Color::Red, players);
+        board.teams = vec![vec![Color::Red, Color::Green], vec![Color::Blue]];
+        board.friendly_capture = true;
+        board.set_piece((0, 0), Some(Color::Red));
+        board.set_piece((1, 0), Some(Color::Green));
+        board.set_piece((3, -3), Some(Color::Blue));
+        board.apply_move(&Move {
+            from: (0, 0),
+            to: (0, 1),
+        });
+        assert_eq!(board.piece_at(&(1, 0)), Some(Color::Red));
+    }
+
+    #[test]
+    fn forfeiting_player_is_out() {
+        let players = vec![Color::Red, Color::Blue, Color::Green];
+        let mut board = Board::new(hexagon_points(4), 4, Color::Red, players);
+        board.set_piece((0, 0), Some(Color::Red));
+        board.set_piece((2, 0), Some(Color::Blue));
+        board.set_piece((-2, 0), Some(Color::Green));
+        assert_eq!(board.forfeit(Color::Red), vec![]);
+        assert_eq!(board.turn, Color::Blue);
+        assert_eq!(board.eliminated, vec![Color::Red]);
+        assert_eq!(board.piece_at(&(0, 0)), None);
+        board.forfeit(Color::Green);
+        assert_eq!(board.outcome(), Outcome::Win(Color::Blue));
+    }
 }
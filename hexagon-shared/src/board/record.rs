@@ -0,0 +1,623 @@
+//! Text format for played games.
+//!
+//! A game record is a header of `key: value` lines, the starting board drawn
+//! like in a map file and the turns of the game, one per line:
+//!
+//! ```text
+//! red: Alice
+//! blue: Bob
+//! shape: hexagon 3
+//! layout: corners
+//! turn time: 90
+//! on timeout: skip
+//! result: red wins
+//! ---
+//!   1 . 2
+//!  . . . .
+//! 2 . . . 1
+//!  . . . .
+//!   1 . 2
+//! ---
+//! 1. red a2-b2 {2.1}
+//! 2. blue a4-b4 {5.0}
+//! 3. red pass {95.0}
+//! ```
+//!
+//! Players are listed by color in seating order and the digits of the
+//! drawing are their starting pieces. Clock lines are left out when the game
+//! had no clock, `teams: red green / blue yellow` and `friendly capture: yes`
+//! describe team games and `takebacks: no` games where moves could not be
+//! taken back. Cells are named by [`Notation`] and the time after each turn
+//! is in seconds since the game started. Backslashes and control characters
+//! in player names are escaped like in Rust strings, `\\`, `\n` or
+//! `\u{7f}`, so every name fits on its line.
+
+use std::collections::HashMap;
+
+use strum::IntoEnumIterator;
+
+use super::map_file::{draw, Drawing, SEPARATOR};
+use super::{Board, BoardMap, GameHistory, MapError, MoveRecord, Outcome, Point, TurnAction};
+use crate::colors::colors::Color;
+use crate::structures::{
+    BoardShape, GameSettings, Move, SettingsError, StartingLayout, TimeControl, TimeoutAction,
+};
+
+/// Names the cells of a board. Rows are lettered from the top, `a` to `z`
+/// and then `aa`, `ab` and so on, and columns numbered from the left edge of
+/// the board. These are the offset coordinates of the cells as drawn in a map
+/// file, so `c4` is the fourth cell position of the third line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Notation {
+    min_r: i32,
+    min_x: i32,
+}
+
+impl Notation {
+    pub fn new(board: &Board) -> Self {
+        Self::for_points(board.points().iter().chain(board.blocked()))
+    }
+
+    fn for_points<'a>(points: impl Iterator<Item = &'a Point> + Clone) -> Self {
+        Self {
+            min_r: points.clone().map(|pt| pt.1).min().unwrap_or(0),
+            min_x: points.map(|(q, r)| 2 * q + r).min().unwrap_or(0),
+        }
+    }
+
+    pub fn cell(&self, (q, r): &Point) -> String {
+        let mut row = (r - self.min_r) + 1;
+        let mut letters = vec![];
+        while row > 0 {
+            row -= 1;
+            letters.push((b'a' + (row % 26) as u8) as char);
+            row /= 26;
+        }
+        let col = (2 * q + r - self.min_x).div_euclid(2) + 1;
+        format!("{}{}", letters.iter().rev().collect::<String>(), col)
+    }
+
+    /// The cell called `name`, it may not be on the board.
+    pub fn parse_cell(&self, name: &str) -> Option<Point> {
+        let split = name.find(|c: char| c.is_ascii_digit())?;
+        let (letters, digits) = name.split_at(split);
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_lowercase()) {
+            return None;
+        }
+        let row = letters.bytes().try_fold(0i32, |row, c| {
+            row.checked_mul(26)?.checked_add((c - b'a') as i32 + 1)
+        })? - 1;
+        let col = digits.parse::<i32>().ok().filter(|col| *col > 0)? - 1;
+        let r = self.min_r.checked_add(row)?;
+        let x = col.checked_mul(2)?.checked_add(self.min_x)?;
+        let dx = x.checked_sub(r)?;
+        let dx = if dx.rem_euclid(2) == 0 {
+            dx
+        } else {
+            dx.checked_add(1)?
+        };
+        Some((dx / 2, r))
+    }
+
+    /// `from-to` with both cells named.
+    pub fn move_name(&self, mov: &Move) -> String {
+        format!("{}-{}", self.cell(&mov.from), self.cell(&mov.to))
+    }
+
+    pub fn parse_move(&self, text: &str) -> Option<Move> {
+        let pos = text.find('-')?;
+        Some(Move {
+            from: self.parse_cell(&text[..pos])?,
+            to: self.parse_cell(&text[pos + 1..])?,
+        })
+    }
+
+    /// The move, `pass` or `forfeit`.
+    pub fn action(&self, action: &TurnAction) -> String {
+        match action {
+            TurnAction::Move(mov) => self.move_name(mov),
+            TurnAction::Pass => "pass".to_string(),
+            TurnAction::Forfeit => "forfeit".to_string(),
+        }
+    }
+
+    pub fn parse_action(&self, text: &str) -> Option<TurnAction> {
+        match text {
+            "pass" => Some(TurnAction::Pass),
+            "forfeit" => Some(TurnAction::Forfeit),
+            _ => self.parse_move(text).map(TurnAction::Move),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    MissingSeparator,
+    NoPlayers,
+    UnknownKey(String),
+    InvalidValue(String),
+    /// Line of a turn that can't be read.
+    InvalidTurn(usize),
+    /// Number of a turn that can't be played after the turns before it.
+    IllegalTurn(usize),
+    Board(MapError),
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecordError::MissingSeparator => write!(f, "Missing {} before the board", SEPARATOR),
+            RecordError::NoPlayers => write!(f, "Record has no players"),
+            RecordError::UnknownKey(key) => write!(f, "Unknown key {}", key),
+            RecordError::InvalidValue(key) => write!(f, "Invalid value for {}", key),
+            RecordError::InvalidTurn(line) => write!(f, "Can't read the turn on line {}", line),
+            RecordError::IllegalTurn(turn) => write!(f, "Turn {} is not legal", turn),
+            RecordError::Board(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<MapError> for RecordError {
+    fn from(err: MapError) -> Self {
+        RecordError::Board(err)
+    }
+}
+
+impl From<SettingsError> for RecordError {
+    fn from(err: SettingsError) -> Self {
+        RecordError::Board(MapError::Invalid(err))
+    }
+}
+
+/// A played game with the names of its players.
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    pub names: HashMap<Color, String>,
+    pub history: GameHistory,
+    pub result: Outcome,
+}
+
+impl std::str::FromStr for GameRecord {
+    type Err = RecordError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        GameRecord::parse(text)
+    }
+}
+
+impl GameRecord {
+    /// Parses a record and replays its turns, every turn has to be legal.
+    /// Without a `result` line the result is that of the last position.
+    pub fn parse(text: &str) -> Result<Self, RecordError> {
+        let mut lines = text.lines().enumerate();
+
+        let mut players = vec![];
+        let mut names = HashMap::new();
+        let mut settings = GameSettings {
+            clock: TimeControl {
+                turn: None,
+                total: None,
+                on_timeout: TimeoutAction::RandomMove,
+            },
+            ..GameSettings::default()
+        };
+        let mut teams = vec![];
+        let mut friendly_capture = false;
+        let mut result = None;
+        let mut separated = false;
+        for (_, line) in &mut lines {
+            let line = line.trim();
+            if line == SEPARATOR {
+                separated = true;
+                break;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(':') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return Err(RecordError::InvalidValue(line.to_string())),
+            };
+            let invalid = || RecordError::InvalidValue(key.to_string());
+            if let Some(color) = parse_color(key) {
+                players.push(color);
+                names.insert(color, unescape_name(value).ok_or_else(invalid)?);
+                continue;
+            }
+            match key {
+                "shape" => {
+                    settings.shape = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                        ["hexagon", radius] => {
+                            settings.radius = radius.parse().map_err(|_| invalid())?;
+                            BoardShape::Hexagon
+                        }
+                        ["honeycomb", radius] => {
+                            settings.radius = radius.parse().map_err(|_| invalid())?;
+                            BoardShape::Honeycomb
+                        }
+                        ["custom"] => BoardShape::Custom(vec![]),
+                        ["map", ..] => BoardShape::Map(value["map".len()..].trim().to_string()),
+                        _ => return Err(invalid()),
+                    }
+                }
+                "layout" => {
+                    settings.layout = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                        ["corners"] => StartingLayout::Corners,
+                        ["random", count] => {
+                            StartingLayout::Random(count.parse().map_err(|_| invalid())?)
+                        }
+                        ["custom"] => StartingLayout::Custom(vec![]),
+                        _ => return Err(invalid()),
+                    }
+                }
+                "turn time" => settings.clock.turn = Some(value.parse().map_err(|_| invalid())?),
+                "game time" => settings.clock.total = Some(value.parse().map_err(|_| invalid())?),
+                "on timeout" => {
+                    settings.clock.on_timeout = match value {
+                        "random move" => TimeoutAction::RandomMove,
+                        "skip" => TimeoutAction::Skip,
+                        "forfeit" => TimeoutAction::Forfeit,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "teams" => {
+                    teams = value
+                        .split('/')
+                        .map(|team| {
+                            team.split_whitespace()
+                                .map(|color| parse_color(color).ok_or_else(invalid))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                }
+                "friendly capture" => friendly_capture = value == "yes",
+                "takebacks" => settings.takebacks = value == "yes",
+                "result" => result = Some(parse_outcome(value).ok_or_else(invalid)?),
+                _ => return Err(RecordError::UnknownKey(key.to_string())),
+            }
+        }
+        if !separated {
+            return Err(RecordError::MissingSeparator);
+        }
+        if players.is_empty() {
+            return Err(RecordError::NoPlayers);
+        }
+
+        let drawing = Drawing::parse(&mut lines)?;
+        let map = BoardMap {
+            name: String::new(),
+            points: drawing.points,
+            blocked: drawing.blocked,
+            layout: StartingLayout::Custom(drawing.pieces.clone()),
+            author: None,
+            players: None,
+        };
+        let mut start = map.board(&players)?;
+        start.teams = teams;
+        start.friendly_capture = friendly_capture;
+        if let BoardShape::Custom(points) = &mut settings.shape {
+            *points = start.points().to_vec();
+        }
+        if let StartingLayout::Custom(pieces) = &mut settings.layout {
+            *pieces = drawing.pieces;
+        }
+
+        let notation = Notation::new(&start);
+        let mut board = start.clone();
+        let mut history = GameHistory::new(start, settings);
+        for (lineno, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let turn = history.moves.len() + 1;
+            let record =
+                parse_turn(&notation, line, turn).ok_or(RecordError::InvalidTurn(lineno + 1))?;
+            let record = match record.action {
+                TurnAction::Move(mov)
+                    if record.color == board.turn && board.is_move_legal(&mov) =>
+                {
+                    MoveRecord {
+                        flipped: board.captures(&mov),
+                        action: TurnAction::Move(mov),
+                        ..record
+                    }
+                }
+                TurnAction::Pass if record.color == board.turn => record,
+                TurnAction::Forfeit if board.alive_players().contains(&record.color) => record,
+                _ => return Err(RecordError::IllegalTurn(turn)),
+            };
+            record.replay(&mut board);
+            history.moves.push(record);
+        }
+
+        Ok(Self {
+            names,
+            history,
+            result: result.unwrap_or_else(|| board.outcome()),
+        })
+    }
+
+    /// Writes the record in the format read by [`GameRecord::parse`].
+    pub fn to_text(&self) -> String {
+        let start = &self.history.start;
+        let settings = &self.history.settings;
+        let mut text = String::new();
+        for color in &start.players {
+            let name = self.names.get(color).map(String::as_str).unwrap_or("");
+            text.push_str(&format!(
+                "{}: {}\n",
+                String::from(*color),
+                escape_name(name)
+            ));
+        }
+        let shape = match &settings.shape {
+            BoardShape::Hexagon => format!("hexagon {}", settings.radius),
+            BoardShape::Honeycomb => format!("honeycomb {}", settings.radius),
+            BoardShape::Custom(_) => "custom".to_string(),
+            BoardShape::Map(name) => format!("map {}", name),
+        };
+        text.push_str(&format!("shape: {}\n", shape));
+        let layout = match &settings.layout {
+            StartingLayout::Corners => "corners".to_string(),
+            StartingLayout::Random(count) => format!("random {}", count),
+            StartingLayout::Custom(_) => "custom".to_string(),
+        };
+        text.push_str(&format!("layout: {}\n", layout));
+        if !settings.clock.is_off() {
+            if let Some(turn) = settings.clock.turn {
+                text.push_str(&format!("turn time: {}\n", turn));
+            }
+            if let Some(total) = settings.clock.total {
+                text.push_str(&format!("game time: {}\n", total));
+            }
+            let action = match settings.clock.on_timeout {
+                TimeoutAction::RandomMove => "random move",
+                TimeoutAction::Skip => "skip",
+                TimeoutAction::Forfeit => "forfeit",
+            };
+            text.push_str(&format!("on timeout: {}\n", action));
+        }
+        if !settings.takebacks {
+            text.push_str("takebacks: no\n");
+        }
+        if !start.teams.is_empty() {
+            let teams = start
+                .teams
+                .iter()
+                .map(|team| {
+                    team.iter()
+                        .map(|color| String::from(*color))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join(" / ");
+            text.push_str(&format!("teams: {}\n", teams));
+            if start.friendly_capture {
+                text.push_str("friendly capture: yes\n");
+            }
+        }
+        text.push_str(&format!("result: {}\n", outcome_name(&self.result)));
+        text.push_str(SEPARATOR);
+        text.push('\n');
+
+        let mut points = start.points().to_vec();
+        points.extend_from_slice(start.blocked());
+        let pieces: Vec<(Point, usize)> = start
+            .pieces()
+            .into_iter()
+            .filter_map(|(pt, color)| Some((pt, start.players.iter().position(|c| *c == color)?)))
+            .collect();
+        text.push_str(&draw(&points, start.blocked(), &pieces));
+        text.push_str(SEPARATOR);
+        text.push('\n');
+
+        let notation = Notation::new(start);
+        for (i, record) in self.history.moves.iter().enumerate() {
+            text.push_str(&format!(
+                "{}. {} {} {{{:.1}}}\n",
+                i + 1,
+                String::from(record.color),
+                notation.action(&record.action),
+                record.time as f64 / 1000.0
+            ));
+        }
+        text
+    }
+}
+
+/// `name` with backslashes and control characters escaped.
+fn escape_name(name: &str) -> String {
+    let mut escaped = String::new();
+    for c in name.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Name written by [`escape_name`], none if an escape is broken.
+fn unescape_name(text: &str) -> Option<String> {
+    let mut name = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            name.push(c);
+            continue;
+        }
+        name.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let end = rest.find('}')?;
+                let code = u32::from_str_radix(&rest[..end], 16).ok()?;
+                chars = rest[end + 1..].chars();
+                std::char::from_u32(code)?
+            }
+            _ => return None,
+        });
+    }
+    Some(name)
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    Color::iter().find(|color| String::from(*color) == name)
+}
+
+/// `<turn>. <color> <action> {<seconds>}`, the time may be left out.
+fn parse_turn(notation: &Notation, line: &str, turn: usize) -> Option<MoveRecord> {
+    let (line, time) = match line.find('{') {
+        Some(pos) => {
+            let secs = line[pos + 1..].trim_end().strip_suffix('}')?.trim();
+            let secs = secs.parse::<f64>().ok().filter(|secs| *secs >= 0.0)?;
+            (&line[..pos], (secs * 1000.0).round() as u64)
+        }
+        None => (line, 0),
+    };
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [number, color, action] if number == format!("{}.", turn) => Some(MoveRecord {
+            color: parse_color(color)?,
+            action: notation.parse_action(action)?,
+            flipped: vec![],
+            time,
+        }),
+        _ => None,
+    }
+}
+
+fn outcome_name(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::InProgress => "unfinished".to_string(),
+        Outcome::Win(color) => format!("{} wins", String::from(*color)),
+        Outcome::TeamWin(team) => format!("team {} wins", team + 1),
+        Outcome::Draw => "draw".to_string(),
+    }
+}
+
+fn parse_outcome(text: &str) -> Option<Outcome> {
+    match text.split_whitespace().collect::<Vec<_>>()[..] {
+        ["unfinished"] => Some(Outcome::InProgress),
+        ["draw"] => Some(Outcome::Draw),
+        [color, "wins"] => parse_color(color).map(Outcome::Win),
+        ["team", team, "wins"] => team
+            .parse::<usize>()
+            .ok()
+            .filter(|team| *team > 0)
+            .map(|team| Outcome::TeamWin(team - 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::builtin_map;
+
+    #[test]
+    fn cell_names_roundtrip() {
+        let board = builtin_map("Donut")
+            .unwrap()
+            .board(&[Color::Red, Color::Blue])
+            .unwrap();
+        let notation = Notation::new(&board);
+        for pt in board.points().iter().chain(board.blocked()) {
+            assert_eq!(notation.parse_cell(&notation.cell(pt)), Some(*pt));
+        }
+        let top = board.points().iter().min_by_key(|(q, r)| (*r, *q)).unwrap();
+        assert_eq!(notation.cell(top), "a3");
+        assert_eq!(notation.parse_cell("a0"), None);
+        assert_eq!(notation.parse_cell("A1"), None);
+        assert_eq!(notation.parse_cell("zzzzzzzzzz1"), None);
+        assert_eq!(notation.parse_cell("a2000000000"), None);
+        assert_eq!(notation.parse_cell("a99999999999"), None);
+    }
+
+    #[test]
+    fn record_roundtrips_through_text() {
+        let settings = GameSettings {
+            radius: 4,
+            takebacks: false,
+            ..GameSettings::default()
+        };
+        let mut board = Board::from_settings(&settings, &[Color::Red, Color::Blue]).unwrap();
+        let mut history = GameHistory::new(board.clone(), settings);
+        for i in 0..8 {
+            let mov = board.legal_moves()[i % 3].clone();
+            let color = board.turn;
+            let flipped = board.captures(&mov);
+            board.apply_move(&mov);
+            board.end_turn(color);
+            history.moves.push(MoveRecord {
+                color,
+                action: TurnAction::Move(mov),
+                flipped,
+                time: 1500 * i as u64,
+            });
+        }
+        let record = GameRecord {
+            names: vec![
+                (Color::Red, "Alice".to_string()),
+                (Color::Blue, "Bob".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            history,
+            result: Outcome::Draw,
+        };
+
+        let text = record.to_text();
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(parsed.names, record.names);
+        assert_eq!(parsed.result, Outcome::Draw);
+        assert_eq!(parsed.history.moves, record.history.moves);
+        assert_eq!(parsed.history.settings, record.history.settings);
+        assert_eq!(parsed.history.start.pieces(), record.history.start.pieces());
+        assert_eq!(parsed.to_text(), text);
+
+        let mut hostile = record;
+        let name = "Eve\n---\r\nred: \\u{41}\t\u{7}".to_string();
+        hostile.names.insert(Color::Blue, name.clone());
+        let text = hostile.to_text();
+        assert_eq!(text.lines().count(), parsed.to_text().lines().count());
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(parsed.names[&Color::Blue], name);
+        assert_eq!(
+            GameRecord::parse(&text.replace("\\u{7}", "\\q")).unwrap_err(),
+            RecordError::InvalidValue("blue".into())
+        );
+    }
+
+    #[test]
+    fn broken_records_are_rejected() {
+        let header = "red: a\nblue: b\nshape: hexagon 3\n---\n  1 . 2\n . . . .\n2 . . . 1\n . . . .\n  1 . 2\n---\n";
+        assert_eq!(
+            GameRecord::parse("red: a\n").unwrap_err(),
+            RecordError::MissingSeparator
+        );
+        assert_eq!(
+            GameRecord::parse("size: 3\n---\n").unwrap_err(),
+            RecordError::UnknownKey("size".into())
+        );
+        assert_eq!(
+            GameRecord::parse(&format!("{}1. red a2-b2\n2. red a2-b1\n", header)).unwrap_err(),
+            RecordError::IllegalTurn(2)
+        );
+        assert_eq!(
+            GameRecord::parse(&format!("{}2. red a2-b2\n", header)).unwrap_err(),
+            RecordError::InvalidTurn(11)
+        );
+        let record = GameRecord::parse(&format!("{}1. red a2-b2 {{1.5}}\n", header)).unwrap();
+        assert_eq!(record.history.moves[0].time, 1500);
+        assert_eq!(record.result, Outcome::InProgress);
+    }
+}
@@ -6,6 +6,7 @@ mod tests {
     }
 }
 
+pub mod ai;
 pub mod board;
 pub mod colors;
 pub mod models;
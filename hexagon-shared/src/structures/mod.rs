@@ -1,27 +1,226 @@
-use log::{error, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
 
-use std::collections::HashSet;
-use strum::IntoEnumIterator;
-
-use crate::board::Board;
+use crate::board::{Board, BoardMap, GameHistory, MoveRecord, Outcome};
 use crate::{board::Point, colors::colors::Color};
 
-static DEFAULT_DRAW_TIME: u32 = 90;
+/// Seconds a player gets for a turn unless the leader picks otherwise.
+pub static DEFAULT_DRAW_TIME: u32 = 90;
+/// Shortest turn or game clock the server accepts, in seconds.
+pub const MIN_CLOCK_TIME: u32 = 5;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub const MAX_PLAYERS: usize = 4;
+
+/// Colors handed to players in the order they join a lobby.
+pub const PLAYER_COLORS: [Color; MAX_PLAYERS] =
+    [Color::Red, Color::Blue, Color::Green, Color::Yellow];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum GameType {
-    TwoPlayer,
+    /// Everyone in the lobby plays, two to [`MAX_PLAYERS`] players.
+    Online,
+    VsComputer(Difficulty),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Difficulty {
+    /// Takes the move that captures the most pieces right now.
+    Easy,
+    /// Alpha-beta search two plies deep.
+    Medium,
+    /// Iterative deepening search with a transposition table.
+    Hard,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TeamMode {
     Solo,
+    /// Two teams of equal size from [`Lobby::teams`], turns alternate
+    /// between the teams.
+    Team {
+        friendly_capture: bool,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Number of teams in team games.
+pub const TEAMS: usize = 2;
+
+/// Radius of the board when the leader doesn't pick one.
+pub const DEFAULT_RADIUS: u32 = 6;
+pub const MIN_RADIUS: u32 = 3;
+pub const MAX_RADIUS: u32 = 9;
+/// Upper bound on the playable cells of a custom board or map, about a
+/// radius 12 hexagon.
+pub const MAX_CUSTOM_CELLS: usize = 450;
+/// Largest coordinate a cell of a custom board or map may have, keeps the
+/// lookup table over its bounding box small.
+pub const MAX_CUSTOM_COORD: i32 = 2 * MAX_RADIUS as i32;
+
+/// Board options picked by the lobby leader when starting a game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSettings {
+    pub radius: u32,
+    pub shape: BoardShape,
+    pub layout: StartingLayout,
+    #[serde(default)]
+    pub clock: TimeControl,
+    /// Players may ask to take back their last move.
+    #[serde(default = "takebacks_default")]
+    pub takebacks: bool,
+}
+
+fn takebacks_default() -> bool {
+    true
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            radius: DEFAULT_RADIUS,
+            shape: BoardShape::Hexagon,
+            layout: StartingLayout::Corners,
+            clock: TimeControl::default(),
+            takebacks: takebacks_default(),
+        }
+    }
+}
+
+/// Chess clock for a game, times are in seconds and `None` turns a clock off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeControl {
+    /// Time for each turn, restarts on every turn.
+    pub turn: Option<u32>,
+    /// Time for all turns of a player together.
+    pub total: Option<u32>,
+    pub on_timeout: TimeoutAction,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            turn: Some(DEFAULT_DRAW_TIME),
+            total: None,
+            on_timeout: TimeoutAction::RandomMove,
+        }
+    }
+}
+
+impl TimeControl {
+    pub fn is_off(&self) -> bool {
+        self.turn.is_none() && self.total.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        for time in self.turn.iter().chain(self.total.iter()) {
+            if *time < MIN_CLOCK_TIME {
+                return Err(SettingsError::ClockTooShort(*time));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happens to a player whose clock runs out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeoutAction {
+    /// A random legal move is played for them.
+    RandomMove,
+    /// The turn passes to the next player.
+    Skip,
+    /// Their pieces are removed from the board.
+    Forfeit,
+}
+
+/// Time left on the clocks of a player, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerClock {
+    pub color: Color,
+    pub turn: Option<u32>,
+    pub total: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BoardShape {
+    Hexagon,
+    /// Rectangle of `2 * radius - 1` rows and columns.
+    Honeycomb,
+    /// Exactly these cells, the radius is ignored.
+    Custom(Vec<Point>),
+    /// A named map, built in or loaded by the server, with its own cells,
+    /// holes and starting pieces. Radius and layout are ignored.
+    Map(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StartingLayout {
+    /// One piece in each of the six outermost corners, handed to the
+    /// players in turn.
+    Corners,
+    /// This many pieces per player on random cells.
+    Random(u32),
+    /// Pieces on these cells, by index into the players of the game.
+    Custom(Vec<(Point, usize)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SettingsError {
+    RadiusOutOfRange(u32),
+    EmptyBoard,
+    TooManyCells(usize),
+    CellOutOfRange(Point),
+    TooManyPieces,
+    UnevenStart,
+    PieceOffBoard(Point),
+    DuplicatePiece(Point),
+    UnknownPlayer(usize),
+    PlayerWithoutPieces(usize),
+    UnknownMap(String),
+    ClockTooShort(u32),
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SettingsError::RadiusOutOfRange(radius) => write!(
+                f,
+                "Radius {} is not between {} and {}",
+                radius, MIN_RADIUS, MAX_RADIUS
+            ),
+            SettingsError::EmptyBoard => write!(f, "Board has no cells"),
+            SettingsError::TooManyCells(cells) => write!(
+                f,
+                "Board has {} cells, at most {} are allowed",
+                cells, MAX_CUSTOM_CELLS
+            ),
+            SettingsError::CellOutOfRange(pt) => write!(
+                f,
+                "Cell {:?} is more than {} cells from the center",
+                pt, MAX_CUSTOM_COORD
+            ),
+            SettingsError::TooManyPieces => write!(f, "Not enough cells for the starting pieces"),
+            SettingsError::UnevenStart => {
+                write!(
+                    f,
+                    "Starting cells can't be split evenly between the players"
+                )
+            }
+            SettingsError::PieceOffBoard(pt) => write!(f, "Piece at {:?} is off the board", pt),
+            SettingsError::DuplicatePiece(pt) => write!(f, "More than one piece at {:?}", pt),
+            SettingsError::UnknownPlayer(idx) => write!(f, "No player {} in this game", idx + 1),
+            SettingsError::PlayerWithoutPieces(idx) => {
+                write!(f, "Player {} has no starting pieces", idx + 1)
+            }
+            SettingsError::UnknownMap(name) => write!(f, "No map called {}", name),
+            SettingsError::ClockTooShort(time) => write!(
+                f,
+                "Clock of {} seconds is shorter than {} seconds",
+                time, MIN_CLOCK_TIME
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Move {
     pub from: Point,
     pub to: Point,
@@ -32,6 +231,86 @@ pub struct Lobby {
     pub id: String,
     pub players: HashMap<String, Player>,
     pub state: State,
+    /// Teams picked by the leader by player id, see [`Lobby::team_of`].
+    #[serde(default)]
+    pub teams: HashMap<String, usize>,
+    /// People watching who don't play, by id.
+    #[serde(default)]
+    pub spectators: HashMap<String, Player>,
+    /// Recent chat, oldest first.
+    #[serde(default)]
+    pub chat: Vec<ChatMessage>,
+    /// Moves of the running or last game.
+    #[serde(default)]
+    pub history: Option<GameHistory>,
+    /// Listed for everyone browsing lobbies.
+    #[serde(default)]
+    pub public: bool,
+}
+
+/// What people browsing lobbies see of a public one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LobbySummary {
+    pub id: String,
+    /// Name of the leader, or of the first player once the game started.
+    pub host: String,
+    pub players: usize,
+    /// Settings of the running game, none while waiting in the room.
+    pub settings: Option<GameSettings>,
+    pub in_game: bool,
+}
+
+/// A player on the leaderboard.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub name: String,
+    pub rating: i32,
+    /// Rated games played.
+    pub games: u32,
+}
+
+/// New rating of the player of a color after a rated game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatingChange {
+    pub color: Color,
+    pub rating: i32,
+    pub delta: i32,
+}
+
+/// Longest chat message in characters.
+pub const MAX_CHAT_LENGTH: usize = 280;
+/// Chat messages a lobby keeps for people who join later.
+pub const CHAT_HISTORY: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub from: String,
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChatError {
+    Empty,
+    TooLong(usize),
+    /// Sent too many messages, can chat again after this many seconds.
+    RateLimited(u32),
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChatError::Empty => write!(f, "Message is empty"),
+            ChatError::TooLong(len) => write!(
+                f,
+                "Message has {} characters, at most {} are allowed",
+                len, MAX_CHAT_LENGTH
+            ),
+            ChatError::RateLimited(secs) => {
+                write!(f, "Too many messages, wait {} seconds", secs)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,25 +318,59 @@ pub struct Player {
     pub id: String,
     pub name: String,
     pub status: PlayerStatus,
+    /// Lost their connection, their seat is held for a while in case they
+    /// come back.
+    #[serde(default)]
+    pub away: bool,
+}
+
+/// Longest display name in characters.
+pub const MAX_NAME_LENGTH: usize = 20;
+
+impl Player {
+    /// `name` as others get to see it, without control characters or
+    /// surrounding spaces and cut to [`MAX_NAME_LENGTH`]. None if nothing
+    /// is left.
+    pub fn display_name(name: &str) -> Option<String> {
+        let name: String = name
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect::<String>()
+            .trim()
+            .chars()
+            .take(MAX_NAME_LENGTH)
+            .collect();
+        let name = name.trim_end();
+        if name.is_empty() {
+            None
+        } else {
+            Some(name.to_string())
+        }
+    }
 }
 
 impl Lobby {
-    pub fn get_available_color(&self) -> Option<Color> {
-        for color in Color::iter() {
-            let mut taken = false;
-            for p in self.players.values() {
-                if let PlayerStatus::JoinedLobby(_, c) = &p.status {
-                    if *c == color {
-                        taken = true;
-                        break;
-                    }
-                }
-            }
-            if !taken {
-                return Some(color.clone());
+    /// Team of `playerid` in team games, players the leader didn't assign
+    /// alternate between the teams in the order they joined.
+    pub fn team_of(&self, playerid: &str) -> usize {
+        if let Some(team) = self.teams.get(playerid) {
+            return *team;
+        }
+        match self.players.get(playerid).map(|p| &p.status) {
+            Some(PlayerStatus::JoinedLobby(_, color)) => {
+                PLAYER_COLORS.iter().position(|c| c == color).unwrap_or(0) % TEAMS
             }
+            _ => 0,
         }
-        None
+    }
+
+    pub fn get_available_color(&self) -> Option<Color> {
+        PLAYER_COLORS.iter().copied().find(|color| {
+            !self
+                .players
+                .values()
+                .any(|p| matches!(&p.status, PlayerStatus::JoinedLobby(_, c) if c == color))
+        })
     }
 }
 
@@ -83,15 +396,29 @@ impl State {
 pub enum PlayerStatus {
     Initiated,
     JoinedLobby(String, Color),
+    /// Watching the lobby, sees every update but can't play.
+    Spectating(String),
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub enum CloseCodes {
     WrongInit,
     CantCreateLobby,
     CantJoinLobbyDoestExist,
     NewSessionOpened,
     LobbyFull,
+    MatchCancelled,
+    /// The session token was not issued by this server.
+    InvalidSession,
+    WrongPassword,
+    UsernameTaken,
+    /// Username or password not allowed for a new account.
+    InvalidAccount,
+    /// Nothing arrived for too long at this stage of the connection.
+    TimedOut,
+    /// Too many wrong passwords for this account or from this address, try
+    /// again later.
+    TooManyAttempts,
 }
 impl std::fmt::Display for CloseCodes {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -107,32 +434,164 @@ impl CloseCodes {
             CloseCodes::CantJoinLobbyDoestExist => 4001,
             CloseCodes::NewSessionOpened => 4002,
             CloseCodes::LobbyFull => 4003,
+            CloseCodes::MatchCancelled => 4004,
+            CloseCodes::InvalidSession => 4005,
+            CloseCodes::WrongPassword => 4006,
+            CloseCodes::UsernameTaken => 4007,
+            CloseCodes::InvalidAccount => 4008,
+            CloseCodes::TimedOut => 4009,
+            CloseCodes::TooManyAttempts => 4010,
         }
     }
 }
 
+/// Why the server refused a message, sent back with
+/// [`SocketMessage::ProtocolError`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProtocolError {
+    /// Not a [`PlayerMessage`].
+    Malformed,
+    /// The first message has to be [`PlayerMessage::Initialize`].
+    NotIdentified,
+    /// Needs a lobby, join or create one first.
+    NotInLobby,
+    /// Leave the lobby with [`PlayerMessage::LeaveLobby`] first.
+    AlreadyInLobby,
+    /// Not something this player can do in the lobby, like spectators
+    /// moving.
+    NotAllowed,
+    /// Nothing arrived for too long, the connection is closed.
+    TimedOut,
+    /// Not taken at this point, like lobby messages while waiting for a
+    /// match.
+    UnexpectedMessage,
+}
+
+/// How a connection proves who it is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Credentials {
+    /// Token the server issued on an earlier connection.
+    Token(String),
+    /// Someone new without an account, they get a fresh id.
+    Guest,
+    /// Username and password of an account.
+    Login(String, String),
+    /// Creates an account with this username and password.
+    Register(String, String),
+}
+
+/// Who the server knows a connection as, the token identifies them on later
+/// connections.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub id: String,
+    pub token: String,
+    /// Username when signed into an account, none for guests.
+    pub account: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum PlayerMessage {
-    Initialize(String, String),
+    /// First message of every connection, with the name to play under.
+    /// Accounts always play under their username. Sent again outside a
+    /// lobby to switch to another identity.
+    Initialize(Credentials, String),
     JoinLobby(String),
     CreateLobby,
+    /// Waits for an opponent who wants a game with the same settings, both
+    /// get a [`SocketMessage::LobbyJoined`] once the game starts.
+    FindMatch(GameSettings),
+    /// Stops looking for a match, the connection is closed.
+    CancelMatch,
+    /// Lists the public lobbies, sent again whenever they change until the
+    /// player joins or creates one.
+    ListLobbies,
+    /// Leader lists the lobby for everyone browsing or takes it off.
+    SetPublic(bool),
+    /// Asks for the best rated players, answered with
+    /// [`SocketMessage::Leaderboard`].
+    Leaderboard,
     Ping,
     Move(Move),
+    ListMaps,
+    /// Takes back the seat held in a lobby after a dropped connection.
+    Rejoin(String),
+    /// Leaves the lobby for good, resigning a running game. The connection
+    /// stays open to join another one.
+    LeaveLobby,
+    Chat(String),
+    /// Asks the other players to take back the last move of the sender.
+    RequestUndo,
+    /// Accepts or declines the takeback asked for.
+    AnswerUndo(bool),
+    /// Leaves the game, their pieces are removed.
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    /// Asks to play again after the game is over.
+    Rematch,
 
-    StartGame(GameType, TeamMode),
+    StartGame(GameType, TeamMode, GameSettings),
+    /// Leader moves a player to a team.
+    AssignTeam(String, usize),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum SocketMessage {
+    /// Answer to [`PlayerMessage::Initialize`].
+    Identified(Identity),
+    /// Answer to [`PlayerMessage::LeaveLobby`].
+    LeftLobby,
+    ProtocolError(ProtocolError),
     LobbyJoined(Lobby, Color),
     PlayerJoined(Player, Color),
     PlayerDisconnected(Player),
+    /// A player lost their connection, their seat is held for them.
+    PlayerAway(Player),
+    /// Joined a lobby as a spectator, sent when all colors are taken or the
+    /// game already started.
+    Spectating(Lobby),
+    SpectatorJoined(Player),
+    SpectatorLeft(Player),
     Close(CloseCodes),
 
     Moved(Board, Move),
+    TurnSkipped(Color, Board),
+    /// A player is out of the game and their pieces are gone.
+    Forfeited(Color, Board),
+    /// Entry added to the move log, sent after the board update it belongs to.
+    TurnRecorded(MoveRecord),
+    /// Clocks of every player, sent every second while a clock runs.
+    Clocks(Vec<PlayerClock>),
+    /// A player asks to take back their last move, the others answer with
+    /// [`PlayerMessage::AnswerUndo`].
+    UndoRequested(Color),
+    /// The takeback was declined by this player.
+    UndoDeclined(Color),
+    /// Turns from this index of the move log on were taken back, the board is
+    /// back where it was before them.
+    Undone(Board, usize),
+    /// A player offers a draw, it lapses when another player takes a turn.
+    DrawOffered(Color),
+    /// A player wants a rematch, it starts with a new
+    /// [`SocketMessage::GameStart`] once everyone does.
+    RematchRequested(Color),
+    /// Outcome, final standings and the id to replay the game under.
+    GameOver(Outcome, Vec<(Color, u32)>, String),
+    /// Ratings of the players after a rated game, sent after the game over.
+    RatingsChanged(Vec<RatingChange>),
+    Leaderboard(Vec<Rating>),
 
     LeaderChange(State),
-    GameStart(State),
+    /// The game began with these settings.
+    GameStart(State, GameSettings),
+    SettingsRejected(SettingsError),
+    Maps(Vec<BoardMap>),
+    TeamsChanged(HashMap<String, usize>),
+    Lobbies(Vec<LobbySummary>),
+    PublicChanged(bool),
+    ChatMessage(ChatMessage),
+    ChatRejected(ChatError),
 
     Pong,
 }